@@ -0,0 +1,64 @@
+//! Three-way merge of DMX files, usable as a git merge driver
+//!
+//! Usage: `dmx-merge <base> <ours> <theirs>`, the merged file is written in
+//! place of `<ours>` and the conflicts are printed to stderr. The exit code
+//! is 1 if the merge has conflicts, which are resolved to the version of
+//! `<ours>` in the written file.
+//!
+//! To use it with git, declare the driver in the git configuration:
+//!
+//! ```text
+//! [merge "dmx"]
+//!     name = DMX three-way merge
+//!     driver = dmx-merge %O %A %B
+//! ```
+//!
+//! and assign it to the DMX files in `.gitattributes`: `*.vmap merge=dmx`
+use std::{
+    env,
+    fs::{read, write},
+    process::exit,
+};
+
+use anyhow::{Context, Result};
+
+use dmxparser::{dmx::merge, from_slice, to_vec};
+
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    if args.len() != 3 {
+        eprintln!("usage: dmx-merge <base> <ours> <theirs>");
+        exit(2);
+    }
+
+    match run(&args[0], &args[1], &args[2]) {
+        Ok(true) => {}
+        Ok(false) => exit(1),
+        Err(err) => {
+            eprintln!("error: {:#}", err);
+            exit(2);
+        }
+    }
+}
+
+/// Returns whether the merge was clean
+fn run(base: &str, ours: &str, theirs: &str) -> Result<bool> {
+    let base_data = read(base).with_context(|| format!("Could not read {}", base))?;
+    let ours_data = read(ours).with_context(|| format!("Could not read {}", ours))?;
+    let theirs_data = read(theirs).with_context(|| format!("Could not read {}", theirs))?;
+
+    let base_file = from_slice(&base_data).with_context(|| format!("Could not parse {}", base))?;
+    let ours_file = from_slice(&ours_data).with_context(|| format!("Could not parse {}", ours))?;
+    let theirs_file =
+        from_slice(&theirs_data).with_context(|| format!("Could not parse {}", theirs))?;
+
+    let result = merge(&base_file, &ours_file, &theirs_file);
+    for conflict in &result.conflicts {
+        eprint!("conflict: {}", conflict);
+    }
+
+    let data = to_vec(&result.file)?;
+    write(ours, data).with_context(|| format!("Could not write {}", ours))?;
+
+    Ok(result.is_clean())
+}
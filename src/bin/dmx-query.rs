@@ -0,0 +1,70 @@
+//! Prints the elements and attributes of a DMX file selected by a query
+//!
+//! Usage: `dmx-query <file> <query>`, see [dmxparser::dmx::Query] for the
+//! syntax of the query
+use std::{env, fs::read, process::exit};
+
+use anyhow::{Context, Result};
+
+use dmxparser::{
+    dmx::{AttributeValue, File, Match, StringRef},
+    from_slice,
+};
+
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    if args.len() != 2 {
+        eprintln!("usage: dmx-query <file> <query>");
+        exit(2);
+    }
+
+    if let Err(err) = run(&args[0], &args[1]) {
+        eprintln!("error: {:#}", err);
+        exit(1);
+    }
+}
+
+fn run(path: &str, query: &str) -> Result<()> {
+    let data = read(path).with_context(|| format!("Could not read {}", path))?;
+    let file = from_slice(&data).with_context(|| format!("Could not parse {}", path))?;
+
+    for item in file.query(query)? {
+        match item {
+            Match::Element(index) => println!("{}", element(&file, index)),
+            Match::Attribute { element: index, attribute } => {
+                let attr = &file.bodies[index].attributes[attribute];
+                println!(
+                    "{} {} = {}",
+                    element(&file, index),
+                    string(&file, attr.name),
+                    value(&file, &attr.value)
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn element(file: &File<&[u8], &str>, index: usize) -> String {
+    let header = &file.headers[index];
+    format!(
+        "#{} {} {:?}",
+        index,
+        string(file, header.type_),
+        string(file, header.name)
+    )
+}
+
+fn string<'a>(file: &'a File<&[u8], &str>, index: StringRef) -> &'a str {
+    file.string(index).unwrap_or("<invalid>")
+}
+
+fn value(file: &File<&[u8], &str>, value: &AttributeValue<&[u8], &str, StringRef>) -> String {
+    match value {
+        AttributeValue::String(index) => format!("{:?}", string(file, *index)),
+        AttributeValue::Binary(bytes) => format!("<{} bytes>", bytes.len()),
+        AttributeValue::BinaryArray(values) => format!("<{} binary values>", values.len()),
+        value => format!("{:?}", value),
+    }
+}
@@ -0,0 +1,165 @@
+//! Removal of the elements and strings that are not referenced anymore
+use std::{convert::TryInto, iter::once, mem::take, os::raw::c_int};
+
+use anyhow::{bail, Result};
+
+use super::{AttributeValue, Body, File, Header, StringRef};
+
+/// Summary of the data removed from a [File] by [File::compact]
+#[derive(Debug)]
+pub struct Compaction<S> {
+    /// Original indices of the elements that were unreachable from the root
+    pub elements: Vec<usize>,
+    /// Strings that were not referenced by any of the remaining elements
+    pub strings: Vec<S>,
+}
+
+impl<B, S> File<B, S> {
+    /// Drops all the elements that cannot be reached from the root element
+    /// (or from an element attribute in the prefix), and all the strings that
+    /// are not referenced by the remaining elements. All the element indices
+    /// and string references are renumbered accordingly, references that were
+    /// already out of range are replaced with -1.
+    ///
+    /// Fails without modifying the file if it does not have as many element
+    /// bodies as element headers.
+    pub fn compact(&mut self) -> Result<Compaction<S>> {
+        if self.headers.len() != self.bodies.len() {
+            bail!(
+                "File has {} element headers but {} element bodies",
+                self.headers.len(),
+                self.bodies.len()
+            );
+        }
+
+        let roots = self
+            .prefix
+            .iter()
+            .flat_map(|(_, value)| value.element_indices())
+            .cloned();
+
+        let reachable = reachable_elements(&self.bodies, Some(0).into_iter().chain(roots));
+
+        let mut element_map = vec![-1; reachable.len()];
+        let mut removed_elements = Vec::new();
+        let mut next_index = 0;
+        for (index, reachable) in reachable.iter().enumerate() {
+            if *reachable {
+                element_map[index] = next_index;
+                next_index += 1;
+            } else {
+                removed_elements.push(index);
+            }
+        }
+
+        let headers = take(&mut self.headers);
+        let bodies = take(&mut self.bodies);
+        for ((header, body), reachable) in headers.into_iter().zip(bodies).zip(&reachable) {
+            if *reachable {
+                self.headers.push(header);
+                self.bodies.push(body);
+            }
+        }
+
+        let prefix_indices = self
+            .prefix
+            .iter_mut()
+            .flat_map(|(_, value)| value.element_indices_mut());
+        let body_indices = self
+            .bodies
+            .iter_mut()
+            .flat_map(|body| body.attributes.iter_mut())
+            .flat_map(|attr| attr.value.element_indices_mut());
+        for index in prefix_indices.chain(body_indices) {
+            *index = remap(&element_map, *index);
+        }
+
+        let mut used = vec![false; self.strings.len()];
+        for index in self.string_refs_mut() {
+            if let Some(used) = index.index().and_then(|index| used.get_mut(index)) {
+                *used = true;
+            }
+        }
+
+        let mut string_map = vec![-1; used.len()];
+        let mut removed_strings = Vec::new();
+        for (index, string) in take(&mut self.strings).into_iter().enumerate() {
+            if used[index] {
+                string_map[index] = self.strings.len() as c_int;
+                self.strings.push(string);
+            } else {
+                removed_strings.push(string);
+            }
+        }
+
+        for index in self.string_refs_mut() {
+            index.0 = remap(&string_map, index.0);
+        }
+
+        Ok(Compaction {
+            elements: removed_elements,
+            strings: removed_strings,
+        })
+    }
+
+    /// Iterates over all the string table references in the element headers and bodies
+    pub(crate) fn string_refs_mut(&mut self) -> impl Iterator<Item = &mut StringRef> {
+        let headers = self.headers.iter_mut().flat_map(|header| {
+            let Header { type_, name, .. } = header;
+            once(type_).chain(once(name))
+        });
+
+        let attributes = self
+            .bodies
+            .iter_mut()
+            .flat_map(|body| body.attributes.iter_mut())
+            .flat_map(|attr| {
+                let value = match &mut attr.value {
+                    AttributeValue::String(value) => Some(value),
+                    _ => None,
+                };
+                once(&mut attr.name).chain(value)
+            });
+
+        headers.chain(attributes)
+    }
+}
+
+/// Marks all the elements transitively referenced from the provided roots
+pub(crate) fn reachable_elements<B, S>(
+    bodies: &[Body<B, S>],
+    roots: impl IntoIterator<Item = c_int>,
+) -> Vec<bool> {
+    let mut reachable = vec![false; bodies.len()];
+    let mut stack: Vec<c_int> = roots.into_iter().collect();
+
+    while let Some(index) = stack.pop() {
+        let index: usize = match index.try_into() {
+            Ok(index) if index < bodies.len() => index,
+            _ => continue,
+        };
+
+        if reachable[index] {
+            continue;
+        }
+
+        reachable[index] = true;
+        stack.extend(
+            bodies[index]
+                .attributes
+                .iter()
+                .flat_map(|attr| attr.value.element_indices()),
+        );
+    }
+
+    reachable
+}
+
+/// Looks up the new value of an index, null and dangling indices become -1
+pub(crate) fn remap(map: &[c_int], index: c_int) -> c_int {
+    TryInto::<usize>::try_into(index)
+        .ok()
+        .and_then(|index| map.get(index))
+        .cloned()
+        .unwrap_or(-1)
+}
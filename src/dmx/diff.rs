@@ -0,0 +1,830 @@
+//! Structural comparison of two [File]s
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::TryInto,
+    fmt::{self, Debug, Display, Formatter},
+    ops::Deref,
+    os::raw::{c_float, c_int},
+};
+
+use super::{
+    format_guid, AttributeValue, File, Qangle, Quaternion, StringRef, Vector2, Vector3, Vector4,
+    Vmatrix,
+};
+
+/// Arrays whose differing part is larger than this (in old × new items) are
+/// compared position by position instead of looking for insertions and removals
+const MAX_ARRAY_DIFF_COST: usize = 1 << 22;
+
+/// An owned attribute value reported by a [Diff], Element values are indices
+/// in the file the value was read from
+pub type Value = AttributeValue<Vec<u8>, String>;
+
+/// Differences between two files, as returned by [diff]
+#[derive(Debug, Default)]
+pub struct Diff {
+    /// Elements of the new file that have no counterpart in the old file
+    pub added: Vec<ElementInfo>,
+    /// Elements of the old file that have no counterpart in the new file
+    pub removed: Vec<ElementInfo>,
+    /// Matching elements with a different type, name or attributes
+    pub changed: Vec<ElementDiff>,
+}
+
+/// Identification of an element in one of the compared files
+#[derive(Clone, Debug)]
+pub struct ElementInfo {
+    pub index: usize,
+    pub guid: [u8; 16],
+    pub type_: String,
+    pub name: String,
+    /// Path of attribute names from the root to the element (as in
+    /// `world.children[12]`), None if the element is not reachable from the root
+    pub path: Option<String>,
+}
+
+/// Differences between two matching elements
+#[derive(Debug)]
+pub struct ElementDiff {
+    pub old: ElementInfo,
+    pub new: ElementInfo,
+    pub attributes: Vec<AttributeDiff>,
+}
+
+#[derive(Debug)]
+pub enum AttributeDiff {
+    Added {
+        name: String,
+        value: Value,
+    },
+    Removed {
+        name: String,
+        value: Value,
+    },
+    /// The attribute changed type, or is a scalar with a different value
+    Changed {
+        name: String,
+        old: Value,
+        new: Value,
+    },
+    /// The attribute is an array of the same type with different items
+    Array {
+        name: String,
+        items: Vec<ItemDiff>,
+    },
+}
+
+/// Difference between two array values, items are reported as scalar values
+/// of the corresponding type
+#[derive(Debug)]
+pub enum ItemDiff {
+    /// An item inserted at `index` in the new array
+    Added { index: usize, value: Value },
+    /// An item removed from `index` in the old array
+    Removed { index: usize, value: Value },
+    /// An item replaced at `old_index` in the old array and `new_index` in the new one
+    Changed {
+        old_index: usize,
+        new_index: usize,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl ElementDiff {
+    pub fn type_changed(&self) -> bool {
+        self.old.type_ != self.new.type_
+    }
+
+    pub fn name_changed(&self) -> bool {
+        self.old.name != self.new.name
+    }
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares two files, matching elements by GUID or by their path from the
+/// root element if their GUID is not found in the other file
+///
+/// Float values are compared by bit pattern: an unchanged NaN is equal to
+/// itself, and `0.0` is different from `-0.0`.
+pub fn diff<B1, S1, B2, S2>(old: &File<B1, S1>, new: &File<B2, S2>) -> Diff
+where
+    B1: Deref<Target = [u8]>,
+    S1: Deref<Target = str>,
+    B2: Deref<Target = [u8]>,
+    S2: Deref<Target = str>,
+{
+    let old_paths = element_paths(old);
+    let new_paths = element_paths(new);
+
+    let old_info = |index: usize| element_info(old, &old_paths, index);
+    let new_info = |index: usize| element_info(new, &new_paths, index);
+
+    let mapping = match_elements(old, &old_paths, new, &new_paths);
+
+    let mut matched = vec![false; new.headers.len()];
+    let mut diff = Diff::default();
+
+    for (old_index, new_index) in mapping.iter().enumerate() {
+        let new_index = match new_index {
+            Some(new_index) => *new_index,
+            None => {
+                diff.removed.push(old_info(old_index));
+                continue;
+            }
+        };
+
+        matched[new_index] = true;
+
+        let attributes = diff_attributes(old, old_index, new, new_index, &mapping);
+        let old_header = &old.headers[old_index];
+        let new_header = &new.headers[new_index];
+        let same_header = old.string(old_header.type_) == new.string(new_header.type_)
+            && old.string(old_header.name) == new.string(new_header.name);
+
+        if !attributes.is_empty() || !same_header {
+            diff.changed.push(ElementDiff {
+                old: old_info(old_index),
+                new: new_info(new_index),
+                attributes,
+            });
+        }
+    }
+
+    for (new_index, matched) in matched.into_iter().enumerate() {
+        if !matched {
+            diff.added.push(new_info(new_index));
+        }
+    }
+
+    diff
+}
+
+/// Computes the first path found from the root to each element, in breadth-first order
+pub(crate) fn element_paths<B, S: Deref<Target = str>>(file: &File<B, S>) -> Vec<Option<String>> {
+    let mut paths = vec![None; file.headers.len()];
+    if paths.is_empty() || file.bodies.is_empty() {
+        return paths;
+    }
+
+    paths[0] = Some(String::new());
+
+    let mut queue = VecDeque::new();
+    queue.push_back(0);
+
+    while let Some(index) = queue.pop_front() {
+        let parent = paths[index].clone().unwrap_or_default();
+        let body = match file.bodies.get(index) {
+            Some(body) => body,
+            None => continue,
+        };
+
+        for attr in &body.attributes {
+            let name = file.string(attr.name).unwrap_or("?");
+            let is_array = matches!(attr.value, AttributeValue::ElementArray(_));
+
+            for (position, child) in attr.value.element_indices().iter().enumerate() {
+                let child = match element_index(*child, paths.len()) {
+                    Some(child) => child,
+                    None => continue,
+                };
+
+                if paths[child].is_some() {
+                    continue;
+                }
+
+                let mut path = parent.clone();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(name);
+                if is_array {
+                    path.push_str(&format!("[{}]", position));
+                }
+
+                paths[child] = Some(path);
+                queue.push_back(child);
+            }
+        }
+    }
+
+    paths
+}
+
+/// Maps each element of the old file to its counterpart in the new file
+fn match_elements<B1, S1, B2, S2>(
+    old: &File<B1, S1>,
+    old_paths: &[Option<String>],
+    new: &File<B2, S2>,
+    new_paths: &[Option<String>],
+) -> Vec<Option<usize>> {
+    // Duplicated GUIDs cannot be used to identify an element
+    fn unique_guids(file_guids: impl Iterator<Item = [u8; 16]>) -> HashMap<[u8; 16], usize> {
+        let mut guids = HashMap::new();
+        let mut duplicates = HashSet::new();
+        for (index, guid) in file_guids.enumerate() {
+            if guids.insert(guid, index).is_some() {
+                duplicates.insert(guid);
+            }
+        }
+        for guid in duplicates {
+            guids.remove(&guid);
+        }
+        guids
+    }
+
+    let old_guids = unique_guids(old.headers.iter().map(|header| header.guid));
+    let new_guids = unique_guids(new.headers.iter().map(|header| header.guid));
+
+    let mut mapping = vec![None; old.headers.len()];
+    let mut matched = vec![false; new.headers.len()];
+
+    for (guid, old_index) in &old_guids {
+        if let Some(new_index) = new_guids.get(guid) {
+            mapping[*old_index] = Some(*new_index);
+            matched[*new_index] = true;
+        }
+    }
+
+    let new_by_path: HashMap<_, _> = new_paths
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !matched[*index])
+        .filter_map(|(index, path)| Some((path.as_ref()?, index)))
+        .collect();
+
+    for (old_index, path) in old_paths.iter().enumerate() {
+        if mapping[old_index].is_some() {
+            continue;
+        }
+
+        let new_index = path.as_ref().and_then(|path| new_by_path.get(path));
+        if let Some(new_index) = new_index {
+            if !matched[*new_index] {
+                mapping[old_index] = Some(*new_index);
+                matched[*new_index] = true;
+            }
+        }
+    }
+
+    mapping
+}
+
+pub(crate) fn element_info<B, S: Deref<Target = str>>(
+    file: &File<B, S>,
+    paths: &[Option<String>],
+    index: usize,
+) -> ElementInfo {
+    let header = &file.headers[index];
+    ElementInfo {
+        index,
+        guid: header.guid,
+        type_: file.string(header.type_).unwrap_or_default().to_string(),
+        name: file.string(header.name).unwrap_or_default().to_string(),
+        path: paths[index].clone(),
+    }
+}
+
+fn diff_attributes<B1, S1, B2, S2>(
+    old: &File<B1, S1>,
+    old_index: usize,
+    new: &File<B2, S2>,
+    new_index: usize,
+    mapping: &[Option<usize>],
+) -> Vec<AttributeDiff>
+where
+    B1: Deref<Target = [u8]>,
+    S1: Deref<Target = str>,
+    B2: Deref<Target = [u8]>,
+    S2: Deref<Target = str>,
+{
+    let old_attributes = old
+        .bodies
+        .get(old_index)
+        .map_or(&[][..], |body| &body.attributes);
+    let new_attributes = new
+        .bodies
+        .get(new_index)
+        .map_or(&[][..], |body| &body.attributes);
+
+    let mut new_by_name = HashMap::new();
+    for attr in new_attributes.iter().rev() {
+        new_by_name.insert(new.string(attr.name).unwrap_or_default(), attr);
+    }
+
+    let comparison = Comparison { old, new, mapping };
+
+    let mut diffs = Vec::new();
+    let mut seen = HashSet::new();
+
+    for attr in old_attributes {
+        let name = old.string(attr.name).unwrap_or_default();
+        if !seen.insert(name) {
+            continue;
+        }
+
+        let other = match new_by_name.get(name) {
+            Some(other) => other,
+            None => {
+                diffs.push(AttributeDiff::Removed {
+                    name: name.to_string(),
+                    value: owned_value(old, &attr.value),
+                });
+                continue;
+            }
+        };
+
+        if let Some(items) = comparison.array_items(&attr.value, &other.value) {
+            if !items.is_empty() {
+                diffs.push(AttributeDiff::Array {
+                    name: name.to_string(),
+                    items,
+                });
+            }
+        } else if !comparison.values_equal(&attr.value, &other.value) {
+            diffs.push(AttributeDiff::Changed {
+                name: name.to_string(),
+                old: owned_value(old, &attr.value),
+                new: owned_value(new, &other.value),
+            });
+        }
+    }
+
+    for attr in new_attributes {
+        let name = new.string(attr.name).unwrap_or_default();
+        if seen.insert(name) {
+            diffs.push(AttributeDiff::Added {
+                name: name.to_string(),
+                value: owned_value(new, &attr.value),
+            });
+        }
+    }
+
+    diffs
+}
+
+struct Comparison<'a, B1, S1, B2, S2> {
+    old: &'a File<B1, S1>,
+    new: &'a File<B2, S2>,
+    mapping: &'a [Option<usize>],
+}
+
+impl<'a, B1, S1, B2, S2> Comparison<'a, B1, S1, B2, S2>
+where
+    B1: Deref<Target = [u8]>,
+    S1: Deref<Target = str>,
+    B2: Deref<Target = [u8]>,
+    S2: Deref<Target = str>,
+{
+    fn elements_equal(&self, old: c_int, new: c_int) -> bool {
+        match element_index(old, self.mapping.len()) {
+            Some(old) => match (
+                self.mapping[old],
+                element_index(new, self.new.headers.len()),
+            ) {
+                (Some(mapped), Some(new)) => mapped == new,
+                _ => false,
+            },
+            None => old == new,
+        }
+    }
+
+    fn strings_equal(&self, old: StringRef, new: StringRef) -> bool {
+        self.old.string(old) == self.new.string(new)
+    }
+
+    fn values_equal(
+        &self,
+        old: &AttributeValue<B1, S1, StringRef>,
+        new: &AttributeValue<B2, S2, StringRef>,
+    ) -> bool {
+        match (old, new) {
+            (AttributeValue::Element(old), AttributeValue::Element(new)) => {
+                self.elements_equal(*old, *new)
+            }
+            (AttributeValue::Int(old), AttributeValue::Int(new)) => old == new,
+            (AttributeValue::Float(old), AttributeValue::Float(new)) => old.bit_eq(new),
+            (AttributeValue::Bool(old), AttributeValue::Bool(new)) => old == new,
+            (AttributeValue::String(old), AttributeValue::String(new)) => {
+                self.strings_equal(*old, *new)
+            }
+            (AttributeValue::Binary(old), AttributeValue::Binary(new)) => **old == **new,
+            (AttributeValue::Time(old), AttributeValue::Time(new)) => old == new,
+            (AttributeValue::Color(old), AttributeValue::Color(new)) => old == new,
+            (AttributeValue::Vector2(old), AttributeValue::Vector2(new)) => old.bit_eq(new),
+            (AttributeValue::Vector3(old), AttributeValue::Vector3(new)) => old.bit_eq(new),
+            (AttributeValue::Vector4(old), AttributeValue::Vector4(new)) => old.bit_eq(new),
+            (AttributeValue::Qangle(old), AttributeValue::Qangle(new)) => old.bit_eq(new),
+            (AttributeValue::Quaternion(old), AttributeValue::Quaternion(new)) => old.bit_eq(new),
+            (AttributeValue::Vmatrix(old), AttributeValue::Vmatrix(new)) => old.bit_eq(new),
+            (AttributeValue::Uint64(old), AttributeValue::Uint64(new)) => old == new,
+            (AttributeValue::Uint8(old), AttributeValue::Uint8(new)) => old == new,
+            _ => matches!(self.array_items(old, new), Some(items) if items.is_empty()),
+        }
+    }
+
+    /// Compares two arrays item by item, returns None if the values are not
+    /// arrays of the same type
+    fn array_items(
+        &self,
+        old: &AttributeValue<B1, S1, StringRef>,
+        new: &AttributeValue<B2, S2, StringRef>,
+    ) -> Option<Vec<ItemDiff>> {
+        macro_rules! items {
+            ( $old:expr, $new:expr, $variant:ident, $equal:expr ) => {
+                diff_items(
+                    $old,
+                    $new,
+                    $equal,
+                    |item| AttributeValue::$variant(*item),
+                    |item| AttributeValue::$variant(*item),
+                )
+            };
+        }
+
+        Some(match (old, new) {
+            (AttributeValue::ElementArray(old), AttributeValue::ElementArray(new)) => {
+                items!(old, new, Element, |old: &c_int, new: &c_int| self
+                    .elements_equal(*old, *new))
+            }
+            (AttributeValue::IntArray(old), AttributeValue::IntArray(new)) => {
+                items!(old, new, Int, PartialEq::eq)
+            }
+            (AttributeValue::FloatArray(old), AttributeValue::FloatArray(new)) => {
+                items!(old, new, Float, BitEq::bit_eq)
+            }
+            (AttributeValue::BoolArray(old), AttributeValue::BoolArray(new)) => {
+                items!(old, new, Bool, PartialEq::eq)
+            }
+            (AttributeValue::StringArray(old), AttributeValue::StringArray(new)) => diff_items(
+                old,
+                new,
+                |old, new| **old == **new,
+                |item| AttributeValue::String(item.to_string()),
+                |item| AttributeValue::String(item.to_string()),
+            ),
+            (AttributeValue::BinaryArray(old), AttributeValue::BinaryArray(new)) => diff_items(
+                old,
+                new,
+                |old, new| **old == **new,
+                |item| AttributeValue::Binary(item.to_vec()),
+                |item| AttributeValue::Binary(item.to_vec()),
+            ),
+            (AttributeValue::TimeArray(old), AttributeValue::TimeArray(new)) => {
+                items!(old, new, Time, PartialEq::eq)
+            }
+            (AttributeValue::ColorArray(old), AttributeValue::ColorArray(new)) => {
+                items!(old, new, Color, PartialEq::eq)
+            }
+            (AttributeValue::Vector2Array(old), AttributeValue::Vector2Array(new)) => {
+                items!(old, new, Vector2, BitEq::bit_eq)
+            }
+            (AttributeValue::Vector3Array(old), AttributeValue::Vector3Array(new)) => {
+                items!(old, new, Vector3, BitEq::bit_eq)
+            }
+            (AttributeValue::Vector4Array(old), AttributeValue::Vector4Array(new)) => {
+                items!(old, new, Vector4, BitEq::bit_eq)
+            }
+            (AttributeValue::QangleArray(old), AttributeValue::QangleArray(new)) => {
+                items!(old, new, Qangle, BitEq::bit_eq)
+            }
+            (AttributeValue::QuaternionArray(old), AttributeValue::QuaternionArray(new)) => {
+                items!(old, new, Quaternion, BitEq::bit_eq)
+            }
+            (AttributeValue::VmatrixArray(old), AttributeValue::VmatrixArray(new)) => {
+                items!(old, new, Vmatrix, BitEq::bit_eq)
+            }
+            (AttributeValue::Uint64Array(old), AttributeValue::Uint64Array(new)) => {
+                items!(old, new, Uint64, PartialEq::eq)
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Equality of floating point values by bit pattern, so that an unchanged NaN
+/// is not reported as changed
+trait BitEq {
+    fn bit_eq(&self, other: &Self) -> bool;
+}
+
+impl BitEq for c_float {
+    fn bit_eq(&self, other: &Self) -> bool {
+        self.to_bits() == other.to_bits()
+    }
+}
+
+macro_rules! impl_bit_eq {
+    ( $( $type:ident { $( $field:ident ),* } ),* $(,)? ) => {
+        $(
+            impl BitEq for $type {
+                fn bit_eq(&self, other: &Self) -> bool {
+                    $( self.$field.bit_eq(&other.$field) )&&*
+                }
+            }
+        )*
+    };
+}
+
+impl_bit_eq! {
+    Vector2 { x, y },
+    Vector3 { x, y, z },
+    Vector4 { x, y, z, w },
+    Qangle { pitch, yaw, roll },
+    Quaternion { x, y, z, w },
+}
+
+impl BitEq for Vmatrix {
+    fn bit_eq(&self, other: &Self) -> bool {
+        self.0.iter().zip(&other.0).all(|(a, b)| a.bit_eq(b))
+    }
+}
+
+/// Finds the items inserted, removed or replaced between two arrays
+fn diff_items<T, U>(
+    old: &[T],
+    new: &[U],
+    equal: impl Fn(&T, &U) -> bool,
+    old_value: impl Fn(&T) -> Value,
+    new_value: impl Fn(&U) -> Value,
+) -> Vec<ItemDiff> {
+    let edits = edit_script(old, new, &equal);
+
+    // Pair up the removals and insertions found between two kept items as replacements
+    let mut items = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+    let mut edits = edits.into_iter().peekable();
+    loop {
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        while let Some(edit) = edits.peek() {
+            match edit {
+                Edit::Keep => break,
+                Edit::Remove => {
+                    removed.push(old_index);
+                    old_index += 1;
+                }
+                Edit::Add => {
+                    added.push(new_index);
+                    new_index += 1;
+                }
+            }
+            edits.next();
+        }
+
+        for index in 0..removed.len().max(added.len()) {
+            items.push(match (removed.get(index), added.get(index)) {
+                (Some(old_index), Some(new_index)) => ItemDiff::Changed {
+                    old_index: *old_index,
+                    new_index: *new_index,
+                    old: old_value(&old[*old_index]),
+                    new: new_value(&new[*new_index]),
+                },
+                (Some(old_index), None) => ItemDiff::Removed {
+                    index: *old_index,
+                    value: old_value(&old[*old_index]),
+                },
+                (None, Some(new_index)) => ItemDiff::Added {
+                    index: *new_index,
+                    value: new_value(&new[*new_index]),
+                },
+                (None, None) => unreachable!(),
+            });
+        }
+
+        match edits.next() {
+            Some(_) => {
+                old_index += 1;
+                new_index += 1;
+            }
+            None => break,
+        }
+    }
+
+    items
+}
+
+pub(crate) enum Edit {
+    Keep,
+    Remove,
+    Add,
+}
+
+/// Computes the edits transforming `old` into `new`, keeping the longest
+/// common subsequence of items unless the arrays are too large
+pub(crate) fn edit_script<T, U>(old: &[T], new: &[U], equal: impl Fn(&T, &U) -> bool) -> Vec<Edit> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| equal(old, new))
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| equal(old, new))
+        .count();
+
+    let old_items = &old[prefix..old.len() - suffix];
+    let new_items = &new[prefix..new.len() - suffix];
+
+    let mut edits: Vec<_> = (0..prefix).map(|_| Edit::Keep).collect();
+    if old_items.len().saturating_mul(new_items.len()) <= MAX_ARRAY_DIFF_COST {
+        edits.extend(longest_common_subsequence(old_items, new_items, &equal));
+    } else {
+        for index in 0..old_items.len().max(new_items.len()) {
+            match (old_items.get(index), new_items.get(index)) {
+                (Some(old), Some(new)) if equal(old, new) => edits.push(Edit::Keep),
+                (old, new) => {
+                    if old.is_some() {
+                        edits.push(Edit::Remove);
+                    }
+                    if new.is_some() {
+                        edits.push(Edit::Add);
+                    }
+                }
+            }
+        }
+    }
+
+    edits.extend((0..suffix).map(|_| Edit::Keep));
+    edits
+}
+
+fn longest_common_subsequence<T, U>(
+    old: &[T],
+    new: &[U],
+    equal: impl Fn(&T, &U) -> bool,
+) -> Vec<Edit> {
+    let width = new.len() + 1;
+    let mut lengths = vec![0usize; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if equal(&old[i], &new[j]) {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if equal(&old[i], &new[j]) {
+            edits.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            edits.push(Edit::Remove);
+            i += 1;
+        } else {
+            edits.push(Edit::Add);
+            j += 1;
+        }
+    }
+
+    edits.extend((i..old.len()).map(|_| Edit::Remove));
+    edits.extend((j..new.len()).map(|_| Edit::Add));
+    edits
+}
+
+fn element_index(index: c_int, len: usize) -> Option<usize> {
+    let index: usize = index.try_into().ok()?;
+    if index < len {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// Copies an attribute value out of a file, resolving its string references
+pub(crate) fn owned_value<B, S>(file: &File<B, S>, value: &AttributeValue<B, S, StringRef>) -> Value
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    value.convert(
+        |buffer| buffer.to_vec(),
+        |string| string.to_string(),
+        |string| file.string(*string).unwrap_or_default().to_string(),
+    )
+}
+
+impl Display for ElementInfo {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) if path.is_empty() => write!(fmt, "<root>")?,
+            Some(path) => write!(fmt, "{}", path)?,
+            None => write!(fmt, "<unreachable #{}>", self.index)?,
+        }
+
+        write!(
+            fmt,
+            " {} {:?} {{{}}}",
+            self.type_,
+            self.name,
+            format_guid(&self.guid)
+        )
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        for element in &self.removed {
+            writeln!(fmt, "- {}", element)?;
+        }
+
+        for element in &self.added {
+            writeln!(fmt, "+ {}", element)?;
+        }
+
+        for element in &self.changed {
+            writeln!(fmt, "~ {}", element.old)?;
+            if element.type_changed() {
+                writeln!(
+                    fmt,
+                    "    type: {} -> {}",
+                    element.old.type_, element.new.type_
+                )?;
+            }
+            if element.name_changed() {
+                writeln!(
+                    fmt,
+                    "    name: {:?} -> {:?}",
+                    element.old.name, element.new.name
+                )?;
+            }
+
+            for attribute in &element.attributes {
+                match attribute {
+                    AttributeDiff::Added { name, value } => {
+                        writeln!(fmt, "  + {} = {}", name, DisplayValue(value))?
+                    }
+                    AttributeDiff::Removed { name, value } => {
+                        writeln!(fmt, "  - {} = {}", name, DisplayValue(value))?
+                    }
+                    AttributeDiff::Changed { name, old, new } => writeln!(
+                        fmt,
+                        "  ~ {}: {} -> {}",
+                        name,
+                        DisplayValue(old),
+                        DisplayValue(new)
+                    )?,
+                    AttributeDiff::Array { name, items } => {
+                        for item in items {
+                            match item {
+                                ItemDiff::Added { index, value } => writeln!(
+                                    fmt,
+                                    "  + {}[{}] = {}",
+                                    name,
+                                    index,
+                                    DisplayValue(value)
+                                )?,
+                                ItemDiff::Removed { index, value } => writeln!(
+                                    fmt,
+                                    "  - {}[{}] = {}",
+                                    name,
+                                    index,
+                                    DisplayValue(value)
+                                )?,
+                                ItemDiff::Changed {
+                                    old_index,
+                                    old,
+                                    new,
+                                    ..
+                                } => writeln!(
+                                    fmt,
+                                    "  ~ {}[{}]: {} -> {}",
+                                    name,
+                                    old_index,
+                                    DisplayValue(old),
+                                    DisplayValue(new)
+                                )?,
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats a value compactly, without dumping the content of binary blobs
+pub(crate) struct DisplayValue<'a>(pub(crate) &'a Value);
+
+impl<'a> Display for DisplayValue<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            AttributeValue::Binary(value) => write!(fmt, "<{} bytes>", value.len()),
+            AttributeValue::BinaryArray(value) => write!(fmt, "<{} binary values>", value.len()),
+            value => Debug::fmt(value, fmt),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Extraction of an element and its dependencies into a new [File]
+use std::{collections::hash_map::RandomState, iter::once, ops::Deref, os::raw::c_int};
+
+use super::{
+    compact::{reachable_elements, remap},
+    random_guid, Attribute, AttributeType, AttributeValue, Body, File, FileHeader, Header,
+    StringRef,
+};
+
+/// Name of the attribute referencing the extracted element from a synthetic root
+const EXTRACTED_ATTRIBUTE: &str = "element";
+
+impl<B, S> File<B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    /// Copies an element and all the elements it transitively references into
+    /// a new file, with a string table containing only the strings they use
+    ///
+    /// The extracted element becomes the root of the new file if it has the
+    /// same type as the root of this file. Otherwise a synthetic root is
+    /// created with the type, name and non-element attributes of the
+    /// original root, a new GUID, and an `element` attribute referencing the
+    /// extracted element (`element1`, `element2`... if the root already has
+    /// an attribute of that name), so the new file is still recognized as
+    /// the same format. Element references in the prefix attributes are kept
+    /// if they point to an extracted element and replaced with -1 otherwise.
+    ///
+    /// Returns None if `index` is not a valid element index.
+    pub fn extract(&self, index: usize) -> Option<File<Vec<u8>, String>> {
+        if index >= self.headers.len() || index >= self.bodies.len() {
+            return None;
+        }
+
+        let root_type = self.string(self.headers[0].type_);
+        let synthetic_root = index != 0 && self.string(self.headers[index].type_) != root_type;
+
+        let reachable = reachable_elements(&self.bodies, once(index as c_int));
+        let order: Vec<usize> = once(index)
+            .chain((0..reachable.len()).filter(|other| reachable[*other] && *other != index))
+            .collect();
+
+        let offset = if synthetic_root { 1 } else { 0 };
+        let mut element_map = vec![-1; self.bodies.len()];
+        for (new_index, old_index) in order.iter().enumerate() {
+            element_map[*old_index] = (new_index + offset) as c_int;
+        }
+
+        let mut strings: Vec<String> = self
+            .strings
+            .iter()
+            .map(|string| string.to_string())
+            .collect();
+        let mut headers = Vec::with_capacity(order.len() + offset);
+        let mut bodies = Vec::with_capacity(order.len() + offset);
+
+        if synthetic_root {
+            let root = &self.headers[0];
+            headers.push(Header {
+                type_: root.type_,
+                name: root.name,
+                guid: random_guid(&RandomState::new(), 0),
+            });
+
+            let mut attributes: Vec<_> = self.bodies[0]
+                .attributes
+                .iter()
+                .filter(|attr| {
+                    !matches!(
+                        attr.value.kind(),
+                        AttributeType::Element | AttributeType::ElementArray
+                    )
+                })
+                .map(|attr| Attribute {
+                    name: attr.name,
+                    value: owned_value(&attr.value),
+                })
+                .collect();
+
+            let name = self.unique_attribute_name(&attributes, EXTRACTED_ATTRIBUTE);
+            attributes.push(Attribute {
+                name: StringRef(strings.len() as c_int),
+                value: AttributeValue::Element(1),
+            });
+            strings.push(name);
+
+            bodies.push(Body { attributes });
+        }
+
+        for index in &order {
+            let header = &self.headers[*index];
+            headers.push(Header {
+                type_: header.type_,
+                name: header.name,
+                guid: header.guid,
+            });
+
+            let attributes = self.bodies[*index]
+                .attributes
+                .iter()
+                .map(|attr| {
+                    let mut value = owned_value(&attr.value);
+                    for index in value.element_indices_mut() {
+                        *index = remap(&element_map, *index);
+                    }
+                    Attribute {
+                        name: attr.name,
+                        value,
+                    }
+                })
+                .collect();
+
+            bodies.push(Body { attributes });
+        }
+
+        let prefix = self
+            .prefix
+            .iter()
+            .map(|(name, value)| {
+                let mut value = value.convert(
+                    |buffer| buffer.to_vec(),
+                    |string| string.to_string(),
+                    |string| string.to_string(),
+                );
+                for index in value.element_indices_mut() {
+                    *index = remap(&element_map, *index);
+                }
+                (name.to_string(), value)
+            })
+            .collect();
+
+        let mut file = File {
+            header: FileHeader {
+                encoding_name: self.header.encoding_name.to_string(),
+                encoding_version: self.header.encoding_version,
+                format_name: self.header.format_name.to_string(),
+                format_version: self.header.format_version,
+            },
+            prefix,
+            strings,
+            headers,
+            bodies,
+        };
+
+        // Everything is reachable from the new root, this only drops the
+        // strings used by the elements that were left out
+        file.compact().ok()?;
+        Some(file)
+    }
+
+    /// First of `base`, `base1`, `base2`... that none of the attributes is
+    /// named
+    fn unique_attribute_name<T, U>(&self, attributes: &[Attribute<T, U>], base: &str) -> String {
+        let taken = |name: &str| {
+            attributes
+                .iter()
+                .any(|attr| self.string(attr.name) == Some(name))
+        };
+
+        once(base.to_string())
+            .chain((1..).map(|suffix| format!("{}{}", base, suffix)))
+            .find(|name| !taken(name))
+            .unwrap()
+    }
+}
+
+fn owned_value<B, S>(
+    value: &AttributeValue<B, S, StringRef>,
+) -> AttributeValue<Vec<u8>, String, StringRef>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    value.convert(
+        |buffer| buffer.to_vec(),
+        |string| string.to_string(),
+        |string| *string,
+    )
+}
@@ -0,0 +1,382 @@
+//! Stable hashing of the content of elements
+use std::{convert::TryInto, ops::Deref, os::raw::c_int, slice};
+
+use super::{AttributeValue, File, StringRef};
+
+/// Options for [File::content_hash]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HashOptions {
+    /// Excludes the GUIDs of the elements from the hash, so that copies of
+    /// the same content get the same hash
+    pub ignore_guids: bool,
+}
+
+impl<B, S> File<B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    /// Computes a hash of the type, name, GUID (unless ignored) and attributes
+    /// of an element, with the referenced elements hashed recursively
+    ///
+    /// The hash only depends on the content of the element and not on its
+    /// index or the layout of the string table, and is stable across versions
+    /// of this library and platforms. A reference to an element that is
+    /// already being hashed higher in the graph is hashed as its distance to
+    /// that element, which makes cycles hash consistently. The graph is
+    /// traversed without recursion, so deep graphs don't overflow the stack.
+    ///
+    /// Returns None if `index` is not a valid element index.
+    pub fn content_hash(&self, index: usize, options: HashOptions) -> Option<u64> {
+        if index >= self.headers.len() || index >= self.bodies.len() {
+            return None;
+        }
+
+        let mut hasher = ContentHasher::new(self, options);
+        Some(hasher.element(index))
+    }
+
+    /// Computes the [content hash](File::content_hash) of every element,
+    /// sharing the work done on common subgraphs
+    pub fn content_hashes(&self, options: HashOptions) -> Vec<u64> {
+        let mut hasher = ContentHasher::new(self, options);
+        (0..self.headers.len().min(self.bodies.len()))
+            .map(|index| hasher.element(index))
+            .collect()
+    }
+}
+
+struct ContentHasher<'a, B, S> {
+    file: &'a File<B, S>,
+    options: HashOptions,
+    /// Hashes of the elements that do not depend on the path used to reach
+    /// them, ie. that do not reference an element higher in the graph
+    cache: Vec<Option<u64>>,
+    /// Elements being hashed, from the outermost one
+    stack: Vec<Frame>,
+    /// Depth in the stack of the elements being hashed
+    depths: Vec<Option<usize>>,
+}
+
+/// Element being hashed
+struct Frame {
+    index: usize,
+    hash: Fnv,
+    /// Depth of the highest element of the stack it references (or
+    /// usize::MAX)
+    highest: usize,
+    /// Index of the attribute being hashed
+    attr: usize,
+    /// Index of the next reference to hash in an Element or ElementArray
+    /// attribute, none until the attribute is started
+    item: Option<usize>,
+}
+
+impl<'a, B, S> ContentHasher<'a, B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    fn new(file: &'a File<B, S>, options: HashOptions) -> Self {
+        ContentHasher {
+            file,
+            options,
+            cache: vec![None; file.headers.len()],
+            stack: Vec::new(),
+            depths: vec![None; file.headers.len()],
+        }
+    }
+
+    /// Returns the hash of a valid element, the elements it references are
+    /// pushed on the stack instead of being hashed recursively
+    fn element(&mut self, index: usize) -> u64 {
+        if let Some(hash) = self.cache[index] {
+            return hash;
+        }
+
+        let file = self.file;
+        self.push(index);
+
+        loop {
+            let top = self.stack.len() - 1;
+            let frame = &mut self.stack[top];
+            let attributes = &file.bodies[frame.index].attributes;
+
+            let attr = match attributes.get(frame.attr) {
+                Some(attr) => attr,
+                None => {
+                    let (hash, highest) = self.pop();
+                    match self.stack.last_mut() {
+                        Some(parent) => {
+                            parent.hash.tag(b'R');
+                            parent.hash.u64(hash);
+                            parent.highest = parent.highest.min(highest);
+                            continue;
+                        }
+                        None => return hash,
+                    }
+                }
+            };
+
+            let references = match &attr.value {
+                AttributeValue::Element(value) => slice::from_ref(value),
+                AttributeValue::ElementArray(values) => &values[..],
+                value => {
+                    frame.hash.string(file.string(attr.name).unwrap_or_default());
+                    self.value(top, value);
+                    self.stack[top].attr += 1;
+                    continue;
+                }
+            };
+
+            let item = match frame.item {
+                Some(item) => item,
+                None => {
+                    frame.hash.string(file.string(attr.name).unwrap_or_default());
+                    frame.hash.string(attr.value.kind().name());
+                    if let AttributeValue::ElementArray(values) = &attr.value {
+                        frame.hash.len(values.len());
+                    }
+                    0
+                }
+            };
+
+            match references.get(item) {
+                Some(reference) => {
+                    frame.item = Some(item + 1);
+                    self.reference(*reference);
+                }
+                None => {
+                    frame.attr += 1;
+                    frame.item = None;
+                }
+            }
+        }
+    }
+
+    /// Starts hashing an element
+    fn push(&mut self, index: usize) {
+        let header = &self.file.headers[index];
+        let mut hash = Fnv::new();
+        hash.tag(b'E');
+        hash.string(self.string(header.type_));
+        hash.string(self.string(header.name));
+        if !self.options.ignore_guids {
+            hash.bytes(&header.guid);
+        }
+        hash.len(self.file.bodies[index].attributes.len());
+
+        self.depths[index] = Some(self.stack.len());
+        self.stack.push(Frame {
+            index,
+            hash,
+            highest: usize::MAX,
+            attr: 0,
+            item: None,
+        });
+    }
+
+    /// Finishes hashing the element on top of the stack, returns its hash and
+    /// the depth of the highest element of the stack it references
+    fn pop(&mut self) -> (u64, usize) {
+        let frame = self.stack.pop().unwrap();
+        self.depths[frame.index] = None;
+        let hash = frame.hash.finish();
+        if frame.highest >= self.stack.len() {
+            self.cache[frame.index] = Some(hash);
+        }
+
+        (hash, frame.highest)
+    }
+
+    /// Hashes an element reference in the element on top of the stack, the
+    /// referenced element is pushed if it has to be hashed
+    fn reference(&mut self, index: c_int) {
+        let depth = self.stack.len();
+        let frame = &mut self.stack[depth - 1];
+
+        let index: usize = match index.try_into() {
+            Ok(index) if index < self.file.headers.len() && index < self.file.bodies.len() => index,
+            _ => return frame.hash.tag(b'N'),
+        };
+
+        if let Some(cached) = self.cache[index] {
+            frame.hash.tag(b'R');
+            frame.hash.u64(cached);
+        } else if let Some(position) = self.depths[index] {
+            let frame = &mut self.stack[depth - 1];
+            frame.hash.tag(b'B');
+            frame.hash.len(depth - position);
+            frame.highest = frame.highest.min(position);
+        } else {
+            self.push(index);
+        }
+    }
+
+    /// Hashes a value other than an element reference in the element at
+    /// `depth` of the stack
+    fn value(&mut self, depth: usize, value: &AttributeValue<B, S, StringRef>) {
+        let file = self.file;
+        let string = |string: StringRef| file.string(string).unwrap_or_default();
+        let hash = &mut self.stack[depth].hash;
+        hash.string(value.kind().name());
+
+        match value {
+            // Hashed by reference, see element
+            AttributeValue::Element(_) | AttributeValue::ElementArray(_) => {}
+
+            AttributeValue::Int(value) => hash.bytes(&value.to_le_bytes()),
+            AttributeValue::Float(value) => hash.bytes(&value.to_le_bytes()),
+            AttributeValue::Bool(value) => hash.bytes(&[*value as u8]),
+            AttributeValue::String(value) => hash.string(string(*value)),
+            AttributeValue::Binary(value) => hash.buffer(value),
+            AttributeValue::Time(value) => hash.bytes(&value.millis.to_le_bytes()),
+            AttributeValue::Color(value) => {
+                hash.bytes(&[value.r as u8, value.g as u8, value.b as u8, value.a as u8])
+            }
+            AttributeValue::Vector2(value) => hash.floats(&[value.x, value.y]),
+            AttributeValue::Vector3(value) => hash.floats(&[value.x, value.y, value.z]),
+            AttributeValue::Vector4(value) => hash.floats(&[value.x, value.y, value.z, value.w]),
+            AttributeValue::Qangle(value) => hash.floats(&[value.pitch, value.yaw, value.roll]),
+            AttributeValue::Quaternion(value) => hash.floats(&[value.x, value.y, value.z, value.w]),
+            AttributeValue::Vmatrix(value) => hash.floats(&value.0),
+            AttributeValue::Uint64(value) => hash.bytes(&value.to_le_bytes()),
+            AttributeValue::Uint8(value) => hash.bytes(&[*value]),
+
+            AttributeValue::IntArray(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.bytes(&value.to_le_bytes());
+                }
+            }
+            AttributeValue::FloatArray(values) => {
+                hash.len(values.len());
+                hash.floats(values);
+            }
+            AttributeValue::BoolArray(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.bytes(&[*value as u8]);
+                }
+            }
+            AttributeValue::StringArray(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.string(value);
+                }
+            }
+            AttributeValue::BinaryArray(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.buffer(value);
+                }
+            }
+            AttributeValue::TimeArray(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.bytes(&value.millis.to_le_bytes());
+                }
+            }
+            AttributeValue::ColorArray(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.bytes(&[value.r as u8, value.g as u8, value.b as u8, value.a as u8]);
+                }
+            }
+            AttributeValue::Vector2Array(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.floats(&[value.x, value.y]);
+                }
+            }
+            AttributeValue::Vector3Array(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.floats(&[value.x, value.y, value.z]);
+                }
+            }
+            AttributeValue::Vector4Array(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.floats(&[value.x, value.y, value.z, value.w]);
+                }
+            }
+            AttributeValue::QangleArray(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.floats(&[value.pitch, value.yaw, value.roll]);
+                }
+            }
+            AttributeValue::QuaternionArray(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.floats(&[value.x, value.y, value.z, value.w]);
+                }
+            }
+            AttributeValue::VmatrixArray(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.floats(&value.0);
+                }
+            }
+            AttributeValue::Uint64Array(values) => {
+                hash.len(values.len());
+                for value in values {
+                    hash.bytes(&value.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    fn string(&self, string: StringRef) -> &'a str {
+        self.file.string(string).unwrap_or_default()
+    }
+}
+
+/// 64-bit FNV-1a, used instead of the hashers of the standard library whose
+/// output is not guaranteed to be stable
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn tag(&mut self, tag: u8) {
+        self.bytes(&[tag]);
+    }
+
+    fn len(&mut self, len: usize) {
+        self.bytes(&(len as u64).to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn floats(&mut self, values: &[f32]) {
+        for value in values {
+            self.bytes(&value.to_le_bytes());
+        }
+    }
+
+    fn buffer(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.bytes(bytes);
+    }
+
+    fn string(&mut self, string: &str) {
+        self.buffer(string.as_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
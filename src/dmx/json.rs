@@ -0,0 +1,420 @@
+//! Lossless conversion between [File]s and JSON
+//!
+//! A file is represented as an object with the following fields:
+//!
+//! ```json
+//! {
+//!     "encoding": "binary",
+//!     "encoding_version": 9,
+//!     "format": "vmap",
+//!     "format_version": 29,
+//!     "prefix": [
+//!         { "name": "asset_preview_thumbnail", "type": "Binary", "value": "89504e47" }
+//!     ],
+//!     "elements": [
+//!         {
+//!             "type": "CMapRootElement",
+//!             "name": "",
+//!             "guid": "3b5e6866-176f-43d9-8ec1-cc1485eb6370",
+//!             "attributes": [
+//!                 { "name": "isprefab", "type": "Bool", "value": false },
+//!                 { "name": "world", "type": "Element", "value": 5 }
+//!             ]
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! The first element of `elements` is the root of the file, and elements are
+//! referenced by their position in this array, so an element shared by
+//! several attributes is only stored once. Attributes are stored as arrays
+//! to keep their order, with the `type` field holding the name of their
+//! [AttributeType](super::AttributeType). Values are encoded as follows:
+//!
+//! | Type | JSON value |
+//! |------|------------|
+//! | `Element` | index in `elements`, or `null` |
+//! | `Int`, `Uint8` | number |
+//! | `Float` | number, or one of the strings `"NaN"`, `"inf"` and `"-inf"` |
+//! | `Bool` | boolean |
+//! | `String` | string, or `null` for a null string reference |
+//! | `Binary` | string of hexadecimal digits |
+//! | `Time` | number of milliseconds |
+//! | `Color` | `[r, g, b, a]` with components between 0 and 255 |
+//! | `Vector2`, `Vector3`, `Vector4` | `[x, y]`, `[x, y, z]`, `[x, y, z, w]` of floats |
+//! | `Qangle` | `[pitch, yaw, roll]` of floats |
+//! | `Quaternion` | `[x, y, z, w]` of floats |
+//! | `Vmatrix` | array of 16 floats, in the order they are stored in the file |
+//! | `Uint64` | decimal string, since most JSON parsers lose precision over 2^53 |
+//!
+//! and array types are arrays of the encoding of the corresponding scalar.
+//! Floats are written with the exact value of the 32-bit float, so a file
+//! converted to JSON and back is identical apart from the order of its
+//! string table.
+use std::{
+    convert::TryInto,
+    ops::Deref,
+    os::raw::{c_char, c_float, c_int},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{Map, Value};
+
+use super::{
+    parse_guid, Attribute, AttributeType, AttributeValue, Body, Color, File, FileHeader, Header,
+    Qangle, Quaternion, StringRef, StringTable, Time, Vector2, Vector3, Vector4, Vmatrix,
+};
+
+/// Converts a file to its JSON representation, described in the [module
+/// documentation](self), this is the same as calling `serde_json::to_value`
+/// on the file
+pub fn to_json<B, S>(file: &File<B, S>) -> Value
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    serde_json::to_value(file).expect("File serialization cannot fail")
+}
+
+/// Reads a file from its JSON representation, described in the [module
+/// documentation](self)
+pub fn from_json(value: &Value) -> Result<File<Vec<u8>, String>> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| anyhow!("expected an object"))?;
+
+    let header = FileHeader {
+        encoding_name: field(object, "encoding", Value::as_str)?.to_string(),
+        encoding_version: field(object, "encoding_version", as_c_int)?,
+        format_name: field(object, "format", Value::as_str)?.to_string(),
+        format_version: field(object, "format_version", as_c_int)?,
+    };
+
+    let prefix = field(object, "prefix", Value::as_array)?
+        .iter()
+        .enumerate()
+        .map(|(index, attr)| {
+            let (name, value) =
+                attribute_from_json(attr).with_context(|| format!("Invalid prefix[{}]", index))?;
+
+            // Prefix attributes store their strings inline
+            if let AttributeValue::String(None) = value {
+                bail!("Invalid prefix[{}]: null String value", index);
+            }
+            let value = value.convert(
+                |buffer| buffer.clone(),
+                |string| string.clone(),
+                |string| string.clone().unwrap_or_default(),
+            );
+            Ok((name, value))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut strings = StringTable::default();
+    let mut headers = Vec::new();
+    let mut bodies = Vec::new();
+
+    for (index, element) in field(object, "elements", Value::as_array)?
+        .iter()
+        .enumerate()
+    {
+        let (header, body) = element_from_json(element, &mut strings)
+            .with_context(|| format!("Invalid elements[{}]", index))?;
+        headers.push(header);
+        bodies.push(body);
+    }
+
+    Ok(File {
+        header,
+        prefix,
+        strings: strings.into_strings(),
+        headers,
+        bodies,
+    })
+}
+
+fn element_from_json(
+    element: &Value,
+    strings: &mut StringTable,
+) -> Result<(Header, Body<Vec<u8>, String>)> {
+    let object = element
+        .as_object()
+        .ok_or_else(|| anyhow!("expected an object"))?;
+
+    let guid = field(object, "guid", Value::as_str)?;
+    let header = Header {
+        type_: strings.intern(field(object, "type", Value::as_str)?),
+        name: strings.intern(field(object, "name", Value::as_str)?),
+        guid: parse_guid(guid).ok_or_else(|| anyhow!("Invalid GUID {:?}", guid))?,
+    };
+
+    let attributes = field(object, "attributes", Value::as_array)?
+        .iter()
+        .enumerate()
+        .map(|(index, attr)| {
+            let (name, value) = attribute_from_json(attr)
+                .with_context(|| format!("Invalid attributes[{}]", index))?;
+
+            Ok(Attribute {
+                name: strings.intern(&name),
+                value: value.convert(
+                    |buffer| buffer.clone(),
+                    |string| string.clone(),
+                    |string| match string {
+                        Some(string) => strings.intern(string),
+                        None => StringRef(-1),
+                    },
+                ),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok((header, Body { attributes }))
+}
+
+fn field<'a, T>(
+    object: &'a Map<String, Value>,
+    name: &str,
+    cast: impl Fn(&'a Value) -> Option<T>,
+) -> Result<T> {
+    let value = object
+        .get(name)
+        .ok_or_else(|| anyhow!("Missing field {:?}", name))?;
+    cast(value).ok_or_else(|| anyhow!("Invalid value for field {:?}: {}", name, value))
+}
+
+/// Attribute value read from JSON, whose String value is none for a null
+/// string
+type ValueFromJson = AttributeValue<Vec<u8>, String, Option<String>>;
+
+fn attribute_from_json(attr: &Value) -> Result<(String, ValueFromJson)> {
+    let object = attr
+        .as_object()
+        .ok_or_else(|| anyhow!("expected an object"))?;
+
+    let name = field(object, "name", Value::as_str)?;
+    let kind = field(object, "type", Value::as_str)?;
+    let kind = AttributeType::from_name(kind)
+        .ok_or_else(|| anyhow!("Unknown attribute type {:?}", kind))?;
+    let value = object
+        .get("value")
+        .ok_or_else(|| anyhow!("Missing field \"value\""))?;
+
+    let value = value_from_json(kind, value).with_context(|| {
+        format!(
+            "Invalid {} value for attribute {:?}: {}",
+            kind.name(),
+            name,
+            value
+        )
+    })?;
+
+    Ok((name.to_string(), value))
+}
+
+fn value_from_json(kind: AttributeType, value: &Value) -> Result<ValueFromJson> {
+    Ok(match kind {
+        AttributeType::Element => AttributeValue::Element(element_from_json_value(value)?),
+        AttributeType::Int => AttributeValue::Int(int_from_json(value)?),
+        AttributeType::Float => AttributeValue::Float(float_from_json(value)?),
+        AttributeType::Bool => AttributeValue::Bool(bool_from_json(value)?),
+        AttributeType::String if value.is_null() => AttributeValue::String(None),
+        AttributeType::String => AttributeValue::String(Some(string_from_json(value)?)),
+        AttributeType::Binary => AttributeValue::Binary(binary_from_json(value)?),
+        AttributeType::Time => AttributeValue::Time(time_from_json(value)?),
+        AttributeType::Color => AttributeValue::Color(color_from_json(value)?),
+        AttributeType::Vector2 => AttributeValue::Vector2(vector2_from_json(value)?),
+        AttributeType::Vector3 => AttributeValue::Vector3(vector3_from_json(value)?),
+        AttributeType::Vector4 => AttributeValue::Vector4(vector4_from_json(value)?),
+        AttributeType::Qangle => AttributeValue::Qangle(qangle_from_json(value)?),
+        AttributeType::Quaternion => AttributeValue::Quaternion(quaternion_from_json(value)?),
+        AttributeType::Vmatrix => AttributeValue::Vmatrix(vmatrix_from_json(value)?),
+        AttributeType::Uint64 => AttributeValue::Uint64(uint64_from_json(value)?),
+        AttributeType::Uint8 => AttributeValue::Uint8(uint8_from_json(value)?),
+
+        AttributeType::ElementArray => {
+            AttributeValue::ElementArray(array_from_json(value, element_from_json_value)?)
+        }
+        AttributeType::IntArray => AttributeValue::IntArray(array_from_json(value, int_from_json)?),
+        AttributeType::FloatArray => {
+            AttributeValue::FloatArray(array_from_json(value, float_from_json)?)
+        }
+        AttributeType::BoolArray => {
+            AttributeValue::BoolArray(array_from_json(value, bool_from_json)?)
+        }
+        AttributeType::StringArray => {
+            AttributeValue::StringArray(array_from_json(value, string_from_json)?)
+        }
+        AttributeType::BinaryArray => {
+            AttributeValue::BinaryArray(array_from_json(value, binary_from_json)?)
+        }
+        AttributeType::TimeArray => {
+            AttributeValue::TimeArray(array_from_json(value, time_from_json)?)
+        }
+        AttributeType::ColorArray => {
+            AttributeValue::ColorArray(array_from_json(value, color_from_json)?)
+        }
+        AttributeType::Vector2Array => {
+            AttributeValue::Vector2Array(array_from_json(value, vector2_from_json)?)
+        }
+        AttributeType::Vector3Array => {
+            AttributeValue::Vector3Array(array_from_json(value, vector3_from_json)?)
+        }
+        AttributeType::Vector4Array => {
+            AttributeValue::Vector4Array(array_from_json(value, vector4_from_json)?)
+        }
+        AttributeType::QangleArray => {
+            AttributeValue::QangleArray(array_from_json(value, qangle_from_json)?)
+        }
+        AttributeType::QuaternionArray => {
+            AttributeValue::QuaternionArray(array_from_json(value, quaternion_from_json)?)
+        }
+        AttributeType::VmatrixArray => {
+            AttributeValue::VmatrixArray(array_from_json(value, vmatrix_from_json)?)
+        }
+        AttributeType::Uint64Array => {
+            AttributeValue::Uint64Array(array_from_json(value, uint64_from_json)?)
+        }
+    })
+}
+
+fn array_from_json<T>(value: &Value, item: impl Fn(&Value) -> Result<T>) -> Result<Vec<T>> {
+    value
+        .as_array()
+        .ok_or_else(|| anyhow!("expected an array"))?
+        .iter()
+        .enumerate()
+        .map(|(index, value)| item(value).with_context(|| format!("Invalid item {}", index)))
+        .collect()
+}
+
+fn as_c_int(value: &Value) -> Option<c_int> {
+    value.as_i64()?.try_into().ok()
+}
+
+fn element_from_json_value(value: &Value) -> Result<c_int> {
+    if value.is_null() {
+        Ok(-1)
+    } else {
+        int_from_json(value)
+    }
+}
+
+fn int_from_json(value: &Value) -> Result<c_int> {
+    as_c_int(value).ok_or_else(|| anyhow!("expected a 32-bit integer, found {}", value))
+}
+
+fn float_from_json(value: &Value) -> Result<c_float> {
+    match value {
+        Value::Number(number) => number
+            .as_f64()
+            .map(|value| value as c_float)
+            .ok_or_else(|| anyhow!("expected a float, found {}", value)),
+        Value::String(string) if string == "NaN" => Ok(c_float::NAN),
+        Value::String(string) if string == "inf" => Ok(c_float::INFINITY),
+        Value::String(string) if string == "-inf" => Ok(c_float::NEG_INFINITY),
+        value => bail!("expected a float, found {}", value),
+    }
+}
+
+fn floats_from_json<const N: usize>(value: &Value) -> Result<[c_float; N]> {
+    let items = array_from_json(value, float_from_json)?;
+    let len = items.len();
+    items
+        .try_into()
+        .map_err(|_| anyhow!("expected an array of {} floats, found {} items", N, len))
+}
+
+fn bool_from_json(value: &Value) -> Result<bool> {
+    value
+        .as_bool()
+        .ok_or_else(|| anyhow!("expected a boolean, found {}", value))
+}
+
+fn string_from_json(value: &Value) -> Result<String> {
+    value
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| anyhow!("expected a string, found {}", value))
+}
+
+fn binary_from_json(value: &Value) -> Result<Vec<u8>> {
+    let string = value
+        .as_str()
+        .ok_or_else(|| anyhow!("expected a hexadecimal string, found {}", value))?;
+    if string.len() % 2 != 0 || !string.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        bail!("expected a hexadecimal string, found {:?}", string);
+    }
+
+    (0..string.len())
+        .step_by(2)
+        .map(|index| Ok(u8::from_str_radix(&string[index..index + 2], 16)?))
+        .collect()
+}
+
+fn time_from_json(value: &Value) -> Result<Time> {
+    Ok(Time {
+        millis: int_from_json(value)?,
+    })
+}
+
+fn color_from_json(value: &Value) -> Result<Color> {
+    let items = array_from_json(value, uint8_from_json)?;
+    match items[..] {
+        [r, g, b, a] => Ok(Color {
+            r: r as c_char,
+            g: g as c_char,
+            b: b as c_char,
+            a: a as c_char,
+        }),
+        _ => bail!(
+            "expected an array of 4 components, found {} items",
+            items.len()
+        ),
+    }
+}
+
+fn vector2_from_json(value: &Value) -> Result<Vector2> {
+    let [x, y] = floats_from_json(value)?;
+    Ok(Vector2 { x, y })
+}
+
+fn vector3_from_json(value: &Value) -> Result<Vector3> {
+    let [x, y, z] = floats_from_json(value)?;
+    Ok(Vector3 { x, y, z })
+}
+
+fn vector4_from_json(value: &Value) -> Result<Vector4> {
+    let [x, y, z, w] = floats_from_json(value)?;
+    Ok(Vector4 { x, y, z, w })
+}
+
+fn qangle_from_json(value: &Value) -> Result<Qangle> {
+    let [pitch, yaw, roll] = floats_from_json(value)?;
+    Ok(Qangle { pitch, yaw, roll })
+}
+
+fn quaternion_from_json(value: &Value) -> Result<Quaternion> {
+    let [x, y, z, w] = floats_from_json(value)?;
+    Ok(Quaternion { x, y, z, w })
+}
+
+fn vmatrix_from_json(value: &Value) -> Result<Vmatrix> {
+    Ok(Vmatrix(floats_from_json(value)?))
+}
+
+fn uint64_from_json(value: &Value) -> Result<u64> {
+    let string = value
+        .as_str()
+        .ok_or_else(|| anyhow!("expected a decimal string, found {}", value))?;
+    string
+        .parse()
+        .with_context(|| format!("expected a decimal string, found {:?}", string))
+}
+
+fn uint8_from_json(value: &Value) -> Result<u8> {
+    value
+        .as_u64()
+        .and_then(|value| value.try_into().ok())
+        .ok_or_else(|| anyhow!("expected an integer between 0 and 255, found {}", value))
+}
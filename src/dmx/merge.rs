@@ -0,0 +1,710 @@
+//! Three-way merge of [File]s sharing a common ancestor
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    fmt::{self, Display, Formatter},
+    ops::Deref,
+    os::raw::c_int,
+};
+
+use super::{
+    diff::{edit_script, element_info, element_paths, DisplayValue, Edit, Value},
+    Attribute, AttributeValue, Body, ElementInfo, File, FileHeader, Header, StringRef, StringTable,
+};
+
+/// Result of a [merge], conflicting changes are resolved to the version of
+/// `ours` in the merged file and listed in `conflicts`
+#[derive(Debug)]
+pub struct Merge {
+    pub file: File<Vec<u8>, String>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A change made on both sides of a merge that could not be reconciled
+#[derive(Debug)]
+pub struct Conflict {
+    /// Element holding the conflicting change in the merged file, None for
+    /// the format and the prefix attributes of the file
+    pub element: Option<ElementInfo>,
+    pub kind: ConflictKind,
+}
+
+/// Element values of a conflict are indices in the merged file, or -1 if the
+/// referenced element is not part of the merged file
+#[derive(Debug)]
+pub enum ConflictKind {
+    /// The format name or version of the file was changed differently on
+    /// both sides
+    Format {
+        base: (String, c_int),
+        ours: (String, c_int),
+        theirs: (String, c_int),
+    },
+    /// The type of the element was changed differently on both sides
+    Type {
+        base: Option<String>,
+        ours: String,
+        theirs: String,
+    },
+    /// The name of the element was changed differently on both sides
+    Name {
+        base: Option<String>,
+        ours: String,
+        theirs: String,
+    },
+    /// The attribute was changed differently on both sides, a value is None
+    /// if the attribute does not exist in that version
+    Attribute {
+        name: String,
+        base: Option<Value>,
+        ours: Option<Value>,
+        theirs: Option<Value>,
+    },
+    /// The element was deleted on one side and modified on the other, the
+    /// modified version is kept in the merged file
+    Deleted { side: Side },
+}
+
+/// The version of the file that made a change
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+impl Merge {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merges the changes made to `base` in `ours` and `theirs`
+///
+/// Elements are matched across the three versions by GUID, and merged
+/// attribute by attribute: a change made on a single side is applied, and a
+/// change made on both sides is a conflict unless both sides agree. Arrays
+/// changed on both sides are merged item by item as long as the changes do
+/// not overlap, elements inserted at the same position of an element array
+/// on both sides are all kept, ours first.
+///
+/// Conflicting changes never make the merge fail: the merged file holds the
+/// version of `ours` (or the modified version of an element deleted on one
+/// side), and each conflict is reported in [Merge::conflicts].
+///
+/// The merged file keeps every element of the three versions that was not
+/// deleted, even if it is not reachable from the root anymore (for instance
+/// when the references to an element were removed on one side and the
+/// element modified on the other), so that the indices reported in the
+/// conflicts match the merged file and no modified element is silently lost.
+/// Call [File::compact] on the merged file to drop the unreachable elements.
+pub fn merge<B, S>(base: &File<B, S>, ours: &File<B, S>, theirs: &File<B, S>) -> Merge
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    let mut ids = Ids::default();
+    let base = Version::new(base, &mut ids);
+    let ours = Version::new(ours, &mut ids);
+    let theirs = Version::new(theirs, &mut ids);
+
+    let mut conflicts = Vec::new();
+
+    let format_name = merge_scalar(Some(&base.format.0), &ours.format.0, &theirs.format.0);
+    let format_version = merge_scalar(Some(&base.format.1), &ours.format.1, &theirs.format.1);
+    if format_name.is_none() || format_version.is_none() {
+        conflicts.push((
+            None,
+            PendingConflict::Format {
+                base: base.format.clone(),
+                ours: ours.format.clone(),
+                theirs: theirs.format.clone(),
+            },
+        ));
+    }
+
+    let header = FileHeader {
+        encoding_name: String::from("binary"),
+        encoding_version: 9,
+        format_name: format_name.unwrap_or(&ours.format.0).clone(),
+        format_version: *format_version.unwrap_or(&ours.format.1),
+    };
+
+    let prefix = merge_attributes(
+        Some(&base.prefix),
+        &ours.prefix,
+        &theirs.prefix,
+        &mut |kind| conflicts.push((None, kind)),
+    );
+
+    // Elements are kept in the order of ours, followed by the elements only
+    // found in theirs
+    let mut order = ours.order.clone();
+    order.extend(
+        theirs
+            .order
+            .iter()
+            .filter(|id| !ours.elements.contains_key(id)),
+    );
+
+    let mut elements = Vec::new();
+    for id in order {
+        let base_element = base.elements.get(&id);
+        let ours_element = ours.elements.get(&id);
+        let theirs_element = theirs.elements.get(&id);
+
+        let element = match (base_element, ours_element, theirs_element) {
+            (base, Some(ours), Some(theirs)) => {
+                let mut conflict = |kind| conflicts.push((Some(id), kind));
+                merge_element(base, ours, theirs, &mut conflict)
+            }
+            (None, Some(element), None) | (None, None, Some(element)) => element.clone(),
+            (Some(base), Some(element), None) | (Some(base), None, Some(element)) => {
+                if element == base {
+                    continue;
+                }
+
+                let side = if ours_element.is_some() {
+                    Side::Theirs
+                } else {
+                    Side::Ours
+                };
+
+                conflicts.push((Some(id), PendingConflict::Deleted { side }));
+                element.clone()
+            }
+            (_, None, None) => continue,
+        };
+
+        elements.push((id, element));
+    }
+
+    let indices: HashMap<c_int, c_int> = elements
+        .iter()
+        .enumerate()
+        .map(|(index, (id, _))| (*id, index as c_int))
+        .collect();
+    let remap = |value: &Value| {
+        let mut value = value.clone();
+        for index in value.element_indices_mut() {
+            *index = indices.get(index).copied().unwrap_or(-1);
+        }
+        value
+    };
+
+    let mut strings = StringTable::default();
+    let mut headers = Vec::with_capacity(elements.len());
+    let mut bodies = Vec::with_capacity(elements.len());
+    for (id, element) in &elements {
+        headers.push(Header {
+            type_: strings.intern(&element.type_),
+            name: strings.intern(&element.name),
+            guid: ids.guids[*id as usize],
+        });
+
+        bodies.push(Body {
+            attributes: element
+                .attributes
+                .iter()
+                .map(|(name, value)| Attribute {
+                    name: strings.intern(name),
+                    value: remap(value).convert(
+                        |buffer| buffer.clone(),
+                        |string| string.clone(),
+                        |string| strings.intern(string),
+                    ),
+                })
+                .collect(),
+        });
+    }
+
+    let file = File {
+        header,
+        prefix: prefix
+            .iter()
+            .map(|(name, value)| (name.clone(), remap(value)))
+            .collect(),
+        strings: strings.into_strings(),
+        headers,
+        bodies,
+    };
+
+    let paths = element_paths(&file);
+    let conflicts = conflicts
+        .into_iter()
+        .map(|(id, kind)| Conflict {
+            element: id.map(|id| {
+                let index = indices[&id] as usize;
+                element_info(&file, &paths, index)
+            }),
+            kind: match kind {
+                PendingConflict::Format { base, ours, theirs } => {
+                    ConflictKind::Format { base, ours, theirs }
+                }
+                PendingConflict::Type { base, ours, theirs } => {
+                    ConflictKind::Type { base, ours, theirs }
+                }
+                PendingConflict::Name { base, ours, theirs } => {
+                    ConflictKind::Name { base, ours, theirs }
+                }
+                PendingConflict::Attribute {
+                    name,
+                    base,
+                    ours,
+                    theirs,
+                } => ConflictKind::Attribute {
+                    name,
+                    base: base.as_ref().map(remap),
+                    ours: ours.as_ref().map(remap),
+                    theirs: theirs.as_ref().map(remap),
+                },
+                PendingConflict::Deleted { side } => ConflictKind::Deleted { side },
+            },
+        })
+        .collect();
+
+    Merge { file, conflicts }
+}
+
+/// Identifiers shared by the elements of the three versions, elements with
+/// the same GUID get the same identifier
+#[derive(Default)]
+struct Ids {
+    guids: Vec<[u8; 16]>,
+    by_guid: HashMap<[u8; 16], c_int>,
+}
+
+impl Ids {
+    /// Assigns an identifier to each element of a file, elements whose GUID
+    /// is duplicated in the file cannot be matched and get a new identifier
+    fn assign(&mut self, headers: &[Header]) -> Vec<c_int> {
+        let mut seen = HashSet::new();
+        headers
+            .iter()
+            .map(|header| {
+                if seen.insert(header.guid) {
+                    if let Some(id) = self.by_guid.get(&header.guid) {
+                        return *id;
+                    }
+                }
+
+                let id = self.guids.len() as c_int;
+                self.guids.push(header.guid);
+                self.by_guid.entry(header.guid).or_insert(id);
+                id
+            })
+            .collect()
+    }
+}
+
+/// One of the versions being merged, with owned values whose element
+/// references are shared identifiers
+struct Version {
+    format: (String, c_int),
+    prefix: Vec<(String, Value)>,
+    elements: HashMap<c_int, Element>,
+    order: Vec<c_int>,
+}
+
+#[derive(Clone, PartialEq)]
+struct Element {
+    type_: String,
+    name: String,
+    attributes: Vec<(String, Value)>,
+}
+
+impl Version {
+    fn new<B, S>(file: &File<B, S>, ids: &mut Ids) -> Self
+    where
+        B: Deref<Target = [u8]>,
+        S: Deref<Target = str>,
+    {
+        let order = ids.assign(&file.headers);
+        let to_id = |value: &mut Value| {
+            for index in value.element_indices_mut() {
+                let position: Option<usize> = (*index).try_into().ok();
+                *index = position
+                    .and_then(|position| order.get(position))
+                    .copied()
+                    .unwrap_or(-1);
+            }
+        };
+
+        let string = |string: StringRef| file.string(string).unwrap_or_default();
+
+        let prefix = file
+            .prefix
+            .iter()
+            .map(|(name, value)| {
+                let mut value = value.convert(
+                    |buffer| buffer.to_vec(),
+                    |string| string.to_string(),
+                    |string| string.to_string(),
+                );
+                to_id(&mut value);
+                (name.to_string(), value)
+            })
+            .collect();
+
+        let elements = file
+            .headers
+            .iter()
+            .zip(&file.bodies)
+            .zip(&order)
+            .map(|((header, body), id)| {
+                let attributes = body
+                    .attributes
+                    .iter()
+                    .map(|attr| {
+                        let mut value = attr.value.convert(
+                            |buffer| buffer.to_vec(),
+                            |string| string.to_string(),
+                            |reference| string(*reference).to_string(),
+                        );
+                        to_id(&mut value);
+                        (string(attr.name).to_string(), value)
+                    })
+                    .collect();
+
+                let element = Element {
+                    type_: string(header.type_).to_string(),
+                    name: string(header.name).to_string(),
+                    attributes,
+                };
+
+                (*id, element)
+            })
+            .collect();
+
+        Version {
+            format: (
+                file.header.format_name.to_string(),
+                file.header.format_version,
+            ),
+            prefix,
+            elements,
+            order,
+        }
+    }
+}
+
+/// A conflict whose values still reference elements by identifier
+enum PendingConflict {
+    Format {
+        base: (String, c_int),
+        ours: (String, c_int),
+        theirs: (String, c_int),
+    },
+    Type {
+        base: Option<String>,
+        ours: String,
+        theirs: String,
+    },
+    Name {
+        base: Option<String>,
+        ours: String,
+        theirs: String,
+    },
+    Attribute {
+        name: String,
+        base: Option<Value>,
+        ours: Option<Value>,
+        theirs: Option<Value>,
+    },
+    Deleted {
+        side: Side,
+    },
+}
+
+fn merge_element(
+    base: Option<&Element>,
+    ours: &Element,
+    theirs: &Element,
+    conflict: &mut dyn FnMut(PendingConflict),
+) -> Element {
+    let base_type = base.map(|base| &base.type_);
+    let type_ = merge_scalar(base_type, &ours.type_, &theirs.type_).unwrap_or_else(|| {
+        conflict(PendingConflict::Type {
+            base: base_type.cloned(),
+            ours: ours.type_.clone(),
+            theirs: theirs.type_.clone(),
+        });
+        &ours.type_
+    });
+
+    let base_name = base.map(|base| &base.name);
+    let name = merge_scalar(base_name, &ours.name, &theirs.name).unwrap_or_else(|| {
+        conflict(PendingConflict::Name {
+            base: base_name.cloned(),
+            ours: ours.name.clone(),
+            theirs: theirs.name.clone(),
+        });
+        &ours.name
+    });
+
+    let attributes = merge_attributes(
+        base.map(|base| &base.attributes[..]),
+        &ours.attributes,
+        &theirs.attributes,
+        conflict,
+    );
+
+    Element {
+        type_: type_.clone(),
+        name: name.clone(),
+        attributes,
+    }
+}
+
+/// Returns the merged value, or None if both sides changed it differently
+fn merge_scalar<'a, T: PartialEq>(
+    base: Option<&'a T>,
+    ours: &'a T,
+    theirs: &'a T,
+) -> Option<&'a T> {
+    if ours == theirs || base == Some(theirs) {
+        Some(ours)
+    } else if base == Some(ours) {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+/// Merges attribute lists by name, keeping the order of ours followed by the
+/// attributes added in theirs
+fn merge_attributes(
+    base: Option<&[(String, Value)]>,
+    ours: &[(String, Value)],
+    theirs: &[(String, Value)],
+    conflict: &mut dyn FnMut(PendingConflict),
+) -> Vec<(String, Value)> {
+    fn find<'a>(attributes: Option<&'a [(String, Value)]>, name: &str) -> Option<&'a Value> {
+        attributes?
+            .iter()
+            .find(|(attr, _)| attr == name)
+            .map(|(_, value)| value)
+    }
+
+    let names = ours.iter().chain(
+        theirs
+            .iter()
+            .filter(|(name, _)| find(Some(ours), name).is_none()),
+    );
+
+    let mut attributes = Vec::new();
+    for (name, _) in names {
+        let base = find(base, name);
+        let ours = find(Some(ours), name);
+        let theirs = find(Some(theirs), name);
+
+        let value = match merge_value(base, ours, theirs) {
+            Some(value) => value,
+            None => {
+                conflict(PendingConflict::Attribute {
+                    name: name.clone(),
+                    base: base.cloned(),
+                    ours: ours.cloned(),
+                    theirs: theirs.cloned(),
+                });
+                ours.cloned()
+            }
+        };
+
+        if let Some(value) = value {
+            attributes.push((name.clone(), value));
+        }
+    }
+
+    attributes
+}
+
+/// Returns the merged value (None if the attribute was deleted), or None if
+/// both sides changed it differently
+fn merge_value(
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Option<Value>> {
+    if let Some(value) = merge_scalar(Some(&base), &ours, &theirs) {
+        return Some(value.cloned());
+    }
+
+    macro_rules! merge_arrays {
+        ( $( $kind:ident => $concat:expr ),* $(,)? ) => {
+            match (base, ours?, theirs?) {
+                $(
+                    (
+                        Some(AttributeValue::$kind(base)),
+                        AttributeValue::$kind(ours),
+                        AttributeValue::$kind(theirs),
+                    ) => merge_items(base, ours, theirs, $concat).map(AttributeValue::$kind),
+                )*
+                _ => None,
+            }
+        };
+    }
+
+    let value = merge_arrays! {
+        ElementArray => true,
+        IntArray => false,
+        FloatArray => false,
+        BoolArray => false,
+        StringArray => false,
+        BinaryArray => false,
+        TimeArray => false,
+        ColorArray => false,
+        Vector2Array => false,
+        Vector3Array => false,
+        Vector4Array => false,
+        QangleArray => false,
+        QuaternionArray => false,
+        VmatrixArray => false,
+        Uint64Array => false,
+    };
+
+    value.map(Some)
+}
+
+/// Merges the changes made to the items of an array, returns None if both
+/// sides changed the same part of the array differently
+///
+/// If `concat` is true, items inserted at the same position on both sides
+/// are kept, otherwise they conflict
+fn merge_items<T: Clone + PartialEq>(
+    base: &[T],
+    ours: &[T],
+    theirs: &[T],
+    concat: bool,
+) -> Option<Vec<T>> {
+    // Position of the base items kept in each side
+    fn kept(edits: Vec<Edit>, len: usize) -> Vec<Option<usize>> {
+        let mut kept = vec![None; len];
+        let (mut old, mut new) = (0, 0);
+        for edit in edits {
+            match edit {
+                Edit::Keep => {
+                    kept[old] = Some(new);
+                    old += 1;
+                    new += 1;
+                }
+                Edit::Remove => old += 1,
+                Edit::Add => new += 1,
+            }
+        }
+        kept
+    }
+
+    let ours_kept = kept(edit_script(base, ours, T::eq), base.len());
+    let theirs_kept = kept(edit_script(base, theirs, T::eq), base.len());
+
+    let mut items = Vec::new();
+    let (mut base_start, mut ours_start, mut theirs_start) = (0, 0, 0);
+    loop {
+        // Find the next base item kept on both sides
+        let stable = (base_start..base.len())
+            .find_map(|index| Some((index, ours_kept[index]?, theirs_kept[index]?)));
+        let (base_end, ours_end, theirs_end) =
+            stable.unwrap_or((base.len(), ours.len(), theirs.len()));
+
+        let base_chunk = &base[base_start..base_end];
+        let ours_chunk = &ours[ours_start..ours_end];
+        let theirs_chunk = &theirs[theirs_start..theirs_end];
+
+        if ours_chunk == base_chunk {
+            items.extend_from_slice(theirs_chunk);
+        } else if theirs_chunk == base_chunk || ours_chunk == theirs_chunk {
+            items.extend_from_slice(ours_chunk);
+        } else if concat && base_chunk.is_empty() {
+            items.extend_from_slice(ours_chunk);
+            items.extend(
+                theirs_chunk
+                    .iter()
+                    .filter(|item| !ours_chunk.contains(item))
+                    .cloned(),
+            );
+        } else {
+            return None;
+        }
+
+        if stable.is_none() {
+            break;
+        }
+
+        items.push(base[base_end].clone());
+        base_start = base_end + 1;
+        ours_start = ours_end + 1;
+        theirs_start = theirs_end + 1;
+    }
+
+    Some(items)
+}
+
+impl Display for Conflict {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match (&self.element, &self.kind) {
+            (Some(element), _) => write!(fmt, "{}", element)?,
+            (None, ConflictKind::Format { .. }) => write!(fmt, "<header>")?,
+            (None, _) => write!(fmt, "<prefix>")?,
+        }
+
+        match &self.kind {
+            ConflictKind::Format { base, ours, theirs } => {
+                writeln!(fmt, ": format changed on both sides")?;
+                writeln!(fmt, "    base:   {} {}", base.0, base.1)?;
+                writeln!(fmt, "    ours:   {} {}", ours.0, ours.1)?;
+                writeln!(fmt, "    theirs: {} {}", theirs.0, theirs.1)
+            }
+            ConflictKind::Type { base, ours, theirs } => {
+                writeln!(fmt, ": type changed on both sides")?;
+                if let Some(base) = base {
+                    writeln!(fmt, "    base:   {}", base)?;
+                }
+                writeln!(fmt, "    ours:   {}", ours)?;
+                writeln!(fmt, "    theirs: {}", theirs)
+            }
+            ConflictKind::Name { base, ours, theirs } => {
+                writeln!(fmt, ": name changed on both sides")?;
+                if let Some(base) = base {
+                    writeln!(fmt, "    base:   {:?}", base)?;
+                }
+                writeln!(fmt, "    ours:   {:?}", ours)?;
+                writeln!(fmt, "    theirs: {:?}", theirs)
+            }
+            ConflictKind::Attribute {
+                name,
+                base,
+                ours,
+                theirs,
+            } => {
+                writeln!(fmt, ": attribute {:?} changed on both sides", name)?;
+                for (side, value) in [("base:  ", base), ("ours:  ", ours), ("theirs:", theirs)] {
+                    match value {
+                        Some(value) => writeln!(fmt, "    {} {}", side, DisplayValue(value))?,
+                        None => writeln!(fmt, "    {} <missing>", side)?,
+                    }
+                }
+                Ok(())
+            }
+            ConflictKind::Deleted { side } => {
+                let modified = match side {
+                    Side::Ours => Side::Theirs,
+                    Side::Theirs => Side::Ours,
+                };
+                writeln!(
+                    fmt,
+                    ": deleted in {} but modified in {}, keeping the modified element",
+                    side, modified
+                )
+            }
+        }
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            Side::Ours => write!(fmt, "ours"),
+            Side::Theirs => write!(fmt, "theirs"),
+        }
+    }
+}
@@ -0,0 +1,1268 @@
+//! Data structures used to represent a DMX file in memory
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::{HashMap, RandomState},
+    convert::TryInto,
+    fmt::{self, Debug, Display, Formatter},
+    hash::{BuildHasher, Hasher},
+    io::Write,
+    ops::Deref,
+    os::raw::{c_char, c_float, c_int},
+    slice,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    read::{Readable, Reader, ReaderString},
+    write::Writable,
+};
+
+mod compact;
+mod diff;
+mod extract;
+mod hash;
+pub mod json;
+mod merge;
+mod query;
+mod restore;
+mod serialize;
+mod validate;
+
+pub use self::{
+    compact::Compaction,
+    diff::{diff, AttributeDiff, Diff, ElementDiff, ElementInfo, ItemDiff},
+    hash::HashOptions,
+    merge::{merge, Conflict, ConflictKind, Merge, Side},
+    query::{Match, Query},
+    validate::{Diagnostic, DiagnosticKind},
+};
+
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum AttributeType {
+    Element,
+    Int,
+    Float,
+    Bool,
+    String,
+    Binary,
+    Time,
+    Color,
+    Vector2,
+    Vector3,
+    Vector4,
+    Qangle,
+    Quaternion,
+    Vmatrix,
+    Uint64,
+    Uint8,
+
+    ElementArray,
+    IntArray,
+    FloatArray,
+    BoolArray,
+    StringArray,
+    BinaryArray,
+    TimeArray,
+    ColorArray,
+    Vector2Array,
+    Vector3Array,
+    Vector4Array,
+    QangleArray,
+    QuaternionArray,
+    VmatrixArray,
+    Uint64Array,
+}
+
+impl AttributeType {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            AttributeType::Element => "Element",
+            AttributeType::Int => "Int",
+            AttributeType::Float => "Float",
+            AttributeType::Bool => "Bool",
+            AttributeType::String => "String",
+            AttributeType::Binary => "Binary",
+            AttributeType::Time => "Time",
+            AttributeType::Color => "Color",
+            AttributeType::Vector2 => "Vector2",
+            AttributeType::Vector3 => "Vector3",
+            AttributeType::Vector4 => "Vector4",
+            AttributeType::Qangle => "Qangle",
+            AttributeType::Quaternion => "Quaternion",
+            AttributeType::Vmatrix => "Vmatrix",
+            AttributeType::Uint64 => "Uint64",
+            AttributeType::Uint8 => "Uint8",
+
+            AttributeType::ElementArray => "ElementArray",
+            AttributeType::IntArray => "IntArray",
+            AttributeType::FloatArray => "FloatArray",
+            AttributeType::BoolArray => "BoolArray",
+            AttributeType::StringArray => "StringArray",
+            AttributeType::BinaryArray => "BinaryArray",
+            AttributeType::TimeArray => "TimeArray",
+            AttributeType::ColorArray => "ColorArray",
+            AttributeType::Vector2Array => "Vector2Array",
+            AttributeType::Vector3Array => "Vector3Array",
+            AttributeType::Vector4Array => "Vector4Array",
+            AttributeType::QangleArray => "QangleArray",
+            AttributeType::QuaternionArray => "QuaternionArray",
+            AttributeType::VmatrixArray => "VmatrixArray",
+            AttributeType::Uint64Array => "Uint64Array",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Element" => AttributeType::Element,
+            "Int" => AttributeType::Int,
+            "Float" => AttributeType::Float,
+            "Bool" => AttributeType::Bool,
+            "String" => AttributeType::String,
+            "Binary" => AttributeType::Binary,
+            "Time" => AttributeType::Time,
+            "Color" => AttributeType::Color,
+            "Vector2" => AttributeType::Vector2,
+            "Vector3" => AttributeType::Vector3,
+            "Vector4" => AttributeType::Vector4,
+            "Qangle" => AttributeType::Qangle,
+            "Quaternion" => AttributeType::Quaternion,
+            "Vmatrix" => AttributeType::Vmatrix,
+            "Uint64" => AttributeType::Uint64,
+            "Uint8" => AttributeType::Uint8,
+
+            "ElementArray" => AttributeType::ElementArray,
+            "IntArray" => AttributeType::IntArray,
+            "FloatArray" => AttributeType::FloatArray,
+            "BoolArray" => AttributeType::BoolArray,
+            "StringArray" => AttributeType::StringArray,
+            "BinaryArray" => AttributeType::BinaryArray,
+            "TimeArray" => AttributeType::TimeArray,
+            "ColorArray" => AttributeType::ColorArray,
+            "Vector2Array" => AttributeType::Vector2Array,
+            "Vector3Array" => AttributeType::Vector3Array,
+            "Vector4Array" => AttributeType::Vector4Array,
+            "QangleArray" => AttributeType::QangleArray,
+            "QuaternionArray" => AttributeType::QuaternionArray,
+            "VmatrixArray" => AttributeType::VmatrixArray,
+            "Uint64Array" => AttributeType::Uint64Array,
+            _ => return None,
+        })
+    }
+}
+
+impl<R: Reader> Readable<R> for AttributeType {
+    fn read(reader: &mut R) -> Result<Self> {
+        Ok(match u8::read(reader)? {
+            1 => AttributeType::Element,
+            2 => AttributeType::Int,
+            3 => AttributeType::Float,
+            4 => AttributeType::Bool,
+            5 => AttributeType::String,
+            6 => AttributeType::Binary,
+            7 => AttributeType::Time,
+            8 => AttributeType::Color,
+            9 => AttributeType::Vector2,
+            10 => AttributeType::Vector3,
+            11 => AttributeType::Vector4,
+            12 => AttributeType::Qangle,
+            13 => AttributeType::Quaternion,
+            14 => AttributeType::Vmatrix,
+            15 => AttributeType::Uint64,
+            16 => AttributeType::Uint8,
+
+            33 => AttributeType::ElementArray,
+            34 => AttributeType::IntArray,
+            35 => AttributeType::FloatArray,
+            36 => AttributeType::BoolArray,
+            37 => AttributeType::StringArray,
+            38 => AttributeType::BinaryArray,
+            39 => AttributeType::TimeArray,
+            40 => AttributeType::ColorArray,
+            41 => AttributeType::Vector2Array,
+            42 => AttributeType::Vector3Array,
+            43 => AttributeType::Vector4Array,
+            44 => AttributeType::QangleArray,
+            45 => AttributeType::QuaternionArray,
+            46 => AttributeType::VmatrixArray,
+            47 => AttributeType::Uint64Array,
+
+            value => bail!("unimplemented AttributeType {}", value),
+        })
+    }
+}
+
+impl Writable for AttributeType {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        let id: u8 = match self {
+            AttributeType::Element => 1,
+            AttributeType::Int => 2,
+            AttributeType::Float => 3,
+            AttributeType::Bool => 4,
+            AttributeType::String => 5,
+            AttributeType::Binary => 6,
+            AttributeType::Time => 7,
+            AttributeType::Color => 8,
+            AttributeType::Vector2 => 9,
+            AttributeType::Vector3 => 10,
+            AttributeType::Vector4 => 11,
+            AttributeType::Qangle => 12,
+            AttributeType::Quaternion => 13,
+            AttributeType::Vmatrix => 14,
+            AttributeType::Uint64 => 15,
+            AttributeType::Uint8 => 16,
+
+            AttributeType::ElementArray => 33,
+            AttributeType::IntArray => 34,
+            AttributeType::FloatArray => 35,
+            AttributeType::BoolArray => 36,
+            AttributeType::StringArray => 37,
+            AttributeType::BinaryArray => 38,
+            AttributeType::TimeArray => 39,
+            AttributeType::ColorArray => 40,
+            AttributeType::Vector2Array => 41,
+            AttributeType::Vector3Array => 42,
+            AttributeType::Vector4Array => 43,
+            AttributeType::QangleArray => 44,
+            AttributeType::QuaternionArray => 45,
+            AttributeType::VmatrixArray => 46,
+            AttributeType::Uint64Array => 47,
+        };
+
+        id.write(writer)
+    }
+}
+
+#[derive(Debug)]
+pub struct File<B, S> {
+    pub header: FileHeader<S>,
+    pub prefix: Vec<(S, AttributeValue<B, S>)>,
+    pub strings: Vec<S>,
+    pub headers: Vec<Header>,
+    pub bodies: Vec<Body<B, S>>,
+}
+
+impl<B, S: Deref<Target = str>> File<B, S> {
+    /// Resolves a reference into the string table, returns None for null or
+    /// out of range references
+    pub fn string(&self, string: StringRef) -> Option<&str> {
+        let value = self.strings.get(string.index()?)?;
+        Some(value)
+    }
+}
+
+/// Builds the string table of a new file, storing each distinct string once
+#[derive(Debug, Default)]
+pub(crate) struct StringTable {
+    strings: Vec<String>,
+    indices: HashMap<String, StringRef>,
+}
+
+impl StringTable {
+    /// Starts from an existing string table, keeping its indices
+    pub(crate) fn from_strings(strings: Vec<String>) -> Self {
+        let mut indices = HashMap::with_capacity(strings.len());
+        for (index, string) in strings.iter().enumerate() {
+            indices
+                .entry(string.clone())
+                .or_insert(StringRef(index as c_int));
+        }
+
+        StringTable { strings, indices }
+    }
+
+    pub(crate) fn intern(&mut self, string: &str) -> StringRef {
+        if let Some(index) = self.indices.get(string) {
+            return *index;
+        }
+
+        let index = StringRef(self.strings.len() as c_int);
+        self.strings.push(string.to_string());
+        self.indices.insert(string.to_string(), index);
+        index
+    }
+
+    pub(crate) fn into_strings(self) -> Vec<String> {
+        self.strings
+    }
+}
+
+impl<R> Readable<R> for File<R::Buffer, R::String>
+where
+    R: Reader,
+    R::String: Debug,
+{
+    fn read(reader: &mut R) -> Result<Self> {
+        let mut file = File::read_head(reader)?;
+
+        file.bodies = (0..file.headers.len())
+            .map(|_| file.read_body(reader))
+            .collect::<Result<_>>()?;
+
+        Ok(file)
+    }
+}
+
+impl<B, S: Debug> File<B, S> {
+    /// Reads everything up to the bodies of the elements, which are left
+    /// empty to be read with [File::read_body]
+    pub(crate) fn read_head<R>(reader: &mut R) -> Result<Self>
+    where
+        R: Reader<Buffer = B, String = S>,
+        S: Readable<R> + Deref<Target = str>,
+        FileHeader<S>: Readable<R>,
+        AttributeValue<B, S>: Readable<R>,
+    {
+        let header = FileHeader::read(reader)?;
+
+        assert_eq!(&*header.encoding_name, "binary");
+        assert_eq!(header.encoding_version, 9);
+
+        let _padding = c_int::read(reader)?;
+
+        let n_prefix = c_int::read(reader)?;
+        let prefix = (0..n_prefix)
+            .map(|_| {
+                let name = S::read(reader)?;
+
+                let value = AttributeValue::read(reader)
+                    .with_context(|| format!("Failed to read attribute {:?}", name))?;
+
+                Ok((name, value))
+            })
+            .collect::<Result<_>>()?;
+
+        let n_strings = c_int::read(reader)?;
+        let strings: Vec<_> = (0..n_strings)
+            .map(|_| S::read(reader))
+            .collect::<Result<_>>()?;
+
+        let n_elements = c_int::read(reader)?;
+        let headers: Vec<_> = (0..n_elements)
+            .map(|_| Header::read(reader))
+            .collect::<Result<_>>()?;
+
+        Ok(File {
+            header,
+            prefix,
+            strings,
+            headers,
+            bodies: Vec::new(),
+        })
+    }
+
+    /// Reads the body of the next element, the bodies follow the headers in
+    /// the same order
+    pub(crate) fn read_body<R>(&self, reader: &mut R) -> Result<Body<B, S>>
+    where
+        R: Reader<Buffer = B, String = S>,
+        Body<B, S>: Readable<R>,
+    {
+        Body::read(reader).map_err(|mut err| {
+            if let Some(err) = err.downcast_mut::<AttributeError>() {
+                err.1 = Some(format!("{:?}", self.strings[(err.0).0 as usize]));
+            }
+            err
+        })
+    }
+}
+
+impl<B, S> Writable for File<B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        if self.headers.len() != self.bodies.len() {
+            bail!(
+                "File has {} element headers but {} element bodies",
+                self.headers.len(),
+                self.bodies.len()
+            );
+        }
+
+        self.header.write(writer)?;
+
+        // Number of prefix elements, always a single one in the files
+        // written by the Source 2 tools
+        let n_prefix_elements: c_int = 1;
+        n_prefix_elements.write(writer)?;
+
+        (self.prefix.len() as c_int).write(writer)?;
+        for (name, value) in &self.prefix {
+            name.write(writer)?;
+            value.write(writer)?;
+        }
+
+        (self.strings.len() as c_int).write(writer)?;
+        for string in &self.strings {
+            string.write(writer)?;
+        }
+
+        (self.headers.len() as c_int).write(writer)?;
+        for header in &self.headers {
+            header.write(writer)?;
+        }
+
+        for body in &self.bodies {
+            body.write(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct FileHeader<S> {
+    pub encoding_name: S,
+    pub encoding_version: c_int,
+    pub format_name: S,
+    pub format_version: c_int,
+}
+
+impl<R: Reader> Readable<R> for FileHeader<R::String>
+where
+    R::String: Debug,
+{
+    fn read(reader: &mut R) -> Result<Self> {
+        static OPEN_TOKEN: &'static str = "<!-- dmx encoding ";
+        static FORMAT_TOKEN: &'static str = " format ";
+        static CLOSE_TOKEN: &'static str = " -->\n";
+
+        fn trim_start<'a, S: ReaderString>(value: &mut S, head: &str) {
+            assert!(value.starts_with(head));
+            value.split(head.len());
+        }
+
+        fn split_at<'a, S: ReaderString + Debug>(value: &mut S, sep: char) -> Result<S> {
+            let index = value
+                .find(sep)
+                .ok_or_else(|| anyhow!("could not find separator {:?} in {:?}", sep, value))?;
+            Ok(value.split(index))
+        }
+
+        let mut value = R::String::read(reader)?;
+
+        trim_start(&mut value, OPEN_TOKEN);
+
+        let encoding_name = split_at(&mut value, ' ')?;
+        trim_start(&mut value, " ");
+
+        let encoding_version = split_at(&mut value, ' ')?;
+        let encoding_version = encoding_version.parse()?;
+
+        trim_start(&mut value, FORMAT_TOKEN);
+
+        let format_name = split_at(&mut value, ' ')?;
+        trim_start(&mut value, " ");
+
+        let format_version = split_at(&mut value, ' ')?;
+        let format_version = format_version.parse()?;
+
+        trim_start(&mut value, CLOSE_TOKEN);
+
+        Ok(FileHeader {
+            encoding_name,
+            encoding_version,
+            format_name,
+            format_version,
+        })
+    }
+}
+
+impl<S: Deref<Target = str>> Writable for FileHeader<S> {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        let value = format!(
+            "<!-- dmx encoding {} {} format {} {} -->\n",
+            &*self.encoding_name, self.encoding_version, &*self.format_name, self.format_version,
+        );
+
+        value.write(writer)
+    }
+}
+
+#[derive(Debug)]
+pub struct Header {
+    pub type_: StringRef,
+    pub name: StringRef,
+    pub guid: [u8; 16],
+}
+
+impl<R: Reader> Readable<R> for Header {
+    fn read(reader: &mut R) -> Result<Self> {
+        let type_ = StringRef::read(reader)?;
+        let name = StringRef::read(reader)?;
+
+        let mut guid = [0; 16];
+        reader.read_into(&mut guid)?;
+
+        Ok(Header { type_, name, guid })
+    }
+}
+
+impl Writable for Header {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.type_.write(writer)?;
+        self.name.write(writer)?;
+        writer.write_all(&self.guid)?;
+        Ok(())
+    }
+}
+
+/// Formats an element GUID in the registry format used by the text encodings
+/// of DMX, with the first three groups stored in little-endian order
+pub(crate) fn format_guid(guid: &[u8; 16]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+        guid[8],
+        guid[9],
+        guid[10],
+        guid[11],
+        guid[12],
+        guid[13],
+        guid[14],
+        guid[15],
+    )
+}
+
+/// Parses an element GUID formatted by [format_guid]
+pub(crate) fn parse_guid(value: &str) -> Option<[u8; 16]> {
+    let groups: Vec<_> = value.split('-').collect();
+    if groups.len() != 5
+        || [8, 4, 4, 4, 12]
+            .iter()
+            .zip(&groups)
+            .any(|(len, group)| group.len() != *len || !group.bytes().all(|c| c.is_ascii_hexdigit()))
+    {
+        return None;
+    }
+
+    let mut guid = [0; 16];
+    guid[0..4].copy_from_slice(&u32::from_str_radix(groups[0], 16).ok()?.to_le_bytes());
+    guid[4..6].copy_from_slice(&u16::from_str_radix(groups[1], 16).ok()?.to_le_bytes());
+    guid[6..8].copy_from_slice(&u16::from_str_radix(groups[2], 16).ok()?.to_le_bytes());
+    let tail = format!("{}{}", groups[3], groups[4]);
+    for (index, byte) in guid[8..].iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&tail[index * 2..index * 2 + 2], 16).ok()?;
+    }
+
+    Some(guid)
+}
+
+/// Generates a random (version 4) GUID, which differs for each `index`
+/// generated from the same `random` state
+pub(crate) fn random_guid(random: &RandomState, index: usize) -> [u8; 16] {
+    let mut guid = [0; 16];
+    for (half, bytes) in guid.chunks_mut(8).enumerate() {
+        let mut hasher = random.build_hasher();
+        hasher.write_usize(index);
+        hasher.write_usize(half);
+        bytes.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+
+    guid[7] = (guid[7] & 0x0f) | 0x40;
+    guid[8] = (guid[8] & 0x3f) | 0x80;
+    guid
+}
+
+#[derive(Debug)]
+pub struct Body<B, S> {
+    pub attributes: Vec<Attribute<B, S>>,
+}
+
+impl<R: Reader> Readable<R> for Body<R::Buffer, R::String> {
+    fn read(reader: &mut R) -> Result<Self> {
+        let n_attributes = c_int::read(reader)?;
+
+        Ok(Body {
+            attributes: (0..n_attributes)
+                .map(|_| Attribute::read(reader))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl<B, S> Writable for Body<B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        (self.attributes.len() as c_int).write(writer)?;
+        for attribute in &self.attributes {
+            attribute.write(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Attribute<B, S> {
+    pub name: StringRef,
+    pub value: AttributeValue<B, S, StringRef>,
+}
+
+impl<R: Reader> Readable<R> for Attribute<R::Buffer, R::String> {
+    fn read(reader: &mut R) -> Result<Self> {
+        let name = StringRef::read(reader)?;
+        let value = AttributeValue::read(reader).with_context(|| AttributeError(name, None))?;
+        Ok(Attribute { name, value })
+    }
+}
+
+impl<B, S> Writable for Attribute<B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.name.write(writer)?;
+        self.value.write(writer)
+    }
+}
+
+#[derive(Debug)]
+struct AttributeError(StringRef, Option<String>);
+
+impl Display for AttributeError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        if let Some(name) = &self.1 {
+            write!(fmt, "Failed to read attribute {}", name)
+        } else {
+            write!(fmt, "Failed to read attribute {:?}", self.0)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct StringRef(pub c_int);
+
+impl StringRef {
+    pub fn index(&self) -> Option<usize> {
+        self.0.try_into().ok()
+    }
+}
+
+impl<R: Reader> Readable<R> for StringRef {
+    fn read(reader: &mut R) -> Result<Self> {
+        Ok(StringRef(c_int::read(reader)?))
+    }
+}
+
+impl Writable for StringRef {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.0.write(writer)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Time {
+    pub millis: c_int,
+}
+
+impl<R: Reader> Readable<R> for Time {
+    fn read(reader: &mut R) -> Result<Self> {
+        Ok(Time {
+            millis: c_int::read(reader)?,
+        })
+    }
+}
+
+impl Writable for Time {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.millis.write(writer)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: c_char,
+    pub g: c_char,
+    pub b: c_char,
+    pub a: c_char,
+}
+
+impl<R: Reader> Readable<R> for Color {
+    fn read(reader: &mut R) -> Result<Self> {
+        let r = c_char::read(reader)?;
+        let g = c_char::read(reader)?;
+        let b = c_char::read(reader)?;
+        let a = c_char::read(reader)?;
+        Ok(Color { r, g, b, a })
+    }
+}
+
+impl Writable for Color {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.r.write(writer)?;
+        self.g.write(writer)?;
+        self.b.write(writer)?;
+        self.a.write(writer)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vector2 {
+    pub x: c_float,
+    pub y: c_float,
+}
+
+impl<R: Reader> Readable<R> for Vector2 {
+    fn read(reader: &mut R) -> Result<Self> {
+        let x = c_float::read(reader)?;
+        let y = c_float::read(reader)?;
+        Ok(Vector2 { x, y })
+    }
+}
+
+impl Writable for Vector2 {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.x.write(writer)?;
+        self.y.write(writer)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vector3 {
+    pub x: c_float,
+    pub y: c_float,
+    pub z: c_float,
+}
+
+impl<R: Reader> Readable<R> for Vector3 {
+    fn read(reader: &mut R) -> Result<Self> {
+        let x = c_float::read(reader)?;
+        let y = c_float::read(reader)?;
+        let z = c_float::read(reader)?;
+        Ok(Vector3 { x, y, z })
+    }
+}
+
+impl Writable for Vector3 {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.x.write(writer)?;
+        self.y.write(writer)?;
+        self.z.write(writer)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vector4 {
+    pub x: c_float,
+    pub y: c_float,
+    pub z: c_float,
+    pub w: c_float,
+}
+
+impl<R: Reader> Readable<R> for Vector4 {
+    fn read(reader: &mut R) -> Result<Self> {
+        let x = c_float::read(reader)?;
+        let y = c_float::read(reader)?;
+        let z = c_float::read(reader)?;
+        let w = c_float::read(reader)?;
+        Ok(Vector4 { x, y, z, w })
+    }
+}
+
+impl Writable for Vector4 {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.x.write(writer)?;
+        self.y.write(writer)?;
+        self.z.write(writer)?;
+        self.w.write(writer)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Qangle {
+    pub pitch: c_float,
+    pub yaw: c_float,
+    pub roll: c_float,
+}
+
+impl<R: Reader> Readable<R> for Qangle {
+    fn read(reader: &mut R) -> Result<Self> {
+        let pitch = c_float::read(reader)?;
+        let yaw = c_float::read(reader)?;
+        let roll = c_float::read(reader)?;
+        Ok(Qangle { pitch, yaw, roll })
+    }
+}
+
+impl Writable for Qangle {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.pitch.write(writer)?;
+        self.yaw.write(writer)?;
+        self.roll.write(writer)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Quaternion {
+    pub x: c_float,
+    pub y: c_float,
+    pub z: c_float,
+    pub w: c_float,
+}
+
+impl<R: Reader> Readable<R> for Quaternion {
+    fn read(reader: &mut R) -> Result<Self> {
+        let x = c_float::read(reader)?;
+        let y = c_float::read(reader)?;
+        let z = c_float::read(reader)?;
+        let w = c_float::read(reader)?;
+        Ok(Quaternion { x, y, z, w })
+    }
+}
+
+impl Writable for Quaternion {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.x.write(writer)?;
+        self.y.write(writer)?;
+        self.z.write(writer)?;
+        self.w.write(writer)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vmatrix(pub [c_float; 16]);
+
+impl<R: Reader> Readable<R> for Vmatrix {
+    fn read(reader: &mut R) -> Result<Self> {
+        let mut matrix = [0.0; 16];
+        for item in matrix.iter_mut() {
+            *item = c_float::read(reader)?;
+        }
+
+        Ok(Vmatrix(matrix))
+    }
+}
+
+impl Writable for Vmatrix {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        for item in &self.0 {
+            item.write(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeValue<B, S, R = S> {
+    Element(c_int),
+    Int(c_int),
+    Float(c_float),
+    Bool(bool),
+    String(R),
+    Binary(B),
+    Time(Time),
+    Color(Color),
+    Vector2(Vector2),
+    Vector3(Vector3),
+    Vector4(Vector4),
+    Qangle(Qangle),
+    Quaternion(Quaternion),
+    Vmatrix(Vmatrix),
+    Uint64(u64),
+    Uint8(u8),
+
+    ElementArray(Vec<c_int>),
+    IntArray(Vec<c_int>),
+    FloatArray(Vec<c_float>),
+    BoolArray(Vec<bool>),
+    StringArray(Vec<S>),
+    BinaryArray(Vec<B>),
+    TimeArray(Vec<Time>),
+    ColorArray(Vec<Color>),
+    Vector2Array(Vec<Vector2>),
+    Vector3Array(Vec<Vector3>),
+    Vector4Array(Vec<Vector4>),
+    QangleArray(Vec<Qangle>),
+    QuaternionArray(Vec<Quaternion>),
+    VmatrixArray(Vec<Vmatrix>),
+    Uint64Array(Vec<u64>),
+}
+
+impl<B, S, R> AttributeValue<B, S, R> {
+    pub fn kind(&self) -> AttributeType {
+        match self {
+            AttributeValue::Element(_) => AttributeType::Element,
+            AttributeValue::Int(_) => AttributeType::Int,
+            AttributeValue::Float(_) => AttributeType::Float,
+            AttributeValue::Bool(_) => AttributeType::Bool,
+            AttributeValue::String(_) => AttributeType::String,
+            AttributeValue::Binary(_) => AttributeType::Binary,
+            AttributeValue::Time(_) => AttributeType::Time,
+            AttributeValue::Color(_) => AttributeType::Color,
+            AttributeValue::Vector2(_) => AttributeType::Vector2,
+            AttributeValue::Vector3(_) => AttributeType::Vector3,
+            AttributeValue::Vector4(_) => AttributeType::Vector4,
+            AttributeValue::Qangle(_) => AttributeType::Qangle,
+            AttributeValue::Quaternion(_) => AttributeType::Quaternion,
+            AttributeValue::Vmatrix(_) => AttributeType::Vmatrix,
+            AttributeValue::Uint64(_) => AttributeType::Uint64,
+            AttributeValue::Uint8(_) => AttributeType::Uint8,
+
+            AttributeValue::ElementArray(_) => AttributeType::ElementArray,
+            AttributeValue::IntArray(_) => AttributeType::IntArray,
+            AttributeValue::FloatArray(_) => AttributeType::FloatArray,
+            AttributeValue::BoolArray(_) => AttributeType::BoolArray,
+            AttributeValue::StringArray(_) => AttributeType::StringArray,
+            AttributeValue::BinaryArray(_) => AttributeType::BinaryArray,
+            AttributeValue::TimeArray(_) => AttributeType::TimeArray,
+            AttributeValue::ColorArray(_) => AttributeType::ColorArray,
+            AttributeValue::Vector2Array(_) => AttributeType::Vector2Array,
+            AttributeValue::Vector3Array(_) => AttributeType::Vector3Array,
+            AttributeValue::Vector4Array(_) => AttributeType::Vector4Array,
+            AttributeValue::QangleArray(_) => AttributeType::QangleArray,
+            AttributeValue::QuaternionArray(_) => AttributeType::QuaternionArray,
+            AttributeValue::VmatrixArray(_) => AttributeType::VmatrixArray,
+            AttributeValue::Uint64Array(_) => AttributeType::Uint64Array,
+        }
+    }
+
+    /// Indices of the elements referenced by this value, empty if the value
+    /// is neither an Element nor an ElementArray
+    pub fn element_indices(&self) -> &[c_int] {
+        match self {
+            AttributeValue::Element(index) => slice::from_ref(index),
+            AttributeValue::ElementArray(indices) => indices,
+            _ => &[],
+        }
+    }
+
+    /// Empty array of the given type, None if `kind` is not an array type
+    pub(crate) fn empty_array(kind: AttributeType) -> Option<Self> {
+        Some(match kind {
+            AttributeType::ElementArray => AttributeValue::ElementArray(Vec::new()),
+            AttributeType::IntArray => AttributeValue::IntArray(Vec::new()),
+            AttributeType::FloatArray => AttributeValue::FloatArray(Vec::new()),
+            AttributeType::BoolArray => AttributeValue::BoolArray(Vec::new()),
+            AttributeType::StringArray => AttributeValue::StringArray(Vec::new()),
+            AttributeType::BinaryArray => AttributeValue::BinaryArray(Vec::new()),
+            AttributeType::TimeArray => AttributeValue::TimeArray(Vec::new()),
+            AttributeType::ColorArray => AttributeValue::ColorArray(Vec::new()),
+            AttributeType::Vector2Array => AttributeValue::Vector2Array(Vec::new()),
+            AttributeType::Vector3Array => AttributeValue::Vector3Array(Vec::new()),
+            AttributeType::Vector4Array => AttributeValue::Vector4Array(Vec::new()),
+            AttributeType::QangleArray => AttributeValue::QangleArray(Vec::new()),
+            AttributeType::QuaternionArray => AttributeValue::QuaternionArray(Vec::new()),
+            AttributeType::VmatrixArray => AttributeValue::VmatrixArray(Vec::new()),
+            AttributeType::Uint64Array => AttributeValue::Uint64Array(Vec::new()),
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn element_indices_mut(&mut self) -> &mut [c_int] {
+        match self {
+            AttributeValue::Element(index) => slice::from_mut(index),
+            AttributeValue::ElementArray(indices) => indices,
+            _ => &mut [],
+        }
+    }
+
+    /// Copies this value, converting its buffers, inline strings and string
+    /// references with the provided functions
+    pub(crate) fn convert<B2, S2, R2>(
+        &self,
+        mut buffer: impl FnMut(&B) -> B2,
+        mut string: impl FnMut(&S) -> S2,
+        mut reference: impl FnMut(&R) -> R2,
+    ) -> AttributeValue<B2, S2, R2> {
+        match self {
+            AttributeValue::Element(value) => AttributeValue::Element(*value),
+            AttributeValue::Int(value) => AttributeValue::Int(*value),
+            AttributeValue::Float(value) => AttributeValue::Float(*value),
+            AttributeValue::Bool(value) => AttributeValue::Bool(*value),
+            AttributeValue::String(value) => AttributeValue::String(reference(value)),
+            AttributeValue::Binary(value) => AttributeValue::Binary(buffer(value)),
+            AttributeValue::Time(value) => AttributeValue::Time(*value),
+            AttributeValue::Color(value) => AttributeValue::Color(*value),
+            AttributeValue::Vector2(value) => AttributeValue::Vector2(*value),
+            AttributeValue::Vector3(value) => AttributeValue::Vector3(*value),
+            AttributeValue::Vector4(value) => AttributeValue::Vector4(*value),
+            AttributeValue::Qangle(value) => AttributeValue::Qangle(*value),
+            AttributeValue::Quaternion(value) => AttributeValue::Quaternion(*value),
+            AttributeValue::Vmatrix(value) => AttributeValue::Vmatrix(*value),
+            AttributeValue::Uint64(value) => AttributeValue::Uint64(*value),
+            AttributeValue::Uint8(value) => AttributeValue::Uint8(*value),
+
+            AttributeValue::ElementArray(value) => AttributeValue::ElementArray(value.clone()),
+            AttributeValue::IntArray(value) => AttributeValue::IntArray(value.clone()),
+            AttributeValue::FloatArray(value) => AttributeValue::FloatArray(value.clone()),
+            AttributeValue::BoolArray(value) => AttributeValue::BoolArray(value.clone()),
+            AttributeValue::StringArray(value) => {
+                AttributeValue::StringArray(value.iter().map(&mut string).collect())
+            }
+            AttributeValue::BinaryArray(value) => {
+                AttributeValue::BinaryArray(value.iter().map(&mut buffer).collect())
+            }
+            AttributeValue::TimeArray(value) => AttributeValue::TimeArray(value.clone()),
+            AttributeValue::ColorArray(value) => AttributeValue::ColorArray(value.clone()),
+            AttributeValue::Vector2Array(value) => AttributeValue::Vector2Array(value.clone()),
+            AttributeValue::Vector3Array(value) => AttributeValue::Vector3Array(value.clone()),
+            AttributeValue::Vector4Array(value) => AttributeValue::Vector4Array(value.clone()),
+            AttributeValue::QangleArray(value) => AttributeValue::QangleArray(value.clone()),
+            AttributeValue::QuaternionArray(value) => {
+                AttributeValue::QuaternionArray(value.clone())
+            }
+            AttributeValue::VmatrixArray(value) => AttributeValue::VmatrixArray(value.clone()),
+            AttributeValue::Uint64Array(value) => AttributeValue::Uint64Array(value.clone()),
+        }
+    }
+
+    /// Converts the string reference of a String value, moving everything else
+    pub(crate) fn map_reference<R2>(
+        self,
+        reference: impl FnOnce(R) -> R2,
+    ) -> AttributeValue<B, S, R2> {
+        match self {
+            AttributeValue::Element(value) => AttributeValue::Element(value),
+            AttributeValue::Int(value) => AttributeValue::Int(value),
+            AttributeValue::Float(value) => AttributeValue::Float(value),
+            AttributeValue::Bool(value) => AttributeValue::Bool(value),
+            AttributeValue::String(value) => AttributeValue::String(reference(value)),
+            AttributeValue::Binary(value) => AttributeValue::Binary(value),
+            AttributeValue::Time(value) => AttributeValue::Time(value),
+            AttributeValue::Color(value) => AttributeValue::Color(value),
+            AttributeValue::Vector2(value) => AttributeValue::Vector2(value),
+            AttributeValue::Vector3(value) => AttributeValue::Vector3(value),
+            AttributeValue::Vector4(value) => AttributeValue::Vector4(value),
+            AttributeValue::Qangle(value) => AttributeValue::Qangle(value),
+            AttributeValue::Quaternion(value) => AttributeValue::Quaternion(value),
+            AttributeValue::Vmatrix(value) => AttributeValue::Vmatrix(value),
+            AttributeValue::Uint64(value) => AttributeValue::Uint64(value),
+            AttributeValue::Uint8(value) => AttributeValue::Uint8(value),
+
+            AttributeValue::ElementArray(value) => AttributeValue::ElementArray(value),
+            AttributeValue::IntArray(value) => AttributeValue::IntArray(value),
+            AttributeValue::FloatArray(value) => AttributeValue::FloatArray(value),
+            AttributeValue::BoolArray(value) => AttributeValue::BoolArray(value),
+            AttributeValue::StringArray(value) => AttributeValue::StringArray(value),
+            AttributeValue::BinaryArray(value) => AttributeValue::BinaryArray(value),
+            AttributeValue::TimeArray(value) => AttributeValue::TimeArray(value),
+            AttributeValue::ColorArray(value) => AttributeValue::ColorArray(value),
+            AttributeValue::Vector2Array(value) => AttributeValue::Vector2Array(value),
+            AttributeValue::Vector3Array(value) => AttributeValue::Vector3Array(value),
+            AttributeValue::Vector4Array(value) => AttributeValue::Vector4Array(value),
+            AttributeValue::QangleArray(value) => AttributeValue::QangleArray(value),
+            AttributeValue::QuaternionArray(value) => AttributeValue::QuaternionArray(value),
+            AttributeValue::VmatrixArray(value) => AttributeValue::VmatrixArray(value),
+            AttributeValue::Uint64Array(value) => AttributeValue::Uint64Array(value),
+        }
+    }
+}
+
+impl<R: Reader, S: Readable<R>> Readable<R> for AttributeValue<R::Buffer, R::String, S> {
+    fn read(reader: &mut R) -> Result<Self> {
+        Ok(match AttributeType::read(reader)? {
+            AttributeType::Element => AttributeValue::Element(c_int::read(reader)?),
+            AttributeType::Int => AttributeValue::Int(c_int::read(reader)?),
+            AttributeType::Float => AttributeValue::Float(c_float::read(reader)?),
+            AttributeType::Bool => AttributeValue::Bool(u8::read(reader)? != 0),
+            AttributeType::String => AttributeValue::String(S::read(reader)?),
+            AttributeType::Binary => {
+                let size = c_int::read(reader)?;
+                AttributeValue::Binary(reader.read_bytes(size as usize)?)
+            }
+            AttributeType::Time => AttributeValue::Time(Time::read(reader)?),
+            AttributeType::Color => AttributeValue::Color(Color::read(reader)?),
+            AttributeType::Vector2 => AttributeValue::Vector2(Vector2::read(reader)?),
+            AttributeType::Vector3 => AttributeValue::Vector3(Vector3::read(reader)?),
+            AttributeType::Vector4 => AttributeValue::Vector4(Vector4::read(reader)?),
+            AttributeType::Qangle => AttributeValue::Qangle(Qangle::read(reader)?),
+            AttributeType::Quaternion => AttributeValue::Quaternion(Quaternion::read(reader)?),
+            AttributeType::Vmatrix => AttributeValue::Vmatrix(Vmatrix::read(reader)?),
+            AttributeType::Uint64 => AttributeValue::Uint64(u64::read(reader)?),
+            AttributeType::Uint8 => AttributeValue::Uint8(u8::read(reader)?),
+
+            AttributeType::ElementArray => AttributeValue::ElementArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| c_int::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::IntArray => AttributeValue::IntArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| c_int::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::FloatArray => AttributeValue::FloatArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| c_float::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::BoolArray => AttributeValue::BoolArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| Ok(u8::read(reader)? != 0))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::StringArray => AttributeValue::StringArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| R::String::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::BinaryArray => AttributeValue::BinaryArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| {
+                        let size = c_int::read(reader)?;
+                        reader.read_bytes(size as usize)
+                    })
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::TimeArray => AttributeValue::TimeArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| Time::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::ColorArray => AttributeValue::ColorArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| Color::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::Vector2Array => AttributeValue::Vector2Array({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| Vector2::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::Vector3Array => AttributeValue::Vector3Array({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| Vector3::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::Vector4Array => AttributeValue::Vector4Array({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| Vector4::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::QangleArray => AttributeValue::QangleArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| Qangle::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::QuaternionArray => AttributeValue::QuaternionArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| Quaternion::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::VmatrixArray => AttributeValue::VmatrixArray({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| Vmatrix::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+            AttributeType::Uint64Array => AttributeValue::Uint64Array({
+                let size = c_int::read(reader)?;
+                (0..size)
+                    .map(|_| u64::read(reader))
+                    .collect::<Result<_>>()?
+            }),
+        })
+    }
+}
+
+/// String values are written inline in the prefix attributes, and as string
+/// table references in the element bodies
+trait AttributeString {
+    fn write_string(&self, writer: &mut dyn Write) -> Result<()>;
+}
+
+impl AttributeString for StringRef {
+    fn write_string(&self, writer: &mut dyn Write) -> Result<()> {
+        self.write(writer)
+    }
+}
+
+impl<S: Deref<Target = str>> AttributeString for S {
+    fn write_string(&self, writer: &mut dyn Write) -> Result<()> {
+        (**self).write(writer)
+    }
+}
+
+impl<B, S, R> Writable for AttributeValue<B, S, R>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+    R: AttributeString,
+{
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        self.kind().write(writer)?;
+
+        match self {
+            AttributeValue::Element(value) => value.write(writer),
+            AttributeValue::Int(value) => value.write(writer),
+            AttributeValue::Float(value) => value.write(writer),
+            AttributeValue::Bool(value) => (*value as u8).write(writer),
+            AttributeValue::String(value) => value.write_string(writer),
+            AttributeValue::Binary(value) => value.write(writer),
+            AttributeValue::Time(value) => value.write(writer),
+            AttributeValue::Color(value) => value.write(writer),
+            AttributeValue::Vector2(value) => value.write(writer),
+            AttributeValue::Vector3(value) => value.write(writer),
+            AttributeValue::Vector4(value) => value.write(writer),
+            AttributeValue::Qangle(value) => value.write(writer),
+            AttributeValue::Quaternion(value) => value.write(writer),
+            AttributeValue::Vmatrix(value) => value.write(writer),
+            AttributeValue::Uint64(value) => value.write(writer),
+            AttributeValue::Uint8(value) => value.write(writer),
+
+            AttributeValue::ElementArray(values) => values.write(writer),
+            AttributeValue::IntArray(values) => values.write(writer),
+            AttributeValue::FloatArray(values) => values.write(writer),
+            AttributeValue::BoolArray(values) => {
+                (values.len() as c_int).write(writer)?;
+                for value in values {
+                    (*value as u8).write(writer)?;
+                }
+                Ok(())
+            }
+            AttributeValue::StringArray(values) => {
+                (values.len() as c_int).write(writer)?;
+                for value in values {
+                    value.write(writer)?;
+                }
+                Ok(())
+            }
+            AttributeValue::BinaryArray(values) => {
+                (values.len() as c_int).write(writer)?;
+                for value in values {
+                    value.write(writer)?;
+                }
+                Ok(())
+            }
+            AttributeValue::TimeArray(values) => values.write(writer),
+            AttributeValue::ColorArray(values) => values.write(writer),
+            AttributeValue::Vector2Array(values) => values.write(writer),
+            AttributeValue::Vector3Array(values) => values.write(writer),
+            AttributeValue::Vector4Array(values) => values.write(writer),
+            AttributeValue::QangleArray(values) => values.write(writer),
+            AttributeValue::QuaternionArray(values) => values.write(writer),
+            AttributeValue::VmatrixArray(values) => values.write(writer),
+            AttributeValue::Uint64Array(values) => values.write(writer),
+        }
+    }
+}
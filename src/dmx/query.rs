@@ -0,0 +1,516 @@
+//! Path queries selecting elements and attributes in a [File]
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    iter::Peekable,
+    ops::Deref,
+    str::{CharIndices, FromStr},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use super::{AttributeValue, File, StringRef};
+
+/// A node selected by a [Query]
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum Match {
+    /// An element, by index
+    Element(usize),
+    /// A non-element attribute, by index of its element and position in the element body
+    Attribute { element: usize, attribute: usize },
+}
+
+/// A parsed path query
+///
+/// A query is a list of steps separated by `/` (direct children) or `//`
+/// (any descendant, excluding the nodes selected by the previous step unless
+/// they are referenced by one of their descendants), for instance `world/children//CMapEntity[entity_properties/classname='light_omni']/origin`.
+///
+/// The children of an element are its attributes: attributes holding an
+/// Element or an ElementArray select the referenced elements directly, other
+/// attributes select the attribute itself. A step is either:
+/// - a name, matching attributes with this name, elements with this type, or
+///   elements referenced by an attribute with this name
+/// - `*`, matching any child
+/// - `.`, selecting the current node
+///
+/// Each step can be filtered by any number of predicates in brackets. A
+/// predicate is a relative path, optionally ending with `@type` or `@name` to
+/// test the header of the selected elements, and optionally followed by `=` or
+/// `!=` and a literal (a quoted string, a number, or a bare word). Without a
+/// comparison the predicate only checks that the path selects something.
+#[derive(Clone, Debug)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    descendants: bool,
+    test: Test,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Clone, Debug)]
+enum Test {
+    Current,
+    Any,
+    Name(String),
+}
+
+#[derive(Clone, Debug)]
+struct Predicate {
+    steps: Vec<Step>,
+    header: Option<HeaderField>,
+    comparison: Option<(bool, String)>,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum HeaderField {
+    Type,
+    Name,
+}
+
+/// A node along with the attribute it was reached from
+#[derive(Copy, Clone)]
+struct Node {
+    target: Match,
+    via: Option<StringRef>,
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(query: &str) -> Result<Self> {
+        Query::parse(query)
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self> {
+        let mut parser = Parser {
+            query,
+            chars: query.char_indices().peekable(),
+        };
+
+        let steps = parser.parse_steps(true)?;
+        if let Some((index, _)) = parser.chars.peek() {
+            bail!("Unexpected {:?} in query {:?}", &query[*index..], query);
+        }
+
+        Ok(Query { steps })
+    }
+
+    /// Evaluates the query starting from the root element, returns the
+    /// selected nodes without duplicates in the order they were first found
+    pub fn eval<B, S: Deref<Target = str>>(&self, file: &File<B, S>) -> Vec<Match> {
+        if file.bodies.is_empty() {
+            return Vec::new();
+        }
+
+        let root = Node {
+            target: Match::Element(0),
+            via: None,
+        };
+
+        eval_steps(file, &self.steps, vec![root])
+            .into_iter()
+            .map(|node| node.target)
+            .collect()
+    }
+}
+
+impl<B, S: Deref<Target = str>> File<B, S> {
+    /// Parses and evaluates a [Query] on this file
+    pub fn query(&self, query: &str) -> Result<Vec<Match>> {
+        Ok(Query::parse(query)?.eval(self))
+    }
+}
+
+fn eval_steps<B, S: Deref<Target = str>>(
+    file: &File<B, S>,
+    steps: &[Step],
+    mut nodes: Vec<Node>,
+) -> Vec<Node> {
+    for step in steps {
+        let candidates = if step.descendants {
+            descendants(file, &nodes)
+        } else if let Test::Current = step.test {
+            nodes
+        } else {
+            nodes.iter().flat_map(|node| children(file, *node)).collect()
+        };
+
+        let mut seen = HashSet::new();
+        nodes = candidates
+            .into_iter()
+            .filter(|node| step.test.matches(file, *node))
+            .filter(|node| {
+                step.predicates
+                    .iter()
+                    .all(|predicate| predicate.matches(file, *node))
+            })
+            .filter(|node| seen.insert(node.target))
+            .collect();
+    }
+
+    nodes
+}
+
+/// Lists the nodes directly referenced by an element
+fn children<B, S>(file: &File<B, S>, node: Node) -> Vec<Node> {
+    let element = match node.target {
+        Match::Element(element) => element,
+        Match::Attribute { .. } => return Vec::new(),
+    };
+
+    let body = match file.bodies.get(element) {
+        Some(body) => body,
+        None => return Vec::new(),
+    };
+
+    let mut nodes = Vec::new();
+    for (attribute, attr) in body.attributes.iter().enumerate() {
+        let via = Some(attr.name);
+        match &attr.value {
+            AttributeValue::Element(_) | AttributeValue::ElementArray(_) => {
+                for index in attr.value.element_indices() {
+                    if let Ok(index) = TryInto::<usize>::try_into(*index) {
+                        if index < file.bodies.len() {
+                            nodes.push(Node {
+                                target: Match::Element(index),
+                                via,
+                            });
+                        }
+                    }
+                }
+            }
+            _ => nodes.push(Node {
+                target: Match::Attribute { element, attribute },
+                via,
+            }),
+        }
+    }
+
+    nodes
+}
+
+/// Lists all the nodes transitively referenced by the provided nodes, once per
+/// attribute each node is reached from
+fn descendants<B, S>(file: &File<B, S>, nodes: &[Node]) -> Vec<Node> {
+    let mut expanded: HashSet<_> = nodes.iter().map(|node| node.target).collect();
+    let mut reached = HashSet::new();
+    let mut result = Vec::new();
+
+    let mut stack: Vec<_> = nodes
+        .iter()
+        .flat_map(|node| children(file, *node))
+        .collect();
+    stack.reverse();
+
+    while let Some(node) = stack.pop() {
+        if !reached.insert((node.target, node.via)) {
+            continue;
+        }
+
+        result.push(node);
+        if expanded.insert(node.target) {
+            stack.extend(children(file, node).into_iter().rev());
+        }
+    }
+
+    result
+}
+
+impl Test {
+    fn matches<B, S: Deref<Target = str>>(&self, file: &File<B, S>, node: Node) -> bool {
+        let name = match self {
+            Test::Current | Test::Any => return true,
+            Test::Name(name) => name.as_str(),
+        };
+
+        if let Some(via) = node.via {
+            if file.string(via) == Some(name) {
+                return true;
+            }
+        }
+
+        match node.target {
+            Match::Element(index) => match file.headers.get(index) {
+                Some(header) => file.string(header.type_) == Some(name),
+                None => false,
+            },
+            Match::Attribute { .. } => false,
+        }
+    }
+}
+
+impl Predicate {
+    fn matches<B, S: Deref<Target = str>>(&self, file: &File<B, S>, node: Node) -> bool {
+        let nodes = eval_steps(file, &self.steps, vec![node]);
+
+        let mut values = nodes.iter().filter_map(|node| match (self.header, node.target) {
+            (None, target) => Some(Value::Node(target)),
+            (Some(field), Match::Element(index)) => {
+                let header = file.headers.get(index)?;
+                let string = match field {
+                    HeaderField::Type => header.type_,
+                    HeaderField::Name => header.name,
+                };
+                Some(Value::String(file.string(string)?))
+            }
+            (Some(_), Match::Attribute { .. }) => None,
+        });
+
+        match &self.comparison {
+            None => values.next().is_some(),
+            Some((equal, literal)) => {
+                values.any(|value| value.equals(file, literal)) == *equal
+            }
+        }
+    }
+}
+
+enum Value<'a> {
+    Node(Match),
+    String(&'a str),
+}
+
+impl<'a> Value<'a> {
+    fn equals<B, S: Deref<Target = str>>(&self, file: &File<B, S>, literal: &str) -> bool {
+        let (element, attribute) = match self {
+            Value::String(value) => return *value == literal,
+            Value::Node(Match::Element(_)) => return false,
+            Value::Node(Match::Attribute { element, attribute }) => (*element, *attribute),
+        };
+
+        let number = literal.parse::<f64>().ok();
+        let number_equals = |value: f64| number == Some(value);
+        // Floats are compared at their own precision, so that 0.1 matches the
+        // float closest to 0.1
+        let float_equals = |value: f32| number.map(|number| number as f32) == Some(value);
+        let bool_equals = |value: bool| match literal {
+            "true" => value,
+            "false" => !value,
+            _ => number_equals(if value { 1.0 } else { 0.0 }),
+        };
+
+        match &file.bodies[element].attributes[attribute].value {
+            AttributeValue::String(value) => file.string(*value) == Some(literal),
+            AttributeValue::StringArray(values) => values.iter().any(|value| &**value == literal),
+
+            AttributeValue::Int(value) => number_equals(f64::from(*value)),
+            AttributeValue::Float(value) => float_equals(*value),
+            AttributeValue::Bool(value) => bool_equals(*value),
+            AttributeValue::Uint64(value) => number_equals(*value as f64),
+            AttributeValue::Uint8(value) => number_equals(f64::from(*value)),
+            AttributeValue::Time(value) => number_equals(f64::from(value.millis)),
+
+            AttributeValue::IntArray(values) => {
+                values.iter().any(|value| number_equals(f64::from(*value)))
+            }
+            AttributeValue::FloatArray(values) => values.iter().any(|value| float_equals(*value)),
+            AttributeValue::BoolArray(values) => values.iter().any(|value| bool_equals(*value)),
+            AttributeValue::Uint64Array(values) => {
+                values.iter().any(|value| number_equals(*value as f64))
+            }
+            AttributeValue::TimeArray(values) => values
+                .iter()
+                .any(|value| number_equals(f64::from(value.millis))),
+
+            _ => false,
+        }
+    }
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_steps(&mut self, absolute: bool) -> Result<Vec<Step>> {
+        let mut steps = Vec::new();
+
+        let mut descendants = false;
+        if absolute && self.eat('/') {
+            descendants = self.eat('/');
+        }
+
+        loop {
+            steps.push(self.parse_step(descendants)?);
+
+            match self.chars.peek() {
+                Some((_, '/')) => {
+                    self.chars.next();
+                    descendants = self.eat('/');
+                }
+                _ => break,
+            }
+
+            if let Some((_, '@')) = self.chars.peek() {
+                break;
+            }
+        }
+
+        Ok(steps)
+    }
+
+    fn parse_step(&mut self, descendants: bool) -> Result<Step> {
+        let test = if self.eat('*') {
+            Test::Any
+        } else if self.eat('.') {
+            Test::Current
+        } else {
+            Test::Name(self.parse_name()?)
+        };
+
+        let mut predicates = Vec::new();
+        while self.eat('[') {
+            predicates.push(self.parse_predicate()?);
+        }
+
+        Ok(Step {
+            descendants,
+            test,
+            predicates,
+        })
+    }
+
+    fn parse_predicate(&mut self) -> Result<Predicate> {
+        self.skip_whitespace();
+
+        let steps = if let Some((_, '@')) = self.chars.peek() {
+            Vec::new()
+        } else {
+            self.parse_steps(false)?
+        };
+
+        let header = if self.eat('@') {
+            Some(match self.parse_name()?.as_str() {
+                "type" => HeaderField::Type,
+                "name" => HeaderField::Name,
+                field => bail!("Unknown header field @{} in query {:?}", field, self.query),
+            })
+        } else {
+            None
+        };
+
+        self.skip_whitespace();
+
+        let comparison = if self.eat('!') {
+            self.expect('=')?;
+            Some((false, self.parse_literal()?))
+        } else if self.eat('=') {
+            Some((true, self.parse_literal()?))
+        } else {
+            None
+        };
+
+        self.skip_whitespace();
+        self.expect(']')?;
+
+        Ok(Predicate {
+            steps,
+            header,
+            comparison,
+        })
+    }
+
+    fn parse_name(&mut self) -> Result<String> {
+        let mut name = String::new();
+        while let Some((_, ch)) = self.chars.peek() {
+            if ch.is_alphanumeric() || "_$:.-".contains(*ch) {
+                name.push(*ch);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        if name.is_empty() {
+            Err(self.unexpected("a name"))
+        } else {
+            Ok(name)
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<String> {
+        self.skip_whitespace();
+
+        let quote = match self.chars.peek() {
+            Some((_, quote @ '\'')) | Some((_, quote @ '"')) => *quote,
+            _ => {
+                let mut literal = String::new();
+                while let Some((_, ch)) = self.chars.peek() {
+                    if *ch == ']' || ch.is_whitespace() {
+                        break;
+                    }
+                    literal.push(*ch);
+                    self.chars.next();
+                }
+                return Ok(literal);
+            }
+        };
+
+        self.chars.next();
+
+        let mut literal = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, ch)) => literal.push(ch),
+                    None => break,
+                },
+                Some((_, ch)) if ch == quote => return Ok(literal),
+                Some((_, ch)) => literal.push(ch),
+                None => break,
+            }
+        }
+
+        Err(anyhow!("Unterminated string in query {:?}", self.query))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some((_, ch)) = self.chars.peek() {
+            if !ch.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        match self.chars.peek() {
+            Some((_, ch)) if *ch == expected => {
+                self.chars.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("{:?}", expected)))
+        }
+    }
+
+    fn unexpected(&mut self, expected: &str) -> anyhow::Error {
+        match self.chars.peek() {
+            Some((index, _)) => anyhow!(
+                "Expected {} at {:?} in query {:?}",
+                expected,
+                &self.query[*index..],
+                self.query
+            ),
+            None => anyhow!(
+                "Expected {} at the end of query {:?}",
+                expected,
+                self.query
+            ),
+        }
+    }
+}
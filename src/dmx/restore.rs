@@ -0,0 +1,252 @@
+//! Restoration of the data of an original file that a rewritten file lacks
+use std::{collections::HashMap, convert::TryInto, mem::take, ops::Deref, os::raw::c_int};
+
+use super::{Attribute, AttributeType, AttributeValue, Body, File, Header, StringRef, StringTable};
+
+impl File<Vec<u8>, String> {
+    /// Copies from `original` the data that this file lacks, for the elements
+    /// of both files with the same GUID:
+    ///
+    /// - The names of the elements.
+    /// - The attributes of the original element that are missing, along with
+    ///   the elements they reference (which are copied unless this file
+    ///   already has an element with the same GUID).
+    /// - The type of the attributes that are an empty ElementArray while the
+    ///   original attribute is another array type, since the type of an
+    ///   empty sequence is unknown when [serializing](crate::serde::to_file).
+    /// - The order of the attributes, with the attributes that are not in the
+    ///   original element last.
+    ///
+    /// The prefix attributes of `original` are also copied if this file has
+    /// none. This is used to write back a file from typed structs that only
+    /// model part of its content, such as
+    /// [write_vmap](crate::formats::vmap::write_vmap).
+    pub fn restore<B, S>(&mut self, original: &File<B, S>)
+    where
+        B: Deref<Target = [u8]>,
+        S: Deref<Target = str>,
+    {
+        self.restore_unmodelled(original, |_, _| false);
+    }
+
+    /// Restores the data of `original` like [restore](Self::restore), except
+    /// for the missing attributes for which `modelled` returns true given the
+    /// GUID of their element and their name, which are left out
+    ///
+    /// This lets attributes be removed by the values they were written from,
+    /// like an optional field of a struct set to None. Null element references
+    /// are restored anyway, since they are read as None as well.
+    pub fn restore_unmodelled<B, S, F>(&mut self, original: &File<B, S>, modelled: F)
+    where
+        B: Deref<Target = [u8]>,
+        S: Deref<Target = str>,
+        F: Fn(&[u8; 16], &str) -> bool,
+    {
+        let len = original.headers.len().min(original.bodies.len());
+        let mut originals = HashMap::with_capacity(len);
+        for (index, header) in original.headers[..len].iter().enumerate() {
+            originals.entry(header.guid).or_insert(index);
+        }
+
+        let mut elements = HashMap::with_capacity(self.headers.len());
+        for (index, header) in self.headers.iter().enumerate() {
+            elements.entry(header.guid).or_insert(index as c_int);
+        }
+
+        let mut restorer = Restorer {
+            original,
+            len,
+            strings: StringTable::from_strings(take(&mut self.strings)),
+            headers: &mut self.headers,
+            bodies: &mut self.bodies,
+            elements,
+            pending: Vec::new(),
+            modelled,
+        };
+
+        let count = restorer.headers.len().min(restorer.bodies.len());
+        for index in 0..count {
+            if let Some(original_index) = originals.get(&restorer.headers[index].guid) {
+                restorer.restore_element(index, *original_index);
+            }
+        }
+
+        if self.prefix.is_empty() {
+            self.prefix = original
+                .prefix
+                .iter()
+                .map(|(name, value)| {
+                    let mut value = value.convert(
+                        |buffer| buffer.to_vec(),
+                        |string| string.to_string(),
+                        |string| string.to_string(),
+                    );
+                    for index in value.element_indices_mut() {
+                        *index = restorer.reference(*index);
+                    }
+                    (name.to_string(), value)
+                })
+                .collect();
+        }
+
+        restorer.copy_pending();
+        self.strings = restorer.strings.into_strings();
+    }
+}
+
+struct Restorer<'a, B, S, F> {
+    original: &'a File<B, S>,
+    /// Number of valid elements in the original file
+    len: usize,
+    strings: StringTable,
+    headers: &'a mut Vec<Header>,
+    bodies: &'a mut Vec<Body<Vec<u8>, String>>,
+    /// Index of the element with each GUID in the restored file
+    elements: HashMap<[u8; 16], c_int>,
+    /// Elements copied from the original file whose attributes are yet to
+    /// be copied, as (original index, new index)
+    pending: Vec<(usize, usize)>,
+    /// Whether an attribute of the element with a GUID is modelled by the
+    /// value it was written from, so that it is not restored when missing
+    modelled: F,
+}
+
+impl<'a, B, S, F> Restorer<'a, B, S, F>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+    F: Fn(&[u8; 16], &str) -> bool,
+{
+    fn restore_element(&mut self, index: usize, original_index: usize) {
+        let original = self.original;
+        self.headers[index].name = self.string(original.headers[original_index].name);
+
+        let mut attributes = take(&mut self.bodies[index].attributes);
+        let mut order = Vec::new();
+
+        for attr in &original.bodies[original_index].attributes {
+            let name = self.string(attr.name);
+            order.push(name);
+
+            match attributes.iter_mut().find(|other| other.name == name) {
+                Some(other) => {
+                    let is_empty_array = match &other.value {
+                        AttributeValue::ElementArray(indices) => indices.is_empty(),
+                        _ => false,
+                    };
+
+                    if is_empty_array {
+                        if let Some(value) = empty_value(attr.value.kind()) {
+                            other.value = value;
+                        }
+                    }
+                }
+                None => {
+                    let guid = &self.headers[index].guid;
+                    let modelled = original
+                        .string(attr.name)
+                        .is_some_and(|name| (self.modelled)(guid, name));
+                    // Null references read as None like missing attributes
+                    let is_null = matches!(attr.value, AttributeValue::Element(-1));
+
+                    if !modelled || is_null {
+                        let value = self.copy_value(&attr.value);
+                        attributes.push(Attribute { name, value });
+                    }
+                }
+            }
+        }
+
+        attributes.sort_by_key(|attr| {
+            order
+                .iter()
+                .position(|name| *name == attr.name)
+                .unwrap_or(usize::MAX)
+        });
+
+        self.bodies[index].attributes = attributes;
+    }
+
+    /// Copies the attributes of the elements referenced from the copied data
+    fn copy_pending(&mut self) {
+        let original = self.original;
+        while let Some((original_index, index)) = self.pending.pop() {
+            let attributes = original.bodies[original_index]
+                .attributes
+                .iter()
+                .map(|attr| Attribute {
+                    name: self.string(attr.name),
+                    value: self.copy_value(&attr.value),
+                })
+                .collect();
+
+            self.bodies[index].attributes = attributes;
+        }
+    }
+
+    fn copy_value(
+        &mut self,
+        value: &AttributeValue<B, S, StringRef>,
+    ) -> AttributeValue<Vec<u8>, String, StringRef> {
+        let mut value = value.convert(
+            |buffer| buffer.to_vec(),
+            |string| string.to_string(),
+            |string| *string,
+        );
+
+        if let AttributeValue::String(string) = &mut value {
+            *string = self.string(*string);
+        }
+        for index in value.element_indices_mut() {
+            *index = self.reference(*index);
+        }
+
+        value
+    }
+
+    /// Index in the restored file of an element of the original file, the
+    /// element is copied if there is no element with its GUID yet
+    fn reference(&mut self, index: c_int) -> c_int {
+        let original_index: usize = match index.try_into() {
+            Ok(index) if index < self.len => index,
+            _ => return -1,
+        };
+
+        let header = &self.original.headers[original_index];
+        if let Some(index) = self.elements.get(&header.guid) {
+            return *index;
+        }
+
+        let index = self.headers.len();
+        let header = Header {
+            type_: self.string(header.type_),
+            name: self.string(header.name),
+            guid: header.guid,
+        };
+
+        self.elements.insert(header.guid, index as c_int);
+        self.headers.push(header);
+        self.bodies.push(Body {
+            attributes: Vec::new(),
+        });
+        self.pending.push((original_index, index));
+
+        index as c_int
+    }
+
+    /// Interns a string of the original file in the restored file
+    fn string(&mut self, string: StringRef) -> StringRef {
+        let original = self.original;
+        self.strings
+            .intern(original.string(string).unwrap_or_default())
+    }
+}
+
+/// Empty value of an array type (or Binary)
+fn empty_value(kind: AttributeType) -> Option<AttributeValue<Vec<u8>, String, StringRef>> {
+    match kind {
+        AttributeType::Binary => Some(AttributeValue::Binary(Vec::new())),
+        AttributeType::ElementArray => None,
+        kind => AttributeValue::empty_array(kind),
+    }
+}
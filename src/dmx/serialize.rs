@@ -0,0 +1,316 @@
+//! [Serialize] implementation for [File], following the schema described in
+//! the [json](super::json) module
+//!
+//! Human-readable formats get the same encoding as JSON. Other formats
+//! store GUIDs, Binary values, Uint64 values and non-finite floats natively.
+use std::{fmt::Write, ops::Deref, os::raw::c_int};
+
+use serde::ser::{Serialize, SerializeSeq, SerializeStruct, Serializer};
+
+use super::{format_guid, AttributeValue, Color, File, StringRef};
+
+impl<B, S> Serialize for File<B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut state = serializer.serialize_struct("File", 6)?;
+        state.serialize_field("encoding", &*self.header.encoding_name)?;
+        state.serialize_field("encoding_version", &self.header.encoding_version)?;
+        state.serialize_field("format", &*self.header.format_name)?;
+        state.serialize_field("format_version", &self.header.format_version)?;
+        state.serialize_field("prefix", &Prefix(self))?;
+        state.serialize_field("elements", &Elements(self))?;
+        state.end()
+    }
+}
+
+struct Prefix<'a, B, S>(&'a File<B, S>);
+
+impl<'a, B, S> Serialize for Prefix<'a, B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.prefix.len()))?;
+        for (name, value) in &self.0.prefix {
+            seq.serialize_element(&AttributeWrapper {
+                name,
+                value: ValueWrapper {
+                    value,
+                    string: |value: &'a S| Some(&**value),
+                },
+            })?;
+        }
+        seq.end()
+    }
+}
+
+struct Elements<'a, B, S>(&'a File<B, S>);
+
+impl<'a, B, S> Serialize for Elements<'a, B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let file = self.0;
+        let len = file.headers.len().min(file.bodies.len());
+        let mut seq = serializer.serialize_seq(Some(len))?;
+        for index in 0..len {
+            seq.serialize_element(&ElementWrapper { file, index })?;
+        }
+        seq.end()
+    }
+}
+
+struct ElementWrapper<'a, B, S> {
+    file: &'a File<B, S>,
+    index: usize,
+}
+
+impl<'a, B, S> Serialize for ElementWrapper<'a, B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let file = self.file;
+        let header = &file.headers[self.index];
+        let string = |value: StringRef| file.string(value).unwrap_or_default();
+
+        let mut state = serializer.serialize_struct("Element", 4)?;
+        state.serialize_field("type", string(header.type_))?;
+        state.serialize_field("name", string(header.name))?;
+        state.serialize_field("guid", &Guid(&header.guid))?;
+        state.serialize_field("attributes", &Attributes(self))?;
+        state.end()
+    }
+}
+
+struct Attributes<'b, 'a, B, S>(&'b ElementWrapper<'a, B, S>);
+
+impl<'b, 'a, B, S> Serialize for Attributes<'b, 'a, B, S>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let file = self.0.file;
+        let attributes = &file.bodies[self.0.index].attributes;
+        let string = |value: &'a StringRef| file.string(*value);
+
+        let mut seq = serializer.serialize_seq(Some(attributes.len()))?;
+        for attr in attributes {
+            seq.serialize_element(&AttributeWrapper {
+                name: string(&attr.name).unwrap_or_default(),
+                value: ValueWrapper {
+                    value: &attr.value,
+                    string,
+                },
+            })?;
+        }
+        seq.end()
+    }
+}
+
+struct AttributeWrapper<'a, B, S, R, F> {
+    name: &'a str,
+    value: ValueWrapper<'a, B, S, R, F>,
+}
+
+impl<'a, B, S, R, F> Serialize for AttributeWrapper<'a, B, S, R, F>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+    F: Fn(&'a R) -> Option<&'a str>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut state = serializer.serialize_struct("Attribute", 3)?;
+        state.serialize_field("name", self.name)?;
+        state.serialize_field("type", self.value.value.kind().name())?;
+        state.serialize_field("value", &self.value)?;
+        state.end()
+    }
+}
+
+/// An attribute value whose strings are resolved by `string`, null strings
+/// are serialized as none
+struct ValueWrapper<'a, B, S, R, F> {
+    value: &'a AttributeValue<B, S, R>,
+    string: F,
+}
+
+impl<'a, B, S, R, F> Serialize for ValueWrapper<'a, B, S, R, F>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+    F: Fn(&'a R) -> Option<&'a str>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        match self.value {
+            AttributeValue::Element(value) => element(*value).serialize(serializer),
+            AttributeValue::Int(value) => value.serialize(serializer),
+            AttributeValue::Float(value) => Float(*value).serialize(serializer),
+            AttributeValue::Bool(value) => value.serialize(serializer),
+            AttributeValue::String(value) => (self.string)(value).serialize(serializer),
+            AttributeValue::Binary(value) => Bytes(value).serialize(serializer),
+            AttributeValue::Time(value) => value.millis.serialize(serializer),
+            AttributeValue::Color(value) => color(value).serialize(serializer),
+            AttributeValue::Vector2(value) => floats([value.x, value.y]).serialize(serializer),
+            AttributeValue::Vector3(value) => {
+                floats([value.x, value.y, value.z]).serialize(serializer)
+            }
+            AttributeValue::Vector4(value) => {
+                floats([value.x, value.y, value.z, value.w]).serialize(serializer)
+            }
+            AttributeValue::Qangle(value) => {
+                floats([value.pitch, value.yaw, value.roll]).serialize(serializer)
+            }
+            AttributeValue::Quaternion(value) => {
+                floats([value.x, value.y, value.z, value.w]).serialize(serializer)
+            }
+            AttributeValue::Vmatrix(value) => floats(value.0).serialize(serializer),
+            AttributeValue::Uint64(value) => Uint64(*value).serialize(serializer),
+            AttributeValue::Uint8(value) => value.serialize(serializer),
+
+            AttributeValue::ElementArray(values) => {
+                serialize_items(serializer, values, |value| element(*value))
+            }
+            AttributeValue::IntArray(values) => values.serialize(serializer),
+            AttributeValue::FloatArray(values) => {
+                serialize_items(serializer, values, |value| Float(*value))
+            }
+            AttributeValue::BoolArray(values) => values.serialize(serializer),
+            AttributeValue::StringArray(values) => {
+                serialize_items(serializer, values, |value| &**value)
+            }
+            AttributeValue::BinaryArray(values) => {
+                serialize_items(serializer, values, |value| Bytes(value))
+            }
+            AttributeValue::TimeArray(values) => {
+                serialize_items(serializer, values, |value| value.millis)
+            }
+            AttributeValue::ColorArray(values) => serialize_items(serializer, values, color),
+            AttributeValue::Vector2Array(values) => {
+                serialize_items(serializer, values, |value| floats([value.x, value.y]))
+            }
+            AttributeValue::Vector3Array(values) => serialize_items(serializer, values, |value| {
+                floats([value.x, value.y, value.z])
+            }),
+            AttributeValue::Vector4Array(values) => serialize_items(serializer, values, |value| {
+                floats([value.x, value.y, value.z, value.w])
+            }),
+            AttributeValue::QangleArray(values) => serialize_items(serializer, values, |value| {
+                floats([value.pitch, value.yaw, value.roll])
+            }),
+            AttributeValue::QuaternionArray(values) => {
+                serialize_items(serializer, values, |value| {
+                    floats([value.x, value.y, value.z, value.w])
+                })
+            }
+            AttributeValue::VmatrixArray(values) => {
+                serialize_items(serializer, values, |value| floats(value.0))
+            }
+            AttributeValue::Uint64Array(values) => {
+                serialize_items(serializer, values, |value| Uint64(*value))
+            }
+        }
+    }
+}
+
+fn serialize_items<'a, Z, T, U>(
+    serializer: Z,
+    items: &'a [T],
+    convert: impl Fn(&'a T) -> U,
+) -> Result<Z::Ok, Z::Error>
+where
+    Z: Serializer,
+    U: Serialize,
+{
+    let mut seq = serializer.serialize_seq(Some(items.len()))?;
+    for item in items {
+        seq.serialize_element(&convert(item))?;
+    }
+    seq.end()
+}
+
+/// Null element references are serialized as none
+fn element(index: c_int) -> Option<c_int> {
+    if index == -1 {
+        None
+    } else {
+        Some(index)
+    }
+}
+
+fn color(color: &Color) -> [u8; 4] {
+    [color.r as u8, color.g as u8, color.b as u8, color.a as u8]
+}
+
+fn floats<const N: usize>(values: [f32; N]) -> [Float; N] {
+    values.map(Float)
+}
+
+/// Non-finite floats are serialized as strings in human-readable formats
+#[derive(Copy, Clone)]
+struct Float(f32);
+
+impl Serialize for Float {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        if self.0.is_finite() || !serializer.is_human_readable() {
+            serializer.serialize_f32(self.0)
+        } else if self.0.is_nan() {
+            serializer.serialize_str("NaN")
+        } else if self.0 > 0.0 {
+            serializer.serialize_str("inf")
+        } else {
+            serializer.serialize_str("-inf")
+        }
+    }
+}
+
+/// GUIDs are serialized in the registry format in human-readable formats
+struct Guid<'a>(&'a [u8; 16]);
+
+impl<'a> Serialize for Guid<'a> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&format_guid(self.0))
+        } else {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+/// Binary data is serialized as a hexadecimal string in human-readable formats
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for Bytes<'a> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        if serializer.is_human_readable() {
+            let mut value = String::with_capacity(self.0.len() * 2);
+            for byte in self.0 {
+                write!(value, "{:02x}", byte).unwrap();
+            }
+            serializer.serialize_str(&value)
+        } else {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+/// Uint64 values are serialized as decimal strings in human-readable formats
+struct Uint64(u64);
+
+impl Serialize for Uint64 {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(&self.0)
+        } else {
+            serializer.serialize_u64(self.0)
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Structural checks on the references stored in a [File]
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    convert::TryInto,
+    fmt::{self, Display, Formatter},
+    ops::Deref,
+    os::raw::c_int,
+};
+
+use super::{AttributeValue, File, StringRef};
+
+/// A problem found in a [File] by [File::validate]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// Index of the element the problem was found in, None for file-level problems
+    pub element: Option<usize>,
+    /// Type and name of the element, if they could be resolved
+    pub element_name: Option<String>,
+    /// Name of the attribute the problem was found in, or its position in
+    /// the element body if the name could not be resolved
+    pub attribute: Option<String>,
+    pub kind: DiagnosticKind,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DiagnosticKind {
+    /// The file has no element 0 to start deserializing from
+    MissingRoot,
+    /// The number of element headers does not match the number of bodies
+    BodyCountMismatch { headers: usize, bodies: usize },
+    /// The type of an element is not a valid string table index
+    InvalidType(StringRef),
+    /// The name of an element is not a valid string table index
+    InvalidName(StringRef),
+    /// The name of an attribute is not a valid string table index
+    InvalidAttributeName(StringRef),
+    /// The value of a String attribute is not a valid string table index (or -1)
+    InvalidString(StringRef),
+    /// An element reference is neither -1 nor a valid element index
+    InvalidElement(c_int),
+    /// An element has multiple attributes with the same name
+    DuplicateAttribute,
+    /// An element has the same GUID as a previous element
+    DuplicateGuid { first: usize },
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        if let Some(element) = self.element {
+            write!(fmt, "element #{}", element)?;
+            if let Some(name) = &self.element_name {
+                write!(fmt, " ({})", name)?;
+            }
+            if let Some(attribute) = &self.attribute {
+                write!(fmt, ", attribute {}", attribute)?;
+            }
+            write!(fmt, ": ")?;
+        }
+
+        match &self.kind {
+            DiagnosticKind::MissingRoot => write!(fmt, "file has no root element"),
+            DiagnosticKind::BodyCountMismatch { headers, bodies } => write!(
+                fmt,
+                "file has {} element headers but {} bodies",
+                headers, bodies
+            ),
+            DiagnosticKind::InvalidType(index) => {
+                write!(fmt, "element type {} is out of range", index.0)
+            }
+            DiagnosticKind::InvalidName(index) => {
+                write!(fmt, "element name {} is out of range", index.0)
+            }
+            DiagnosticKind::InvalidAttributeName(index) => {
+                write!(fmt, "attribute name {} is out of range", index.0)
+            }
+            DiagnosticKind::InvalidString(index) => {
+                write!(fmt, "string value {} is out of range", index.0)
+            }
+            DiagnosticKind::InvalidElement(index) => {
+                write!(fmt, "element reference {} is out of range", index)
+            }
+            DiagnosticKind::DuplicateAttribute => write!(fmt, "attribute is defined twice"),
+            DiagnosticKind::DuplicateGuid { first } => {
+                write!(fmt, "GUID is already used by element #{}", first)
+            }
+        }
+    }
+}
+
+impl<B, S: Deref<Target = str>> File<B, S> {
+    /// Checks that all the string and element references in the file are in
+    /// range, that element 0 exists, and that attribute names and element
+    /// GUIDs are unique. An empty list means the file can be safely traversed.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        if self.headers.is_empty() {
+            diagnostics.push(Diagnostic {
+                element: None,
+                element_name: None,
+                attribute: None,
+                kind: DiagnosticKind::MissingRoot,
+            });
+        }
+
+        if self.headers.len() != self.bodies.len() {
+            diagnostics.push(Diagnostic {
+                element: None,
+                element_name: None,
+                attribute: None,
+                kind: DiagnosticKind::BodyCountMismatch {
+                    headers: self.headers.len(),
+                    bodies: self.bodies.len(),
+                },
+            });
+        }
+
+        let mut guids = HashMap::new();
+
+        for (index, header) in self.headers.iter().enumerate() {
+            let element_name = match (self.string(header.type_), self.string(header.name)) {
+                (Some(type_), Some(name)) => Some(format!("{} {:?}", type_, name)),
+                (Some(type_), None) => Some(type_.to_string()),
+                _ => None,
+            };
+
+            let report = |diagnostics: &mut Vec<_>, attribute: Option<String>, kind| {
+                diagnostics.push(Diagnostic {
+                    element: Some(index),
+                    element_name: element_name.clone(),
+                    attribute,
+                    kind,
+                })
+            };
+
+            if self.string(header.type_).is_none() {
+                report(
+                    &mut diagnostics,
+                    None,
+                    DiagnosticKind::InvalidType(header.type_),
+                );
+            }
+
+            if self.string(header.name).is_none() {
+                report(
+                    &mut diagnostics,
+                    None,
+                    DiagnosticKind::InvalidName(header.name),
+                );
+            }
+
+            match guids.entry(header.guid) {
+                Entry::Occupied(entry) => report(
+                    &mut diagnostics,
+                    None,
+                    DiagnosticKind::DuplicateGuid { first: *entry.get() },
+                ),
+                Entry::Vacant(entry) => {
+                    entry.insert(index);
+                }
+            }
+
+            let body = match self.bodies.get(index) {
+                Some(body) => body,
+                None => continue,
+            };
+
+            let mut names = HashSet::new();
+
+            for (position, attr) in body.attributes.iter().enumerate() {
+                let name = self.string(attr.name);
+                let attribute = Some(match name {
+                    Some(name) => format!("{:?}", name),
+                    None => format!("#{}", position),
+                });
+
+                match name {
+                    Some(name) => {
+                        if !names.insert(name) {
+                            report(
+                                &mut diagnostics,
+                                attribute.clone(),
+                                DiagnosticKind::DuplicateAttribute,
+                            );
+                        }
+                    }
+                    None => report(
+                        &mut diagnostics,
+                        attribute.clone(),
+                        DiagnosticKind::InvalidAttributeName(attr.name),
+                    ),
+                }
+
+                if let AttributeValue::String(value) = attr.value {
+                    if value.0 != -1 && self.string(value).is_none() {
+                        report(
+                            &mut diagnostics,
+                            attribute.clone(),
+                            DiagnosticKind::InvalidString(value),
+                        );
+                    }
+                }
+
+                for element in attr.value.element_indices() {
+                    let is_valid = match TryInto::<usize>::try_into(*element) {
+                        Ok(element) => element < self.headers.len(),
+                        Err(_) => *element == -1,
+                    };
+
+                    if !is_valid {
+                        report(
+                            &mut diagnostics,
+                            attribute.clone(),
+                            DiagnosticKind::InvalidElement(*element),
+                        );
+                    }
+                }
+            }
+        }
+
+        diagnostics
+    }
+}
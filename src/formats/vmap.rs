@@ -1,27 +1,64 @@
-//! Rust representation of the VMAP format, deserializable from a [File]
+//! Rust representation of the VMAP format, deserializable from a [File] and
+//! serializable back to one
 use crate::{
     dmx::{Color, File, Qangle, Quaternion, Vector2, Vector3, Vector4},
-    serde::{from_file, BufferWrapper, StringWrapper},
+    serde::{
+        from_file, reference::FileRef, to_file_modelled, BufferWrapper, Custom, Error, Guid,
+        StringWrapper, Transient, ID_FIELD,
+    },
 };
 use serde::{
-    de::{value::Error, IntoDeserializer},
-    Deserialize, Serialize,
+    de::{IntoDeserializer, MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
 };
 use std::{
     collections::HashMap,
-    fmt::Debug,
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
     os::raw::{c_float, c_int},
 };
 
 pub fn read_vmap<'de, B, S: Debug>(file: &'de File<B, S>) -> Result<CMapRootElement<B, S>, Error>
 where
-    BufferWrapper<'de, B>: IntoDeserializer<'de>,
-    StringWrapper<'de, S>: IntoDeserializer<'de>,
+    &'de File<B, S>: Into<FileRef<'de>>,
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
     CMapRootElement<B, S>: Deserialize<'de>,
 {
     from_file(file)
 }
 
+/// Writes a map read with [read_vmap] back to a new [File]
+///
+/// The elements keep the GUIDs stored in their `id` field, and the data that
+/// the structs of this module do not model is copied from the elements of
+/// `original` with the same GUIDs (see [File::restore_unmodelled]): element
+/// names, the attributes that are not fields of the structs and the prefix
+/// attributes. Setting an optional field to None removes its attribute, and
+/// removing a property of an [EditGameClassProps] removes it as well. Elements
+/// that are not referenced from the root are not written.
+pub fn write_vmap<B, S, B2, S2>(
+    root: &CMapRootElement<B, S>,
+    original: &File<B2, S2>,
+) -> Result<File<Vec<u8>, String>, Error>
+where
+    CMapRootElement<B, S>: Serialize,
+    B2: Deref<Target = [u8]>,
+    S2: Deref<Target = str>,
+{
+    let (mut file, modelled) = to_file_modelled(
+        root,
+        &original.header.format_name,
+        original.header.format_version,
+    )?;
+
+    file.restore_unmodelled(original, |guid, name| modelled.contains(guid, name));
+    Ok(file)
+}
+
 pub type Element<B, S> = Box<ElementType<B, S>>;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,7 +76,98 @@ pub enum PropValue<B, S> {
     DmElement(DmElement<B, S>),
 }
 
-pub type EditGameClassProps<B, S> = HashMap<String, PropValue<B, S>>;
+/// Properties of an entity, stored as the attributes of an
+/// `EditGameClassProps` element
+#[derive(Debug)]
+pub struct EditGameClassProps<B, S> {
+    pub id: Option<Guid>,
+    pub properties: HashMap<String, PropValue<B, S>>,
+}
+
+impl<B, S> Deref for EditGameClassProps<B, S> {
+    type Target = HashMap<String, PropValue<B, S>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.properties
+    }
+}
+
+impl<B, S> DerefMut for EditGameClassProps<B, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.properties
+    }
+}
+
+// The properties are stored as a map, wrapped in a newtype struct to give its
+// name to the element written by `to_file`
+impl<B, S> Serialize for EditGameClassProps<B, S>
+where
+    PropValue<B, S>: Serialize,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.serialize_newtype_struct("EditGameClassProps", &PropertiesMap(self))
+    }
+}
+
+struct PropertiesMap<'a, B, S>(&'a EditGameClassProps<B, S>);
+
+impl<'a, B, S> Serialize for PropertiesMap<'a, B, S>
+where
+    PropValue<B, S>: Serialize,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let props = self.0;
+        let len = props.properties.len() + props.id.iter().count();
+        let mut map = serializer.serialize_map(Some(len))?;
+        if let Some(id) = &props.id {
+            map.serialize_entry(ID_FIELD, id)?;
+        }
+        for (key, value) in &props.properties {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, B, S> Deserialize<'de> for EditGameClassProps<B, S>
+where
+    PropValue<B, S>: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct(
+            "EditGameClassProps",
+            &[ID_FIELD],
+            PropertiesVisitor(PhantomData),
+        )
+    }
+}
+
+struct PropertiesVisitor<B, S>(PhantomData<(B, S)>);
+
+impl<'de, B, S> Visitor<'de> for PropertiesVisitor<B, S>
+where
+    PropValue<B, S>: Deserialize<'de>,
+{
+    type Value = EditGameClassProps<B, S>;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("a map of entity properties")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut id = None;
+        let mut properties = HashMap::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if key == ID_FIELD {
+                id = map.next_value()?;
+            } else {
+                properties.insert(key, map.next_value()?);
+            }
+        }
+
+        Ok(EditGameClassProps { id, properties })
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ElementType<B, S> {
@@ -91,16 +219,23 @@ pub enum ElementType<B, S> {
     DmeConnectionData(DmeConnectionData<S>),
     DmePlugList(DmePlugList<S>),
     DmeVertexData(DmeVertexData<S>),
+    /// Element decoded by the [Registry](crate::serde::Registry) in scope
+    #[serde(rename = "$custom")]
+    Custom(Custom),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmExtraVertexData<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "m_ExtraStreams")]
     pub extra_streams: Vec<Element<B, S>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmExtraVertexStream<S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "m_nDrawCallIndex")]
     pub n_draw_call_index: c_int,
     #[serde(rename = "m_nMeshIndex")]
@@ -111,6 +246,8 @@ pub struct CDmExtraVertexStream<S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmeDrawCallSnapshot<S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "m_Material")]
     pub material: S,
     #[serde(rename = "m_Normals")]
@@ -125,6 +262,8 @@ pub struct CDmeDrawCallSnapshot<S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmeNavData {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "settingsAgentHeight_0")]
     pub settings_agent_height_0: c_float,
     #[serde(rename = "settingsAgentHeight_1")]
@@ -197,6 +336,8 @@ pub struct CDmeNavData {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmeNodeInstanceData {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "vertexLightingData")]
     pub vertex_lighting_data: Vec<Color>,
     #[serde(rename = "vertexLightingPositions")]
@@ -205,6 +346,8 @@ pub struct CDmeNodeInstanceData {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmePolygonMesh<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "edgeData")]
     pub edge_data: CDmePolygonMeshDataArray<B, S>,
     #[serde(rename = "edgeDataIndices")]
@@ -240,12 +383,16 @@ pub struct CDmePolygonMesh<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmePolygonMeshDataArray<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub size: c_int,
     pub streams: Vec<Element<B, S>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmePolygonMeshDataStream<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub data: PolygonMeshStreamData,
     #[serde(rename = "dataStateFlags")]
     pub data_state_flags: c_int,
@@ -263,6 +410,8 @@ pub struct CDmePolygonMeshDataStream<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmePolygonMeshSubdivisionData<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub streams: Vec<Element<B, S>>,
     #[serde(rename = "subdivisionLevels")]
     pub subdivision_levels: Vec<c_int>,
@@ -270,6 +419,8 @@ pub struct CDmePolygonMeshSubdivisionData<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmePolygonMeshSubdivisiondataBinding {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "streamSourceType")]
     pub stream_source_type: c_int,
     #[serde(rename = "targetDataType")]
@@ -280,6 +431,8 @@ pub struct CDmePolygonMeshSubdivisiondataBinding {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmeReferencedMeshSnapshot<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "m_DrawCalls")]
     pub draw_calls: Vec<Element<B, S>>,
     #[serde(rename = "m_MeshResourceName")]
@@ -288,6 +441,8 @@ pub struct CDmeReferencedMeshSnapshot<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CDmeTileMesh<S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "faceIds")]
     pub face_ids: Vec<c_int>,
     #[serde(rename = "materialSetAssignments")]
@@ -306,13 +461,18 @@ pub struct CDmeTileMesh<S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CFaceSelectionSetDataElement<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub faces: Vec<c_int>,
     pub meshes: Vec<Element<B, S>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapCable<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "collisionEnabled")]
     pub collision_enabled: bool,
@@ -369,7 +529,13 @@ pub struct CMapCable<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapCordon<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(
+        with = "crate::serde::skip_null",
+        bound(serialize = "B: Serialize", deserialize = "B: Deserialize<'de>")
+    )]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "editorOnly")]
     pub editor_only: bool,
@@ -390,6 +556,8 @@ pub struct CMapCordon<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapEntity<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
     #[serde(rename = "boneNames")]
     pub bone_names: Option<Vec<S>>,
@@ -397,6 +565,7 @@ pub struct CMapEntity<B, S> {
     pub bone_positions: Option<Vec<Vector3>>,
     #[serde(rename = "boneRotations")]
     pub bone_rotations: Option<Vec<Quaternion>>,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "connectionsData")]
     pub connections_data: Vec<Element<B, S>>,
@@ -427,7 +596,13 @@ pub struct CMapEntity<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapGroup<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(
+        with = "crate::serde::skip_null",
+        bound(serialize = "B: Serialize", deserialize = "B: Deserialize<'de>")
+    )]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "editorOnly")]
     pub editor_only: bool,
@@ -448,9 +623,12 @@ pub struct CMapGroup<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapGroupProxy<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "alwaysOrientUp")]
     pub always_orient_up: bool,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     pub conditional: bool,
     #[serde(rename = "contentsDeform")]
@@ -504,7 +682,10 @@ pub struct CMapGroupProxy<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapInstance<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "connectionsData")]
     pub connections_data: Vec<Element<B, S>>,
@@ -530,9 +711,12 @@ pub struct CMapInstance<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapMesh<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
     pub bakelighting: Option<bool>,
     pub bakelightoutput: Option<c_int>,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "cubeMapName")]
     pub cube_map_name: S,
@@ -593,7 +777,13 @@ pub struct CMapMesh<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapNavData<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(
+        with = "crate::serde::skip_null",
+        bound(serialize = "B: Serialize", deserialize = "B: Deserialize<'de>")
+    )]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "editorOnly")]
     pub editor_only: bool,
@@ -616,7 +806,10 @@ pub struct CMapNavData<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapPath<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "connectionsData")]
     pub connections_data: Vec<Element<B, S>>,
@@ -648,7 +841,10 @@ pub struct CMapPath<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapPathNode<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "connectionsData")]
     pub connections_data: Vec<Element<B, S>>,
@@ -694,9 +890,15 @@ pub struct CMapPathNode<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapPhysicsPin<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
     #[serde(rename = "bConstrainRotation")]
     pub b_constrain_rotation: Option<bool>,
+    #[serde(
+        with = "crate::serde::skip_null",
+        bound(serialize = "B: Serialize", deserialize = "B: Deserialize<'de>")
+    )]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "constrainRotation")]
     pub constrain_rotation: bool,
@@ -725,7 +927,10 @@ pub struct CMapPhysicsPin<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapPrefab<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "connectionsData")]
     pub connections_data: Vec<Element<B, S>>,
@@ -768,7 +973,10 @@ pub struct CMapPrefab<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapProxyInstance<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "connectionsData")]
     pub connections_data: Vec<Element<B, S>>,
@@ -796,6 +1004,8 @@ pub struct CMapProxyInstance<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapRootElement<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "3dcameras")]
     pub _3dcameras: Option<Element<B, S>>,
     pub defaultcamera: CStoredCamera,
@@ -828,6 +1038,9 @@ pub struct CMapRootElement<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapSelectionSet<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "selectionSetData")]
     pub selection_set_data: Option<Element<B, S>>,
@@ -839,9 +1052,12 @@ pub struct CMapSelectionSet<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapStaticOverlay<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
     pub bakelighting: bool,
     pub bakelightoutput: Option<c_int>,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "cubeMapName")]
     pub cube_map_name: S,
@@ -914,11 +1130,17 @@ pub struct CMapStaticOverlay<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapTile<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "alignToAxis")]
     pub align_to_axis: c_int,
     pub angles: Qangle,
     #[serde(rename = "baseSize")]
     pub base_size: Vector2,
+    #[serde(
+        with = "crate::serde::skip_null",
+        bound(serialize = "B: Serialize", deserialize = "B: Deserialize<'de>")
+    )]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "cornerPropertyValues0")]
     pub corner_property_values0: Vec<S>,
@@ -972,9 +1194,12 @@ pub struct CMapTile<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapTileMesh<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
     pub bakelighting: bool,
     pub bakelightoutput: Option<c_int>,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "cubeMapName")]
     pub cube_map_name: S,
@@ -1041,7 +1266,10 @@ pub struct CMapTileMesh<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapTileSet<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "convexityAngle")]
     pub convexity_angle: c_float,
@@ -1071,12 +1299,16 @@ pub struct CMapTileSet<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapVariableChoice<S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "m_ChoiceValues")]
     pub choice_values: Vec<S>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapVariableChoiceGroup<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "m_ActiveValue")]
     pub active_value: S,
     #[serde(rename = "m_ChoiceVariables")]
@@ -1087,6 +1319,8 @@ pub struct CMapVariableChoiceGroup<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapVariableSet<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "m_ChoiceGroups")]
     pub choice_groups: Vec<Element<B, S>>,
     #[serde(rename = "variableEditorOverrides")]
@@ -1107,7 +1341,10 @@ pub struct CMapVariableSet<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CMapWorld<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "connectionsData")]
     pub connections_data: Vec<Element<B, S>>,
@@ -1139,24 +1376,32 @@ pub struct CMapWorld<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CObjectSelectionSetDataElement<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "selectedObjects")]
     pub selected_objects: Vec<Element<B, S>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CStoredCamera {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub lookat: Vector3,
     pub position: Vector3,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CStoredCameras<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub activecamera: c_int,
     pub cameras: Vec<Element<B, S>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CTileSetMaterialSet<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "grassParams")]
     pub grass_params: Option<Element<B, S>>,
     #[serde(rename = "materialNames")]
@@ -1165,6 +1410,8 @@ pub struct CTileSetMaterialSet<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CTileSetProperty<S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "componentTypeFlags")]
     pub component_type_flags: c_int,
     #[serde(rename = "defaultValue")]
@@ -1183,7 +1430,10 @@ pub struct CTileSetProperty<S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CTrajectoryPath<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "connectionsData")]
     pub connections_data: Vec<Element<B, S>>,
@@ -1230,9 +1480,12 @@ pub struct CTrajectoryPath<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CTrajectoryPathNode<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
     #[serde(rename = "autoTime")]
     pub auto_time: bool,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "connectionsData")]
     pub connections_data: Vec<Element<B, S>>,
@@ -1286,7 +1539,10 @@ pub struct CTrajectoryPathNode<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CVisibilityMgr<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub angles: Qangle,
+    #[serde(with = "crate::serde::skip_null")]
     pub children: Vec<Element<B, S>>,
     #[serde(rename = "editorOnly")]
     pub editor_only: bool,
@@ -1310,6 +1566,8 @@ pub struct CVisibilityMgr<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DmElement<B, S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "CanPhysPull")]
     pub can_phys_pull: Option<c_int>,
     #[serde(rename = "DoNotDrop")]
@@ -1335,6 +1593,8 @@ pub struct DmElement<B, S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DmeConnectionData<S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     pub delay: c_float,
     #[serde(rename = "inputName")]
     pub input_name: S,
@@ -1352,6 +1612,8 @@ pub struct DmeConnectionData<S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DmePlugList<S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "dataTypes")]
     pub data_types: Vec<c_int>,
     pub descriptions: Vec<S>,
@@ -1362,6 +1624,8 @@ pub struct DmePlugList<S> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DmeVertexData<S> {
+    #[serde(rename = "$id")]
+    pub id: Option<Guid>,
     #[serde(rename = "PerVertexLighting")]
     pub per_vertex_lighting: Option<Vec<Vector4>>,
     #[serde(rename = "PerVertexLightingIndices")]
@@ -1,5 +1,8 @@
 //! A library for the Valve Data Model eXchange (DMX) format in Rust
-use std::io::BufRead;
+use std::{
+    io::{BufRead, Write},
+    ops::Deref,
+};
 
 use anyhow::Result;
 
@@ -7,10 +10,12 @@ pub mod dmx;
 pub mod formats;
 mod read;
 pub mod serde;
+mod write;
 
 use crate::{
     dmx::File,
     read::{Readable, Slice},
+    write::Writable,
 };
 
 /// Read a DMX file from an in-memory buffer, returns a borrowed version of the [dmx::File] struct
@@ -22,3 +27,23 @@ pub fn from_slice(reader: &[u8]) -> Result<File<&[u8], &str>> {
 pub fn from_reader(mut reader: impl BufRead) -> Result<File<Vec<u8>, String>> {
     File::read(&mut reader)
 }
+
+/// Write a DMX file in the binary encoding, version 9
+pub fn to_writer<B, S>(file: &File<B, S>, mut writer: impl Write) -> Result<()>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    file.write(&mut writer)
+}
+
+/// Write a DMX file in the binary encoding, version 9, to an in-memory buffer
+pub fn to_vec<B, S>(file: &File<B, S>) -> Result<Vec<u8>>
+where
+    B: Deref<Target = [u8]>,
+    S: Deref<Target = str>,
+{
+    let mut buffer = Vec::new();
+    to_writer(file, &mut buffer)?;
+    Ok(buffer)
+}
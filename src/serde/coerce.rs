@@ -0,0 +1,87 @@
+//! Parsing of numbers and booleans from strings for [Options::coerce](super::Options::coerce)
+//!
+//! Strings are parsed like Hammer does with `atoi` and `atof`: leading
+//! whitespace is skipped, the longest prefix forming a number is read and the
+//! rest of the string is ignored, and strings not starting with a number
+//! read as 0.
+
+/// Parses the integer at the start of a string, saturating on overflow
+pub(super) fn parse_int(value: &str) -> i64 {
+    let value = value.trim_start();
+    let (negative, digits) = match value.as_bytes().first() {
+        Some(b'-') => (true, &value[1..]),
+        Some(b'+') => (false, &value[1..]),
+        _ => (false, value),
+    };
+
+    let mut result: i64 = 0;
+    for digit in digits.bytes().take_while(u8::is_ascii_digit) {
+        let digit = i64::from(digit - b'0');
+        result = if negative {
+            result.saturating_mul(10).saturating_sub(digit)
+        } else {
+            result.saturating_mul(10).saturating_add(digit)
+        };
+    }
+
+    result
+}
+
+/// Parses the floating point number at the start of a string
+pub(super) fn parse_float(value: &str) -> f64 {
+    let value = value.trim_start();
+    let bytes = value.as_bytes();
+    let digits = |start: usize| {
+        bytes[start.min(bytes.len())..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count()
+    };
+
+    let mut end = match bytes.first() {
+        Some(b'-') | Some(b'+') => 1,
+        _ => 0,
+    };
+
+    let integer = digits(end);
+    end += integer;
+
+    let mut fraction = 0;
+    if bytes.get(end) == Some(&b'.') {
+        fraction = digits(end + 1);
+        if integer > 0 || fraction > 0 {
+            end += 1 + fraction;
+        }
+    }
+
+    if integer == 0 && fraction == 0 {
+        return 0.0;
+    }
+
+    // The exponent is only part of the number if it has digits
+    if let Some(b'e') | Some(b'E') = bytes.get(end) {
+        let sign = match bytes.get(end + 1) {
+            Some(b'-') | Some(b'+') => 1,
+            _ => 0,
+        };
+        let exponent = digits(end + 1 + sign);
+        if exponent > 0 {
+            end += 1 + sign + exponent;
+        }
+    }
+
+    value[..end].parse().unwrap_or_default()
+}
+
+/// Parses a boolean as an integer, any value other than 0 being true, and
+/// also accepts `true` and `false`
+pub(super) fn parse_bool(value: &str) -> bool {
+    let trimmed = value.trim();
+    if trimmed.eq_ignore_ascii_case("true") {
+        true
+    } else if trimmed.eq_ignore_ascii_case("false") {
+        false
+    } else {
+        parse_int(value) != 0
+    }
+}
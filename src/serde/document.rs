@@ -0,0 +1,166 @@
+//! Deserialization of the whole file: the header, the prefix attributes and
+//! the root element
+use super::{
+    inline_string, BufferWrapper, Context, ElementDeserializer, Error, FileValues, PathSegment,
+    StringWrapper, Transient, ValueDeserializer,
+};
+use serde::{
+    de::{
+        value::BorrowedStrDeserializer, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess,
+        Visitor,
+    },
+    forward_to_deserialize_any, Deserialize,
+};
+use std::{fmt::Debug, os::raw::c_int};
+
+/// Name of the struct requested from the Deserializer by [Document]
+pub(crate) const DOCUMENT_TOKEN: &str = "$dmxparser::Document";
+
+const DOCUMENT_FIELDS: &[&str] = &["format_name", "format_version", "prefix", "root"];
+
+/// A file read by [from_file](super::from_file): the format of the file, its
+/// prefix attributes deserialized as `P`, and its root element deserialized
+/// as `T`
+///
+/// ```ignore
+/// let document: Document<CMapRootElement<_, _>, DmElement<_, _>> = from_file(&file)?;
+/// assert_eq!(document.format_name, "vmap");
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename = "$dmxparser::Document")]
+pub struct Document<T, P = ()> {
+    pub format_name: String,
+    pub format_version: c_int,
+    pub prefix: P,
+    pub root: T,
+}
+
+/// Yields the fields of a [Document]
+pub(crate) struct DocumentDeserializer<'de, 'c, B, S> {
+    pub(crate) context: &'c Context<'de, B, S>,
+    pub(crate) index: i32,
+    pub(crate) field: usize,
+}
+
+impl<'de, 'c, B, S: Debug> MapAccess<'de> for DocumentDeserializer<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match DOCUMENT_FIELDS.get(self.field) {
+            Some(field) => seed
+                .deserialize(BorrowedStrDeserializer::new(field))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let header = self.context.header;
+        self.field += 1;
+
+        match self.field {
+            1 => seed.deserialize(StringWrapper(&header.format_name).into_deserializer()),
+            2 => seed.deserialize(header.format_version.into_deserializer()),
+            3 => seed
+                .deserialize(PrefixDeserializer {
+                    context: self.context,
+                    attr: 0,
+                })
+                .map_err(|err| err.prepend(PathSegment::Attribute("prefix".to_string()))),
+            _ => seed.deserialize(ElementDeserializer {
+                context: self.context,
+                index: self.index,
+                item: false,
+            }),
+        }
+    }
+}
+
+/// Deserialize the prefix attributes of a file as a map
+struct PrefixDeserializer<'de, 'c, B, S> {
+    context: &'c Context<'de, B, S>,
+    attr: usize,
+}
+
+impl<'de, 'c, B, S: Debug> Deserializer<'de> for PrefixDeserializer<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(self)
+    }
+
+    /// The prefix is ignored by the default `()` type of [Document]
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, 'c, B, S: Debug> MapAccess<'de> for PrefixDeserializer<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.context.prefix.get(self.attr) {
+            Some((name, _)) => seed
+                .deserialize(StringWrapper(name).into_deserializer())
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let (name, value) = &self.context.prefix[self.attr];
+        self.attr += 1;
+
+        seed.deserialize(ValueDeserializer {
+            context: self.context,
+            value,
+            wrap: FileValues,
+            attribute: None,
+            string: inline_string,
+        })
+        .map_err(|err| {
+            let name = self.context.copy_string(name).unwrap_or_default();
+            err.with_types(None, value.kind())
+                .prepend(PathSegment::Attribute(name))
+        })
+    }
+}
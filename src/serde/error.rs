@@ -0,0 +1,107 @@
+//! Error type of the Deserializer and Serializer, locating the value the
+//! error occurred in
+use super::Guid;
+use crate::dmx::AttributeType;
+use std::fmt::{self, Display, Formatter};
+
+/// Error returned by [from_file](super::from_file) and [to_file](super::to_file)
+///
+/// Displayed as the path to the value followed by the message, as in
+/// `world.children[12]<CMapEntity "light_01">.entity_properties.brightness:
+/// expected Float, found String: invalid type: ...`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    /// Path from the root element to the value the error occurred in
+    pub path: Vec<PathSegment>,
+    /// Type of attribute the target type expected, if it is known
+    pub expected: Option<AttributeType>,
+    /// Type of the attribute the error occurred in
+    pub found: Option<AttributeType>,
+    pub message: String,
+}
+
+/// Part of the path of an [Error]
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    /// Attribute of an element (or field of a serialized struct)
+    Attribute(String),
+    /// Item of an array
+    Index(usize),
+    /// Element stored in an ElementArray, following its index
+    Element {
+        type_: String,
+        name: String,
+        guid: Guid,
+    },
+}
+
+impl Error {
+    /// Adds a segment to the start of the path, as the error goes up to the root
+    pub(crate) fn prepend(mut self, segment: PathSegment) -> Self {
+        self.path.insert(0, segment);
+        self
+    }
+
+    /// Sets the types of the value the error occurred in, unless the error
+    /// comes from a nested value
+    pub(crate) fn with_types(
+        mut self,
+        expected: Option<AttributeType>,
+        found: AttributeType,
+    ) -> Self {
+        if self.path.is_empty() && self.found.is_none() {
+            self.expected = expected;
+            self.found = Some(found);
+        }
+        self
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        for (index, segment) in self.path.iter().enumerate() {
+            match segment {
+                PathSegment::Attribute(name) if index == 0 => write!(fmt, "{}", name)?,
+                PathSegment::Attribute(name) => write!(fmt, ".{}", name)?,
+                PathSegment::Index(index) => write!(fmt, "[{}]", index)?,
+                PathSegment::Element { type_, name, .. } => write!(fmt, "<{} {:?}>", type_, name)?,
+            }
+        }
+
+        if !self.path.is_empty() {
+            write!(fmt, ": ")?;
+        }
+
+        match (self.expected, self.found) {
+            (Some(expected), Some(found)) => write!(
+                fmt,
+                "expected {}, found {}: ",
+                expected.name(),
+                found.name()
+            )?,
+            (None, Some(found)) => write!(fmt, "found {}: ", found.name())?,
+            _ => {}
+        }
+
+        write!(fmt, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error {
+            path: Vec::new(),
+            expected: None,
+            found: None,
+            message: msg.to_string(),
+        }
+    }
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        <Error as serde::de::Error>::custom(msg)
+    }
+}
@@ -0,0 +1,79 @@
+//! GUID of an element, as exposed to typed structs through the `$id` field
+use crate::dmx::{format_guid, parse_guid};
+use serde::{
+    de::{Error, SeqAccess, Unexpected, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// GUID of an element, deserialized from the reserved `$id` field of a struct
+///
+/// When writing a file with [to_file](super::to_file), the element gets this
+/// GUID instead of a random one, and values with the same GUID are written
+/// as a single element referenced multiple times.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Display for Guid {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&format_guid(&self.0))
+    }
+}
+
+/// Serialized in the registry format in human-readable formats, and as bytes otherwise
+impl Serialize for Guid {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&format_guid(&self.0))
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Guid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(GuidVisitor)
+        } else {
+            deserializer.deserialize_bytes(GuidVisitor)
+        }
+    }
+}
+
+struct GuidVisitor;
+
+impl<'de> Visitor<'de> for GuidVisitor {
+    type Value = Guid;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("a GUID")
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<Guid, E> {
+        parse_guid(value)
+            .map(Guid)
+            .ok_or_else(|| E::invalid_value(Unexpected::Str(value), &self))
+    }
+
+    fn visit_bytes<E: Error>(self, value: &[u8]) -> Result<Guid, E> {
+        let mut guid = [0; 16];
+        if value.len() != guid.len() {
+            return Err(E::invalid_length(value.len(), &self));
+        }
+
+        guid.copy_from_slice(value);
+        Ok(Guid(guid))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Guid, A::Error> {
+        let mut guid = [0; 16];
+        for (index, byte) in guid.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| Error::invalid_length(index, &self))?;
+        }
+
+        Ok(Guid(guid))
+    }
+}
@@ -0,0 +1,1873 @@
+//! Implementation of a serde [Deserializer] reading from a [File], and of a
+//! [Serializer](serde::Serializer) producing one
+//!
+//! Structs can declare a field named `$id` (with `#[serde(rename = "$id")]`)
+//! of type [Guid] to receive the GUID of the element they are deserialized
+//! from, the GUID is then preserved when the struct is written back with
+//! [to_file]. The fields named `$type` and `$name` receive the type and the
+//! name of the element in the same way, as strings.
+//!
+//! Similarly, a field named `$extra` of type [RawAttributes] receives the
+//! attributes that are not consumed by the other fields of the struct, with
+//! their exact types, and [to_file] writes them back as attributes.
+use crate::dmx::{
+    format_guid, AttributeType, AttributeValue, Body, Color, File, FileHeader, Header, Qangle,
+    Quaternion, StringRef, Time, Vector2, Vector3, Vector4, Vmatrix,
+};
+use serde::{
+    de::{
+        value::{BorrowedStrDeserializer, MapDeserializer, SeqDeserializer},
+        DeserializeSeed, Deserializer, EnumAccess, Error as _, IgnoredAny, IntoDeserializer,
+        MapAccess, SeqAccess, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize,
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    convert::TryInto,
+    fmt::{self, Debug, Formatter},
+    iter::Cloned,
+    ops::Deref,
+    os::raw::{c_char, c_float, c_int},
+    rc::Rc,
+    slice::Iter,
+    sync::atomic::{AtomicU64, Ordering},
+    thread::LocalKey,
+};
+
+mod coerce;
+mod document;
+mod error;
+mod guid;
+mod raw;
+pub mod reference;
+pub mod registry;
+mod ser;
+pub mod shared;
+pub mod skip_null;
+mod stream;
+
+pub use self::{
+    document::Document,
+    error::{Error, PathSegment},
+    guid::Guid,
+    raw::{RawAttributes, RawElement, RawValue},
+    reference::ElementRef,
+    registry::{Custom, Registry},
+    ser::to_file,
+    shared::Shared,
+    stream::{from_reader, from_reader_with},
+};
+pub(crate) use self::ser::to_file_modelled;
+use self::{
+    document::DocumentDeserializer,
+    raw::{RawAttributesDeserializer, RawValueDeserializer},
+    reference::FileRef,
+};
+
+/// Name of the reserved struct field receiving the GUID of an element
+pub(crate) const ID_FIELD: &str = "$id";
+/// Name of the reserved struct field receiving the attributes not consumed
+/// by the other fields
+pub(crate) const EXTRA_FIELD: &str = "$extra";
+/// Name of the reserved struct field receiving the type of an element
+pub(crate) const TYPE_FIELD: &str = "$type";
+/// Name of the reserved struct field receiving the name of an element
+pub(crate) const NAME_FIELD: &str = "$name";
+
+/// Reserved fields yielded before the attributes of an element, when the
+/// struct being deserialized has them
+const RESERVED_FIELDS: &[&str] = &[ID_FIELD, TYPE_FIELD, NAME_FIELD, EXTRA_FIELD];
+
+/// Identifier of the next from_file call, for the values cached by [shared]
+static NEXT_CALL: AtomicU64 = AtomicU64::new(0);
+
+/// Options of [from_file_with]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Options {
+    /// Fails if some attributes of the deserialized elements are not
+    /// consumed by the target type, the error lists all of them with the
+    /// type, name and GUID of their element
+    pub strict: bool,
+    /// Converts the scalar attributes to the type requested by the target
+    /// type when they differ, for the keys that are stored with different
+    /// types depending on the version of Hammer: integers to booleans (any
+    /// value other than 0 being true) and back, integers to floats, numbers
+    /// to strings, and strings to numbers and booleans, which are parsed
+    /// like Hammer does with `atoi` and `atof`
+    pub coerce: bool,
+}
+
+pub fn from_file<'de, B, S: Debug, T>(file: &'de File<B, S>) -> Result<T, Error>
+where
+    &'de File<B, S>: Into<FileRef<'de>>,
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    T: Deserialize<'de>,
+{
+    from_file_with(file, Options::default())
+}
+
+/// Same as [from_file], with options
+pub fn from_file_with<'de, B, S: Debug, T>(
+    file: &'de File<B, S>,
+    options: Options,
+) -> Result<T, Error>
+where
+    &'de File<B, S>: Into<FileRef<'de>>,
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    T: Deserialize<'de>,
+{
+    from_element(file, 0, options)
+}
+
+/// Deserializes an element of a file, the root element for [from_file]
+fn from_element<'de, B, S: Debug, T>(
+    file: &'de File<B, S>,
+    index: usize,
+    options: Options,
+) -> Result<T, Error>
+where
+    &'de File<B, S>: Into<FileRef<'de>>,
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    T: Deserialize<'de>,
+{
+    let context = Context::new(file, Some(file.into()), Box::new(&*file.bodies), options);
+    deserialize_element(context, index)
+}
+
+/// Deserializes an element with a context, and checks that all the
+/// attributes were consumed in strict mode
+fn deserialize_element<'de, B, S: Debug, T>(
+    context: Context<'de, B, S>,
+    index: usize,
+) -> Result<T, Error>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    T: Deserialize<'de>,
+{
+    // Elements other than the root are located in the path of the errors
+    let deserializer = ElementDeserializer {
+        context: &context,
+        index: index as i32,
+        item: index != 0,
+    };
+
+    let value = T::deserialize(deserializer);
+    shared::release(context.call);
+    let value = value?;
+
+    if context.options.strict {
+        let mut unconsumed = context.unconsumed.take();
+        if !unconsumed.is_empty() {
+            unconsumed.sort_unstable_by_key(|&(element, attribute, _)| (element, attribute));
+            unconsumed.dedup_by_key(|&mut (element, attribute, _)| (element, attribute));
+            return Err(context.unconsumed_error(&unconsumed));
+        }
+    }
+
+    Ok(value)
+}
+
+/// Sets a thread-local value for the duration of `f`, the previous value is
+/// restored when it returns or panics
+fn scoped<T: 'static, R>(key: &'static LocalKey<Cell<T>>, value: T, f: impl FnOnce() -> R) -> R {
+    struct Restore<T: 'static> {
+        key: &'static LocalKey<Cell<T>>,
+        previous: Option<T>,
+    }
+
+    impl<T> Drop for Restore<T> {
+        fn drop(&mut self) {
+            if let Some(previous) = self.previous.take() {
+                self.key.with(|cell| cell.set(previous));
+            }
+        }
+    }
+
+    let _restore = Restore {
+        key,
+        previous: Some(key.with(|cell| cell.replace(value))),
+    };
+    f()
+}
+
+/// Bodies of the elements being deserialized, either all in memory or read
+/// from a stream as they are needed
+trait Bodies<'de, B, S> {
+    /// Number of elements of the file
+    fn len(&self) -> usize;
+
+    fn body(&self, index: usize) -> Result<BodyRef<'de, B, S>, Error>;
+
+    /// Called once an element has been deserialized
+    fn release(&self, _index: usize) {}
+}
+
+impl<'de, B, S> Bodies<'de, B, S> for &'de [Body<B, S>] {
+    fn len(&self) -> usize {
+        <[_]>::len(self)
+    }
+
+    fn body(&self, index: usize) -> Result<BodyRef<'de, B, S>, Error> {
+        self.get(index)
+            .map(BodyRef::Borrowed)
+            .ok_or_else(|| Error::custom(format!("missing body of element {}", index)))
+    }
+}
+
+/// Body of an element, borrowed from the file or shared with the stream it
+/// is read from
+enum BodyRef<'de, B, S> {
+    Borrowed(&'de Body<B, S>),
+    Shared(Rc<Body<B, S>>),
+}
+
+impl<'de, B, S> Deref for BodyRef<'de, B, S> {
+    type Target = Body<B, S>;
+
+    fn deref(&self) -> &Body<B, S> {
+        match self {
+            BodyRef::Borrowed(body) => body,
+            BodyRef::Shared(body) => body,
+        }
+    }
+}
+
+/// Implementation detail: deserializes the values of the bodies shared with
+/// a stream, which only live as long as their element is being visited
+///
+/// Only the buffers of streamed files can be visited for a lifetime shorter
+/// than the deserialization, the other buffer types never have such bodies.
+#[doc(hidden)]
+pub trait Transient<'de, S>: Sized {
+    fn deserialize_value<T>(
+        value: TransientValue<'de, '_, Self, S>,
+        _seed: T,
+    ) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        Err(Error::custom(format!(
+            "the body of element {} is not borrowed from the file",
+            value.element
+        )))
+    }
+}
+
+impl<'de, S> Transient<'de, S> for &'de [u8] {}
+
+impl<'de, S> Transient<'de, S> for Vec<u8> {}
+
+/// Implementation detail: attribute value of a body shared with a stream,
+/// see [Transient]
+#[doc(hidden)]
+pub struct TransientValue<'de, 'c, B, S> {
+    context: &'c Context<'de, B, S>,
+    body: Rc<Body<B, S>>,
+    element: usize,
+    attr: usize,
+    /// Elements already reached by the RawAttributes the value is
+    /// deserialized for, none for the other target types
+    seen: Option<&'c RefCell<HashSet<usize>>>,
+}
+
+impl<'de, 'c, B, S: Debug> TransientValue<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    /// Deserializes the value while the body is borrowed, the buffers and
+    /// strings of the value are wrapped by `wrap`
+    fn deserialize<W, T>(self, wrap: W, seed: T) -> Result<T::Value, Error>
+    where
+        for<'b> W: Wrap<'de, 'b, B, S>,
+        T: DeserializeSeed<'de>,
+    {
+        let attr = &self.body.attributes[self.attr];
+        match self.seen {
+            Some(seen) => seed.deserialize(RawValueDeserializer {
+                context: self.context,
+                value: &attr.value,
+                wrap,
+                seen,
+            }),
+            None => seed.deserialize(ValueDeserializer {
+                context: self.context,
+                value: &attr.value,
+                wrap,
+                attribute: Some((self.element, self.attr, attr.name)),
+                string: table_string,
+            }),
+        }
+    }
+}
+
+/// File being deserialized, along with the options and the state shared by
+/// the deserializers
+struct Context<'de, B, S> {
+    /// File the registered [Custom] elements are decoded from and the
+    /// [ElementRef] values point to, none when the bodies are streamed
+    file: Option<FileRef<'de>>,
+    header: &'de FileHeader<S>,
+    prefix: &'de [(S, AttributeValue<B, S>)],
+    strings: &'de [S],
+    headers: &'de [Header],
+    bodies: Box<dyn Bodies<'de, B, S> + 'de>,
+    options: Options,
+    /// Registry in scope when the deserialization started
+    registry: Option<Rc<Registry>>,
+    /// Identifier of the from_file call
+    call: u64,
+    /// Attributes not consumed by the target type, as (element, attribute)
+    /// indices and name, only recorded in strict mode
+    unconsumed: RefCell<Vec<(usize, usize, StringRef)>>,
+    /// Elements being deserialized, from the root
+    stack: RefCell<Vec<usize>>,
+}
+
+impl<'de, B, S> Context<'de, B, S> {
+    fn new(
+        file: &'de File<B, S>,
+        file_ref: Option<FileRef<'de>>,
+        bodies: Box<dyn Bodies<'de, B, S> + 'de>,
+        options: Options,
+    ) -> Self {
+        Context {
+            file: file_ref,
+            header: &file.header,
+            prefix: &file.prefix,
+            strings: &file.strings,
+            headers: &file.headers,
+            bodies,
+            options,
+            registry: registry::current(),
+            call: NEXT_CALL.fetch_add(1, Ordering::Relaxed),
+            unconsumed: RefCell::new(Vec::new()),
+            stack: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl<'de, B, S> Context<'de, B, S>
+where
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    /// Copies a string of the string table
+    fn string(&self, index: StringRef) -> Option<String> {
+        self.copy_string(self.strings.get(index.index()?)?)
+    }
+
+    /// Copies a string of the file
+    fn copy_string(&self, value: &'de S) -> Option<String> {
+        copy_string(StringWrapper(value))
+    }
+
+    /// Deserializes the attributes of an element, fails instead of recursing
+    /// endlessly if the element is already being deserialized
+    fn visit_element<T>(
+        &self,
+        index: usize,
+        visit: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if self.stack.borrow().contains(&index) {
+            let header = &self.headers[index];
+            return Err(Error::custom(format!(
+                "cyclic reference to element {} {:?} {}",
+                self.string(header.type_).unwrap_or_default(),
+                self.string(header.name).unwrap_or_default(),
+                format_guid(&header.guid),
+            )));
+        }
+
+        self.stack.borrow_mut().push(index);
+        let result = visit();
+        self.stack.borrow_mut().pop();
+        self.bodies.release(index);
+        result
+    }
+
+    /// Segment of the path of errors identifying an element
+    fn element_segment(&self, index: usize) -> PathSegment {
+        let header = &self.headers[index];
+        PathSegment::Element {
+            type_: self.string(header.type_).unwrap_or_default(),
+            name: self.string(header.name).unwrap_or_default(),
+            guid: Guid(header.guid),
+        }
+    }
+}
+
+impl<'de, B, S: Debug> Context<'de, B, S> {
+    fn unconsumed_error(&self, unconsumed: &[(usize, usize, StringRef)]) -> Error {
+        let string = |index: StringRef| {
+            index
+                .index()
+                .and_then(|index| self.strings.get(index))
+                .map(|string| format!("{:?}", string))
+                .unwrap_or_else(|| "null".to_string())
+        };
+
+        let mut message = format!(
+            "{} attributes are not consumed by the target type:",
+            unconsumed.len()
+        );
+        for &(element, _, name) in unconsumed {
+            let header = &self.headers[element];
+            message += &format!(
+                "\n- {} {} {}: {}",
+                string(header.type_),
+                string(header.name),
+                format_guid(&header.guid),
+                string(name),
+            );
+        }
+
+        Error::custom(message)
+    }
+}
+
+/// Deserialize a single element (header + body) from a file
+struct ElementDeserializer<'de, 'c, B, S> {
+    context: &'c Context<'de, B, S>,
+    index: i32,
+    /// Whether the element is an item of an ElementArray, which adds it to
+    /// the path of the errors
+    item: bool,
+}
+
+impl<'de, 'c, B, S> ElementDeserializer<'de, 'c, B, S>
+where
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    fn locate(&self, err: Error) -> Error {
+        match self.index.try_into() {
+            Ok(index) if self.item && index < self.context.headers.len() => {
+                err.prepend(self.context.element_segment(index))
+            }
+            _ => err,
+        }
+    }
+
+    /// Index of the element, none for null references (-1), fails for the
+    /// references to elements the file doesn't have
+    fn element(&self) -> Result<Option<usize>, Error> {
+        match self.index.try_into() {
+            Ok(index) if index < self.context.headers.len() => Ok(Some(index)),
+            Ok(_) => Err(Error::custom(format!(
+                "reference to element {} of a file with {} elements",
+                self.index,
+                self.context.headers.len()
+            ))),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl<'de, 'c, B, S: Debug> Deserializer<'de> for ElementDeserializer<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(index) = self.element()? {
+            self.context
+                .visit_element(index, || {
+                    visitor.visit_map(AttributesDeserializer {
+                        context: self.context,
+                        index,
+                        attr: 0,
+                        fields: &[],
+                        reserved: RESERVED_FIELDS.len(),
+                        pending: None,
+                    })
+                })
+                .map_err(|err| self.locate(err))
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if name == document::DOCUMENT_TOKEN {
+            visitor.visit_map(DocumentDeserializer {
+                context: self.context,
+                index: self.index,
+                field: 0,
+            })
+        } else if let Some(index) = self.element()? {
+            self.context
+                .visit_element(index, || {
+                    visitor.visit_map(AttributesDeserializer {
+                        context: self.context,
+                        index,
+                        attr: 0,
+                        fields,
+                        reserved: 0,
+                        pending: None,
+                    })
+                })
+                .map_err(|err| self.locate(err))
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(index) = self.element()? {
+            if variants.contains(&registry::CUSTOM_VARIANT) {
+                let type_ = self.context.string(self.context.headers[index].type_);
+                let custom = match (&self.context.registry, type_) {
+                    (Some(registry), Some(type_)) => registry.handles(&type_, variants),
+                    _ => false,
+                };
+
+                if custom {
+                    let variant = CustomVariant(ElementDeserializer {
+                        context: self.context,
+                        index: self.index,
+                        item: false,
+                    });
+
+                    return visitor.visit_enum(variant).map_err(|err| self.locate(err));
+                }
+            }
+
+            let variant = ElementDeserializer {
+                context: self.context,
+                index: self.index,
+                item: false,
+            };
+
+            visitor.visit_enum(variant).map_err(|err| self.locate(err))
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if self.element()?.is_some() {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    /// Shared values receive the identity of the element before its content,
+    /// and references only receive its identity
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.element()? {
+            Some(index) if name == reference::REFERENCE_TOKEN => {
+                let context = self.context;
+                let file = context.file.ok_or_else(|| {
+                    self.locate(Error::custom(
+                        "an ElementRef cannot be deserialized by from_reader, the bodies of the elements are not kept",
+                    ))
+                })?;
+                reference::provide(file, index, context.options, |headers| {
+                    visitor.visit_borrowed_bytes(headers)
+                })
+            }
+            Some(index) if name == registry::CUSTOM_TOKEN => {
+                let context = self.context;
+                let type_ = context
+                    .string(context.headers[index].type_)
+                    .unwrap_or_default();
+                let raw = || {
+                    RawElement::deserialize(ElementDeserializer {
+                        context,
+                        index: self.index,
+                        item: false,
+                    })
+                };
+
+                let custom = match &context.registry {
+                    Some(registry) => {
+                        registry.decode(&type_, context.file, index, context.options, raw)
+                    }
+                    None => Err(Error::custom("no Registry is in scope")),
+                }?;
+                registry::provide(custom, || visitor.visit_unit())
+            }
+            Some(index) if name == shared::SHARED_TOKEN => visitor.visit_seq(SharedDeserializer {
+                call: Some(self.context.call),
+                index: Some(index as u64),
+                element: Some(self),
+            }),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map identifier ignored_any
+    }
+}
+
+// Enum values are not directly supported by the DMX format, but tagged enums are
+// used for fields that can deserialize to multiple type (with the variant name being
+// the AttributeType name, or the type of the element for Element values). String
+// values are deserialized as unit variants instead, when the enum has no String
+// variant
+impl<'de, 'c, B, S: Debug> EnumAccess<'de> for ElementDeserializer<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let index = self
+            .element()?
+            .ok_or_else(|| Error::custom("expected an element, found a null reference"))?;
+        let head: &Header = &self.context.headers[index];
+
+        let value = seed.deserialize(StringDeserializer {
+            strings: self.context.strings,
+            index: head.type_,
+        })?;
+        Ok((value, self))
+    }
+}
+
+impl<'de, 'c, B, S: Debug> VariantAccess<'de> for ElementDeserializer<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    /// The value is ignored, and reported in strict mode
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.deserialize_ignored_any(IgnoredAny).map(|_| ())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_struct("", fields, visitor)
+    }
+}
+
+/// Yields the `$custom` variant for the elements decoded by the [Registry]
+/// in scope, the decoded element is then provided by the
+/// deserialize_newtype_struct of the element
+struct CustomVariant<'de, 'c, B, S>(ElementDeserializer<'de, 'c, B, S>);
+
+impl<'de, 'c, B, S: Debug> EnumAccess<'de> for CustomVariant<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let value = seed.deserialize(BorrowedStrDeserializer::new(registry::CUSTOM_VARIANT))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de, 'c, B, S: Debug> VariantAccess<'de> for CustomVariant<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Err(Error::custom(
+            "the $custom variant must be a newtype variant",
+        ))
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.0)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::custom(
+            "the $custom variant must be a newtype variant",
+        ))
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::custom(
+            "the $custom variant must be a newtype variant",
+        ))
+    }
+}
+
+/// Provides the from_file call and the index of an element before the
+/// element to [shared]
+struct SharedDeserializer<'de, 'c, B, S> {
+    call: Option<u64>,
+    index: Option<u64>,
+    element: Option<ElementDeserializer<'de, 'c, B, S>>,
+}
+
+impl<'de, 'c, B, S: Debug> SeqAccess<'de> for SharedDeserializer<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if let Some(call) = self.call.take() {
+            seed.deserialize(call.into_deserializer()).map(Some)
+        } else if let Some(index) = self.index.take() {
+            seed.deserialize(index.into_deserializer()).map(Some)
+        } else if let Some(element) = self.element.take() {
+            seed.deserialize(element).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// String of the string table at an index, fails for the references to
+/// strings the file doesn't have
+fn string<S>(strings: &[S], index: usize) -> Result<&S, Error> {
+    strings.get(index).ok_or_else(|| {
+        Error::custom(format!(
+            "reference to string {} of a file with {} strings",
+            index,
+            strings.len()
+        ))
+    })
+}
+
+/// Deserialize a string from an index in the string table
+struct StringDeserializer<'de, S> {
+    strings: &'de [S],
+    index: StringRef,
+}
+
+impl<'de, S> Deserializer<'de> for StringDeserializer<'de, S>
+where
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(index) = self.index.index() {
+            StringWrapper(string(self.strings, index)?)
+                .into_deserializer()
+                .deserialize_any(visitor)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if self.index.index().is_some() {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Deserialize an Attribute
+struct AttributesDeserializer<'de, 'c, B, S> {
+    context: &'c Context<'de, B, S>,
+    index: usize,
+    attr: usize,
+    /// Fields of the struct being deserialized, empty for maps
+    fields: &'static [&'static str],
+    /// Position in RESERVED_FIELDS of the next reserved field to look for
+    reserved: usize,
+    /// Reserved field whose value is to be yielded next
+    pending: Option<&'static str>,
+}
+
+impl<'de, 'c, B, S> AttributesDeserializer<'de, 'c, B, S> {
+    /// Whether the attributes that are not fields go to a `$extra` field
+    /// instead of being yielded
+    fn has_extra(&self) -> bool {
+        self.fields.contains(&EXTRA_FIELD)
+    }
+}
+
+impl<'de, 'c, B, S: Debug> MapAccess<'de> for AttributesDeserializer<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        while let Some(field) = RESERVED_FIELDS.get(self.reserved) {
+            self.reserved += 1;
+            if self.fields.contains(field) {
+                self.pending = Some(field);
+                return seed
+                    .deserialize(BorrowedStrDeserializer::new(field))
+                    .map(Some);
+            }
+        }
+
+        let body = self.context.bodies.body(self.index)?;
+
+        while let Some(attr) = body.attributes.get(self.attr) {
+            let index = match attr.name.index() {
+                Some(index) => index,
+                None => return Ok(None),
+            };
+
+            let value = string(self.context.strings, index)?;
+            if self.has_extra() && !is_field(value, self.fields) {
+                self.attr += 1;
+                continue;
+            }
+
+            return seed
+                .deserialize(StringWrapper(value).into_deserializer())
+                .map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.pending.take() {
+            Some(ID_FIELD) => {
+                return seed.deserialize(GuidDeserializer(&self.context.headers[self.index].guid));
+            }
+            Some(field @ TYPE_FIELD) | Some(field @ NAME_FIELD) => {
+                let header = &self.context.headers[self.index];
+                return seed
+                    .deserialize(StringDeserializer {
+                        strings: self.context.strings,
+                        index: if field == TYPE_FIELD {
+                            header.type_
+                        } else {
+                            header.name
+                        },
+                    })
+                    .map_err(|err| err.prepend(PathSegment::Attribute(field.to_string())));
+            }
+            Some(_) => {
+                // The element itself is captured as a reference if reached again
+                let seen = RefCell::new(HashSet::new());
+                seen.borrow_mut().insert(self.index);
+
+                return seed
+                    .deserialize(RawAttributesDeserializer {
+                        context: self.context,
+                        index: self.index,
+                        fields: self.fields,
+                        seen: &seen,
+                        empty: false,
+                    })
+                    .map_err(|err| err.prepend(PathSegment::Attribute(EXTRA_FIELD.to_string())));
+            }
+            None => {}
+        }
+
+        let body = self.context.bodies.body(self.index)?;
+        let attr = self.attr;
+        let (name, kind) = match body.attributes.get(attr) {
+            Some(attr) => (attr.name, attr.value.kind()),
+            None => return Err(Error::custom("next_value_seed called before next_key_seed")),
+        };
+        self.attr += 1;
+
+        let result = match body {
+            BodyRef::Borrowed(body) => seed.deserialize(ValueDeserializer {
+                context: self.context,
+                value: &body.attributes[attr].value,
+                wrap: FileValues,
+                attribute: Some((self.index, attr, name)),
+                string: table_string,
+            }),
+            BodyRef::Shared(body) => {
+                let value = TransientValue {
+                    context: self.context,
+                    body,
+                    element: self.index,
+                    attr,
+                    seen: None,
+                };
+                B::deserialize_value(value, seed)
+            }
+        };
+
+        result.map_err(|err| {
+            let name = self.context.string(name).unwrap_or_default();
+            err.with_types(None, kind)
+                .prepend(PathSegment::Attribute(name))
+        })
+    }
+}
+
+/// Implements deserialization methods forwarding to deserialize_any, with the
+/// attribute type they expect
+macro_rules! deserialize_expecting {
+    ($($method:ident => $kind:ident,)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Error>
+            where
+                V: Visitor<'de>,
+            {
+                self.expecting(Some(AttributeType::$kind), visitor)
+            }
+        )*
+    };
+}
+
+/// Wraps the buffers and strings of the values borrowed for `'b` into
+/// deserializers
+trait Wrap<'de, 'b, B, S>: Copy {
+    type Buffer: IntoDeserializer<'de, Error>;
+    type String: IntoDeserializer<'de, Error>;
+
+    fn buffer(self, value: &'b B) -> Self::Buffer;
+
+    fn string(self, value: &'b S) -> Self::String;
+}
+
+/// Values borrowed from the file for the whole deserialization
+#[derive(Clone, Copy)]
+struct FileValues;
+
+impl<'de, B: 'de, S: 'de> Wrap<'de, 'de, B, S> for FileValues
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Buffer = BufferWrapper<'de, B>;
+    type String = StringWrapper<'de, S>;
+
+    fn buffer(self, value: &'de B) -> Self::Buffer {
+        BufferWrapper(value)
+    }
+
+    fn string(self, value: &'de S) -> Self::String {
+        StringWrapper(value)
+    }
+}
+
+/// Deserialize an AttributeValue
+///
+/// The value is borrowed for `'b`, which is shorter than `'de` for the
+/// bodies shared with a stream.
+struct ValueDeserializer<'de, 'b, 'c, B, S, W, R = StringRef> {
+    context: &'c Context<'de, B, S>,
+    value: &'b AttributeValue<B, S, R>,
+    /// Wraps the buffers and strings of the value
+    wrap: W,
+    /// Element and attribute indices and name of the value, to report it if
+    /// the target type ignores it
+    attribute: Option<(usize, usize, StringRef)>,
+    /// Resolves the String values, [table_string] or [inline_string]
+    string: fn(&'b [S], &'b R) -> Option<&'b S>,
+}
+
+/// Strings of the element attributes are references into the string table
+fn table_string<'b, S>(strings: &'b [S], index: &'b StringRef) -> Option<&'b S> {
+    strings.get(index.index()?)
+}
+
+/// Strings of the prefix attributes are stored inline
+fn inline_string<'b, S>(_strings: &'b [S], value: &'b S) -> Option<&'b S> {
+    Some(value)
+}
+
+impl<'de: 'b, 'b, 'c, B, S: Debug, W, R> Deserializer<'de>
+    for ValueDeserializer<'de, 'b, 'c, B, S, W, R>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    W: Wrap<'de, 'b, B, S>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            AttributeValue::Element(index) => {
+                let deserializer = ElementDeserializer {
+                    context: self.context,
+                    index: *index,
+                    item: false,
+                };
+
+                deserializer.deserialize_any(visitor)
+            }
+
+            AttributeValue::Bool(value) => visitor.visit_bool(*value),
+            AttributeValue::Uint8(value) => visitor.visit_u8(*value),
+            AttributeValue::Int(value) => visitor.visit_i32(*value),
+            AttributeValue::Uint64(value) => visitor.visit_u64(*value),
+            AttributeValue::Float(value) => visitor.visit_f32(*value),
+
+            AttributeValue::String(value) => {
+                if let Some(value) = (self.string)(self.context.strings, value) {
+                    self.wrap
+                        .string(value)
+                        .into_deserializer()
+                        .deserialize_any(visitor)
+                } else {
+                    visitor.visit_none()
+                }
+            }
+
+            AttributeValue::Binary(value) => self
+                .wrap
+                .buffer(value)
+                .into_deserializer()
+                .deserialize_any(visitor),
+
+            AttributeValue::Time(value) => value.into_deserializer().deserialize_any(visitor),
+            AttributeValue::Color(value) => value.into_deserializer().deserialize_any(visitor),
+            AttributeValue::Vector2(value) => value.into_deserializer().deserialize_any(visitor),
+            AttributeValue::Vector3(value) => value.into_deserializer().deserialize_any(visitor),
+            AttributeValue::Vector4(value) => value.into_deserializer().deserialize_any(visitor),
+            AttributeValue::Qangle(value) => value.into_deserializer().deserialize_any(visitor),
+            AttributeValue::Quaternion(value) => value.into_deserializer().deserialize_any(visitor),
+            AttributeValue::Vmatrix(value) => value.into_deserializer().deserialize_any(visitor),
+
+            AttributeValue::ElementArray(value) => {
+                let items = value.iter().map(|index| ElementWrapper {
+                    context: self.context,
+                    index: *index,
+                });
+                visit_array(items, visitor)
+            }
+            AttributeValue::IntArray(value) => visit_array(value.iter().cloned(), visitor),
+            AttributeValue::FloatArray(value) => visit_array(value.iter().cloned(), visitor),
+            AttributeValue::BoolArray(value) => visit_array(value.iter().cloned(), visitor),
+            AttributeValue::StringArray(value) => {
+                visit_array(value.iter().map(|value| self.wrap.string(value)), visitor)
+            }
+            AttributeValue::BinaryArray(value) => {
+                visit_array(value.iter().map(|value| self.wrap.buffer(value)), visitor)
+            }
+            AttributeValue::TimeArray(value) => visit_array(value.iter(), visitor),
+            AttributeValue::ColorArray(value) => visit_array(value.iter(), visitor),
+            AttributeValue::Vector2Array(value) => visit_array(value.iter(), visitor),
+            AttributeValue::Vector3Array(value) => visit_array(value.iter(), visitor),
+            AttributeValue::Vector4Array(value) => visit_array(value.iter(), visitor),
+            AttributeValue::QangleArray(value) => visit_array(value.iter(), visitor),
+            AttributeValue::QuaternionArray(value) => visit_array(value.iter(), visitor),
+            AttributeValue::VmatrixArray(value) => visit_array(value.iter(), visitor),
+            AttributeValue::Uint64Array(value) => visit_array(value.iter().cloned(), visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            AttributeValue::String(value) => {
+                if (self.string)(self.context.strings, value).is_some() {
+                    visitor.visit_some(self)
+                } else {
+                    visitor.visit_none()
+                }
+            }
+            AttributeValue::Element(index) => {
+                let deserializer = ElementDeserializer {
+                    context: self.context,
+                    index: *index,
+                    item: false,
+                };
+
+                deserializer.deserialize_option(visitor)
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let AttributeValue::Element(index) = self.value {
+            let deserializer = ElementDeserializer {
+                context: self.context,
+                index: *index,
+                item: false,
+            };
+
+            deserializer.deserialize_struct(name, fields, visitor)
+        } else {
+            self.expecting(AttributeType::from_name(name), visitor)
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let AttributeValue::Element(index) = self.value {
+            let deserializer = ElementDeserializer {
+                context: self.context,
+                index: *index,
+                item: false,
+            };
+
+            deserializer.deserialize_enum(name, variants, visitor)
+        } else {
+            match self.value {
+                AttributeValue::String(value)
+                    if !variants.contains(&AttributeType::String.name()) =>
+                {
+                    if let Some(value) = (self.string)(self.context.strings, value) {
+                        self.wrap
+                            .string(value)
+                            .into_deserializer()
+                            .deserialize_enum(name, variants, visitor)
+                    } else {
+                        visitor.visit_none()
+                    }
+                }
+                _ => visitor.visit_enum(self),
+            }
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let AttributeValue::Element(index) = self.value {
+            let deserializer = ElementDeserializer {
+                context: self.context,
+                index: *index,
+                item: false,
+            };
+
+            deserializer.deserialize_newtype_struct(name, visitor)
+        } else {
+            self.expecting(AttributeType::from_name(name), visitor)
+        }
+    }
+
+    deserialize_expecting! {
+        deserialize_bool => Bool,
+        deserialize_i8 => Int,
+        deserialize_i16 => Int,
+        deserialize_i32 => Int,
+        deserialize_i64 => Int,
+        deserialize_u8 => Uint8,
+        deserialize_u16 => Int,
+        deserialize_u32 => Int,
+        deserialize_u64 => Uint64,
+        deserialize_f32 => Float,
+        deserialize_f64 => Float,
+        deserialize_char => String,
+        deserialize_str => String,
+        deserialize_string => String,
+        deserialize_bytes => Binary,
+        deserialize_byte_buf => Binary,
+    }
+
+    /// Ignored values are not visited, and are reported in strict mode
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(attribute) = self.attribute {
+            if self.context.options.strict {
+                self.context.unconsumed.borrow_mut().push(attribute);
+            }
+        }
+
+        visitor.visit_unit()
+    }
+
+    /// Math types are deserialized as sequences of their components, instead
+    /// of maps, and binary values as sequences of u8 instead of bytes
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            AttributeValue::Time(value) => value.into_deserializer().deserialize_seq(visitor),
+            AttributeValue::Color(value) => value.into_deserializer().deserialize_seq(visitor),
+            AttributeValue::Vector2(value) => value.into_deserializer().deserialize_seq(visitor),
+            AttributeValue::Vector3(value) => value.into_deserializer().deserialize_seq(visitor),
+            AttributeValue::Vector4(value) => value.into_deserializer().deserialize_seq(visitor),
+            AttributeValue::Qangle(value) => value.into_deserializer().deserialize_seq(visitor),
+            AttributeValue::Quaternion(value) => value.into_deserializer().deserialize_seq(visitor),
+            AttributeValue::Binary(value) => self
+                .wrap
+                .buffer(value)
+                .into_deserializer()
+                .deserialize_seq(visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 unit unit_struct map identifier
+    }
+}
+
+impl<'de: 'b, 'b, 'c, B, S: Debug, W, R> ValueDeserializer<'de, 'b, 'c, B, S, W, R>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    W: Wrap<'de, 'b, B, S>,
+{
+    /// Deserializes the value, recording the type expected by the target
+    /// type in the errors
+    fn expecting<V>(self, expected: Option<AttributeType>, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let found = self.value.kind();
+        let result = match expected {
+            Some(expected) if self.context.options.coerce && expected != found => {
+                self.coerce(expected, visitor)
+            }
+            _ => self.deserialize_any(visitor),
+        };
+
+        result.map_err(|err| err.with_types(expected, found))
+    }
+
+    /// Converts a scalar value to the type expected by the target type, see
+    /// [Options::coerce]
+    fn coerce<V>(self, expected: AttributeType, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        use AttributeType::*;
+
+        let integer = match *self.value {
+            AttributeValue::Int(value) => Some(i128::from(value)),
+            AttributeValue::Uint8(value) => Some(i128::from(value)),
+            AttributeValue::Uint64(value) => Some(i128::from(value)),
+            _ => None,
+        };
+        let string = match self.value {
+            AttributeValue::String(value) => (self.string)(self.context.strings, value)
+                .and_then(|value| copy_string(self.wrap.string(value))),
+            _ => None,
+        };
+
+        match (expected, self.value, integer, string) {
+            (Bool, _, Some(value), _) => visitor.visit_bool(value != 0),
+            (Bool, _, _, Some(value)) => visitor.visit_bool(coerce::parse_bool(&value)),
+
+            (Int | Uint8 | Uint64, AttributeValue::Bool(value), _, _) => {
+                visitor.visit_u8(*value as u8)
+            }
+            (Int | Uint8 | Uint64, _, _, Some(value)) => {
+                visitor.visit_i64(coerce::parse_int(&value))
+            }
+
+            (Float, _, Some(value), _) => visitor.visit_f64(value as f64),
+            (Float, _, _, Some(value)) => visitor.visit_f64(coerce::parse_float(&value)),
+
+            (String, _, Some(value), _) => visitor.visit_string(value.to_string()),
+            (String, AttributeValue::Float(value), _, _) => visitor.visit_string(value.to_string()),
+
+            _ => self.deserialize_any(visitor),
+        }
+    }
+}
+
+impl<'de: 'b, 'b, 'c, B, S: Debug, W, R> EnumAccess<'de>
+    for ValueDeserializer<'de, 'b, 'c, B, S, W, R>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    W: Wrap<'de, 'b, B, S>,
+{
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        Ok((
+            seed.deserialize(BorrowedStrDeserializer::new(self.value.kind().name()))?,
+            self,
+        ))
+    }
+}
+
+impl<'de: 'b, 'b, 'c, B, S: Debug, W, R> VariantAccess<'de>
+    for ValueDeserializer<'de, 'b, 'c, B, S, W, R>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    W: Wrap<'de, 'b, B, S>,
+{
+    type Error = Error;
+
+    /// The value is ignored, and reported in strict mode
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.deserialize_ignored_any(IgnoredAny).map(|_| ())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_struct("", fields, visitor)
+    }
+}
+
+/// Deserialize the GUID of an element
+struct GuidDeserializer<'de>(&'de [u8; 16]);
+
+impl<'de> Deserializer<'de> for GuidDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(self.0)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Visits the items of an array, locating the errors at their index
+fn visit_array<'de, I, V>(items: I, visitor: V) -> Result<V::Value, Error>
+where
+    I: Iterator,
+    I::Item: IntoDeserializer<'de, Error>,
+    V: Visitor<'de>,
+{
+    let mut seq = ArrayDeserializer { items, index: 0 };
+    let value = visitor.visit_seq(&mut seq)?;
+
+    match seq.items.count() {
+        0 => Ok(value),
+        remaining => Err(Error::invalid_length(
+            seq.index + remaining,
+            &format!("{} items", seq.index).as_str(),
+        )),
+    }
+}
+
+struct ArrayDeserializer<I> {
+    items: I,
+    index: usize,
+}
+
+impl<'de, I> SeqAccess<'de> for ArrayDeserializer<I>
+where
+    I: Iterator,
+    I::Item: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let item = match self.items.next() {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+
+        let index = self.index;
+        self.index += 1;
+
+        seed.deserialize(item.into_deserializer())
+            .map(Some)
+            .map_err(|err| err.prepend(PathSegment::Index(index)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self.items.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(lower),
+            _ => None,
+        }
+    }
+}
+
+/// Copies a string of the file
+fn copy_string<'de>(value: impl IntoDeserializer<'de, Error>) -> Option<String> {
+    struct StringVisitor;
+
+    impl<'de> Visitor<'de> for StringVisitor {
+        type Value = String;
+
+        fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+            f.write_str("a string")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+    }
+
+    value
+        .into_deserializer()
+        .deserialize_str(StringVisitor)
+        .ok()
+}
+
+/// Whether an attribute name is one of the fields of a struct
+fn is_field<'de, S>(name: &'de S, fields: &'static [&'static str]) -> bool
+where
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    struct FieldVisitor(&'static [&'static str]);
+
+    impl<'de> Visitor<'de> for FieldVisitor {
+        type Value = bool;
+
+        fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+            f.write_str("an attribute name")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<bool, E> {
+            Ok(self.0.contains(&value))
+        }
+    }
+
+    StringWrapper(name)
+        .into_deserializer()
+        .deserialize_str(FieldVisitor(fields))
+        .unwrap_or(false)
+}
+
+/// IntoDeserializer implementation for ElementDeserializer
+struct ElementWrapper<'de, 'c, B, S> {
+    context: &'c Context<'de, B, S>,
+    index: i32,
+}
+
+impl<'de, 'c, B, S: Debug> IntoDeserializer<'de, Error> for ElementWrapper<'de, 'c, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Deserializer = ElementDeserializer<'de, 'c, B, S>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ElementDeserializer {
+            context: self.context,
+            index: self.index,
+            item: true,
+        }
+    }
+}
+
+/// Implementation detail: provides byte array deserialization borrowing from
+/// the buffers of the input file, whether it is owned or borrowed
+#[doc(hidden)]
+pub struct BufferWrapper<'de, T>(&'de T);
+
+impl<'de> IntoDeserializer<'de, Error> for BufferWrapper<'de, &'de [u8]> {
+    type Deserializer = BufferDeserializer<'de>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        BufferDeserializer(self.0)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for BufferWrapper<'de, Vec<u8>> {
+    type Deserializer = BufferDeserializer<'de>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        BufferDeserializer(self.0)
+    }
+}
+
+/// Implementation detail: deserializes a buffer as borrowed bytes, or as a
+/// sequence of u8 for the types that expect one such as `Vec<u8>`
+#[doc(hidden)]
+pub struct BufferDeserializer<'de>(&'de [u8]);
+
+impl<'de> Deserializer<'de> for BufferDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(self.0)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        SeqDeserializer::new(self.0.iter().cloned()).deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+/// Implementation detail: provides string deserialization borrowing from the
+/// string table of the input file, whether it is owned or borrowed
+#[doc(hidden)]
+pub struct StringWrapper<'de, T>(&'de T);
+
+impl<'de> IntoDeserializer<'de, Error> for StringWrapper<'de, &'de str> {
+    type Deserializer = BorrowedStrDeserializer<'de, Error>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        BorrowedStrDeserializer::new(self.0)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for StringWrapper<'de, String> {
+    type Deserializer = BorrowedStrDeserializer<'de, Error>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        BorrowedStrDeserializer::new(self.0)
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for &'b Time {
+    type Deserializer = ComponentsDeserializer<c_int>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ComponentsDeserializer::new(&["millis"], vec![self.millis])
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for &'b Color {
+    type Deserializer = ComponentsDeserializer<c_char>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ComponentsDeserializer::new(&["r", "g", "b", "a"], vec![self.r, self.g, self.b, self.a])
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for &'b Vector2 {
+    type Deserializer = ComponentsDeserializer<c_float>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ComponentsDeserializer::new(&["x", "y"], vec![self.x, self.y])
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for &'b Vector3 {
+    type Deserializer = ComponentsDeserializer<c_float>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ComponentsDeserializer::new(&["x", "y", "z"], vec![self.x, self.y, self.z])
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for &'b Vector4 {
+    type Deserializer = ComponentsDeserializer<c_float>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ComponentsDeserializer::new(&["x", "y", "z", "w"], vec![self.x, self.y, self.z, self.w])
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for &'b Qangle {
+    type Deserializer = ComponentsDeserializer<c_float>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ComponentsDeserializer::new(
+            &["pitch", "yaw", "roll"],
+            vec![self.pitch, self.yaw, self.roll],
+        )
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for &'b Quaternion {
+    type Deserializer = ComponentsDeserializer<c_float>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ComponentsDeserializer::new(&["x", "y", "z", "w"], vec![self.x, self.y, self.z, self.w])
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for &'b Vmatrix {
+    type Deserializer = SeqDeserializer<Cloned<Iter<'b, f32>>, Error>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        SeqDeserializer::new(self.0.iter().cloned())
+    }
+}
+
+/// Implementation detail: deserializes a math type as a map of its named
+/// components (the default), or as a sequence of its components for tuples,
+/// arrays and the types of other math libraries
+#[doc(hidden)]
+pub struct ComponentsDeserializer<T> {
+    names: &'static [&'static str],
+    values: Vec<T>,
+}
+
+impl<T> ComponentsDeserializer<T> {
+    fn new(names: &'static [&'static str], values: Vec<T>) -> Self {
+        ComponentsDeserializer { names, values }
+    }
+}
+
+impl<'de, T> Deserializer<'de> for ComponentsDeserializer<T>
+where
+    T: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let components = self.names.iter().cloned().zip(self.values);
+        MapDeserializer::new(components).deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        SeqDeserializer::new(self.values.into_iter()).deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct enum identifier
+        ignored_any
+    }
+}
@@ -0,0 +1,588 @@
+//! Untyped representation of the attributes of an element, used to capture
+//! the attributes that are not modelled by a struct
+use super::{
+    is_field, table_string, BodyRef, BufferWrapper, Context, Error, FileValues, Guid,
+    GuidDeserializer, StringDeserializer, StringWrapper, Transient, TransientValue,
+    ValueDeserializer, Wrap, EXTRA_FIELD, ID_FIELD, NAME_FIELD, TYPE_FIELD,
+};
+use crate::dmx::{
+    AttributeValue, Color, Qangle, Quaternion, StringRef, Time, Vector2, Vector3, Vector4, Vmatrix,
+};
+use serde::{
+    de::{
+        value::{BorrowedStrDeserializer, SeqDeserializer},
+        DeserializeSeed, Deserializer, EnumAccess, Error as _, IntoDeserializer, MapAccess,
+        SeqAccess, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any,
+    ser::SerializeMap,
+    Deserialize, Serialize, Serializer,
+};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    convert::TryInto,
+    fmt::{self, Debug, Formatter},
+    os::raw::{c_float, c_int},
+};
+
+/// Attributes of an element with their exact [AttributeType](crate::dmx::AttributeType),
+/// in the order they are stored in
+///
+/// A struct field named `$extra` of this type receives all the attributes
+/// of the element that are not consumed by the other fields of the struct,
+/// and [to_file](super::to_file) writes them back as attributes of the
+/// element:
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct CMapEntity {
+///     origin: Vector3,
+///     #[serde(rename = "$extra", default)]
+///     extra: RawAttributes,
+/// }
+/// ```
+///
+/// `#[serde(flatten)]` cannot be used for this purpose, since serde buffers
+/// the flattened values in a representation that does not keep their type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawAttributes(pub Vec<(String, RawValue)>);
+
+/// Serialized as a map of attribute names to values
+impl Serialize for RawAttributes {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for RawAttributes {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(RawAttributesVisitor)
+    }
+}
+
+struct RawAttributesVisitor;
+
+impl<'de> Visitor<'de> for RawAttributesVisitor {
+    type Value = RawAttributes;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("a map of attributes")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawAttributes, A::Error> {
+        let mut attributes = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some(entry) = map.next_entry()? {
+            attributes.push(entry);
+        }
+        Ok(RawAttributes(attributes))
+    }
+}
+
+/// An element referenced by a [RawValue]
+///
+/// An element referenced multiple times within the same [RawAttributes]
+/// (including through cycles) only has its attributes the first time it is
+/// reached, the other references have empty attributes. Since
+/// [to_file](super::to_file) writes the values with the same `$id` as a
+/// single element, these references are written back as they were read.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RawElement {
+    #[serde(rename = "$id")]
+    pub id: Guid,
+    #[serde(rename = "$type")]
+    pub type_: String,
+    #[serde(rename = "$name")]
+    pub name: String,
+    #[serde(rename = "$extra")]
+    pub attributes: RawAttributes,
+}
+
+/// Value of an attribute in a [RawAttributes], the variants are named after
+/// the [AttributeType](crate::dmx::AttributeType) they store
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RawValue {
+    Element(Option<RawElement>),
+    Int(c_int),
+    Float(c_float),
+    Bool(bool),
+    String(String),
+    Binary(#[serde(with = "binary")] Vec<u8>),
+    Time(Time),
+    Color(Color),
+    Vector2(Vector2),
+    Vector3(Vector3),
+    Vector4(Vector4),
+    Qangle(Qangle),
+    Quaternion(Quaternion),
+    Vmatrix(Vmatrix),
+    Uint64(u64),
+    Uint8(u8),
+
+    ElementArray(Vec<Option<RawElement>>),
+    IntArray(Vec<c_int>),
+    FloatArray(Vec<c_float>),
+    BoolArray(Vec<bool>),
+    StringArray(Vec<String>),
+    BinaryArray(#[serde(with = "binary_array")] Vec<Vec<u8>>),
+    TimeArray(Vec<Time>),
+    ColorArray(Vec<Color>),
+    Vector2Array(Vec<Vector2>),
+    Vector3Array(Vec<Vector3>),
+    Vector4Array(Vec<Vector4>),
+    QangleArray(Vec<Qangle>),
+    QuaternionArray(Vec<Quaternion>),
+    VmatrixArray(Vec<Vmatrix>),
+    Uint64Array(Vec<u64>),
+}
+
+/// Binary values are serialized as bytes instead of a sequence of u8
+mod binary {
+    use super::{ByteBuf, Bytes};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<Z: Serializer>(value: &[u8], serializer: Z) -> Result<Z::Ok, Z::Error> {
+        Bytes(value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        ByteBuf::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+mod binary_array {
+    use super::{ByteBuf, Bytes};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<Z: Serializer>(values: &[Vec<u8>], serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(values.iter().map(|value| Bytes(value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        let values = Vec::<ByteBuf>::deserialize(deserializer)?;
+        Ok(values.into_iter().map(|value| value.0).collect())
+    }
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for Bytes<'a> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+struct ByteBufVisitor;
+
+impl<'de> Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("binary data")
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, value: &[u8]) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(value.to_vec()))
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, value: Vec<u8>) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ByteBuf, A::Error> {
+        let mut value = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            value.push(byte);
+        }
+        Ok(ByteBuf(value))
+    }
+}
+
+/// Deserialize the attributes of an element that are not named in `fields`
+/// as a map of [RawValue]
+pub(super) struct RawAttributesDeserializer<'de, 's, B, S> {
+    pub(super) context: &'s Context<'de, B, S>,
+    pub(super) index: usize,
+    pub(super) fields: &'static [&'static str],
+    /// Elements already reached from the RawAttributes being deserialized
+    pub(super) seen: &'s RefCell<HashSet<usize>>,
+    /// Yields no attributes, for elements that were already reached
+    pub(super) empty: bool,
+}
+
+impl<'de, 's, B, S: Debug> Deserializer<'de> for RawAttributesDeserializer<'de, 's, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(RawAttributesAccess {
+            attr: if self.empty {
+                self.context.bodies.body(self.index)?.attributes.len()
+            } else {
+                0
+            },
+            value: None,
+            inner: self,
+        })
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct RawAttributesAccess<'de, 's, B, S> {
+    inner: RawAttributesDeserializer<'de, 's, B, S>,
+    attr: usize,
+    /// Index of the attribute whose name was yielded last
+    value: Option<usize>,
+}
+
+impl<'de, 's, B, S: Debug> MapAccess<'de> for RawAttributesAccess<'de, 's, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let inner = &self.inner;
+        let body = inner.context.bodies.body(inner.index)?;
+
+        while let Some(attr) = body.attributes.get(self.attr) {
+            self.attr += 1;
+
+            let name = match attr
+                .name
+                .index()
+                .and_then(|index| inner.context.strings.get(index))
+            {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if is_field(name, inner.fields) {
+                continue;
+            }
+
+            self.value = Some(self.attr - 1);
+            return seed
+                .deserialize(StringWrapper(name).into_deserializer())
+                .map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let attr = self
+            .value
+            .take()
+            .ok_or_else(|| Error::custom("next_value_seed called before next_key_seed"))?;
+
+        let inner = &self.inner;
+        match inner.context.bodies.body(inner.index)? {
+            BodyRef::Borrowed(body) => seed.deserialize(RawValueDeserializer {
+                context: inner.context,
+                value: &body.attributes[attr].value,
+                wrap: FileValues,
+                seen: inner.seen,
+            }),
+            BodyRef::Shared(body) => {
+                let value = TransientValue {
+                    context: inner.context,
+                    body,
+                    element: inner.index,
+                    attr,
+                    seen: Some(inner.seen),
+                };
+                B::deserialize_value(value, seed)
+            }
+        }
+    }
+}
+
+/// Deserialize an AttributeValue as an enum whose variant is its type, the
+/// value is borrowed for `'b` like for a [ValueDeserializer]
+pub(super) struct RawValueDeserializer<'de, 'b, 's, B, S, W> {
+    pub(super) context: &'s Context<'de, B, S>,
+    pub(super) value: &'b AttributeValue<B, S, StringRef>,
+    pub(super) wrap: W,
+    pub(super) seen: &'s RefCell<HashSet<usize>>,
+}
+
+impl<'de: 'b, 'b, 's, B, S: Debug, W> Deserializer<'de>
+    for RawValueDeserializer<'de, 'b, 's, B, S, W>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    W: Wrap<'de, 'b, B, S>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_enum(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de: 'b, 'b, 's, B, S: Debug, W> EnumAccess<'de> for RawValueDeserializer<'de, 'b, 's, B, S, W>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    W: Wrap<'de, 'b, B, S>,
+{
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(BorrowedStrDeserializer::new(self.value.kind().name()))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de: 'b, 'b, 's, B, S: Debug, W> VariantAccess<'de>
+    for RawValueDeserializer<'de, 'b, 's, B, S, W>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+    W: Wrap<'de, 'b, B, S>,
+{
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Err(Error::custom("expected a newtype variant for a raw value"))
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let element = |index: c_int| RawElementDeserializer {
+            context: self.context,
+            index,
+            seen: self.seen,
+        };
+
+        match self.value {
+            AttributeValue::Element(index) => seed.deserialize(element(*index)),
+            AttributeValue::ElementArray(indices) => seed.deserialize(SeqDeserializer::new(
+                indices.iter().map(|index| element(*index)),
+            )),
+            value => seed.deserialize(ValueDeserializer {
+                context: self.context,
+                value,
+                wrap: self.wrap,
+                attribute: None,
+                string: table_string,
+            }),
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::custom("expected a newtype variant for a raw value"))
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::custom("expected a newtype variant for a raw value"))
+    }
+}
+
+/// Deserialize an element as a [RawElement], null and out of range indices
+/// are deserialized as none
+struct RawElementDeserializer<'de, 's, B, S> {
+    context: &'s Context<'de, B, S>,
+    index: c_int,
+    seen: &'s RefCell<HashSet<usize>>,
+}
+
+impl<'de, 's, B, S> RawElementDeserializer<'de, 's, B, S> {
+    fn index(&self) -> Option<usize> {
+        let index: usize = self.index.try_into().ok()?;
+        if index < self.context.headers.len() && index < self.context.bodies.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'de, 's, B, S: Debug> Deserializer<'de> for RawElementDeserializer<'de, 's, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let index = match self.index() {
+            Some(index) => index,
+            None => return visitor.visit_none(),
+        };
+
+        let first = self.seen.borrow_mut().insert(index);
+        visitor.visit_map(RawElementAccess {
+            inner: self,
+            index,
+            first,
+            field: 0,
+        })
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if self.index().is_some() {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, 's, B, S: Debug> IntoDeserializer<'de, Error> for RawElementDeserializer<'de, 's, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Fields of a [RawElement], in the order they are yielded
+const RAW_ELEMENT_FIELDS: &[&str] = &[ID_FIELD, TYPE_FIELD, NAME_FIELD, EXTRA_FIELD];
+
+struct RawElementAccess<'de, 's, B, S> {
+    inner: RawElementDeserializer<'de, 's, B, S>,
+    index: usize,
+    /// Whether this is the first time the element is reached
+    first: bool,
+    field: usize,
+}
+
+impl<'de, 's, B, S: Debug> MapAccess<'de> for RawElementAccess<'de, 's, B, S>
+where
+    BufferWrapper<'de, B>: IntoDeserializer<'de, Error>,
+    B: Transient<'de, S>,
+    StringWrapper<'de, S>: IntoDeserializer<'de, Error>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match RAW_ELEMENT_FIELDS.get(self.field) {
+            Some(field) => seed
+                .deserialize(BorrowedStrDeserializer::new(field))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let inner = &self.inner;
+        let header = &inner.context.headers[self.index];
+
+        let field = self.field;
+        self.field += 1;
+
+        match field {
+            0 => seed.deserialize(GuidDeserializer(&header.guid)),
+            1 => seed.deserialize(StringDeserializer {
+                strings: inner.context.strings,
+                index: header.type_,
+            }),
+            2 => seed.deserialize(StringDeserializer {
+                strings: inner.context.strings,
+                index: header.name,
+            }),
+            _ => seed.deserialize(RawAttributesDeserializer {
+                context: inner.context,
+                index: self.index,
+                fields: &[],
+                seen: inner.seen,
+                empty: !self.first,
+            }),
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Lazy references to elements, deserialized when they are accessed
+//!
+//! An [ElementRef] field only records the file and the index of the element
+//! it points to. The element is deserialized by [ElementRef::get], each time
+//! it is called, which keeps large sub-trees out of the way until they are
+//! needed:
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct CMapMesh<'a> {
+//!     #[serde(rename = "meshData", borrow)]
+//!     mesh_data: ElementRef<'a, CDmePolygonMesh<&'a [u8], &'a str>>,
+//! }
+//!
+//! let mesh: CMapMesh = from_file(&file)?;
+//! let mesh_data = mesh.mesh_data.get()?;
+//! ```
+//!
+//! An ElementRef borrows from the file it was deserialized from, so it cannot
+//! outlive it:
+//!
+//! ```compile_fail
+//! use dmxparser::{from_reader, serde::{from_file, ElementRef}};
+//!
+//! fn reference(bytes: &[u8]) -> ElementRef<'static, ()> {
+//!     let file = from_reader(bytes).unwrap();
+//!     from_file(&file).unwrap()
+//! }
+//! ```
+//!
+//! Only the Deserializer of this crate can produce an ElementRef, other
+//! deserializers fail with an error.
+use super::{from_element, scoped, Error, Guid, Options};
+use crate::dmx::{File, Header};
+use serde::{de::Visitor, Deserialize, Deserializer};
+use std::{
+    cell::Cell,
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    mem, ptr, slice,
+};
+
+/// Name of the newtype struct requested from the Deserializer, which
+/// provides the element through [provide]
+pub(crate) const REFERENCE_TOKEN: &str = "$dmxparser::ElementRef";
+
+/// Implementation detail: one of the two types of [File] the Deserializer
+/// can read the elements of [Custom](super::Custom) values and of
+/// [ElementRef] values from
+#[doc(hidden)]
+#[derive(Copy, Clone)]
+pub enum FileRef<'de> {
+    Borrowed(&'de File<&'de [u8], &'de str>),
+    Owned(&'de File<Vec<u8>, String>),
+}
+
+impl<'de> FileRef<'de> {
+    fn headers(self) -> &'de [Header] {
+        match self {
+            FileRef::Borrowed(file) => &file.headers,
+            FileRef::Owned(file) => &file.headers,
+        }
+    }
+}
+
+impl<'de> From<&'de File<&'de [u8], &'de str>> for FileRef<'de> {
+    fn from(file: &'de File<&'de [u8], &'de str>) -> Self {
+        FileRef::Borrowed(file)
+    }
+}
+
+impl<'de> From<&'de File<Vec<u8>, String>> for FileRef<'de> {
+    fn from(file: &'de File<Vec<u8>, String>) -> Self {
+        FileRef::Owned(file)
+    }
+}
+
+/// [FileRef] without its lifetime, to be kept in [TARGET]
+#[derive(Copy, Clone)]
+enum RawFile {
+    Borrowed(*const File<&'static [u8], &'static str>),
+    Owned(*const File<Vec<u8>, String>),
+}
+
+/// Element being provided to the visitor of an ElementRef
+#[derive(Copy, Clone)]
+struct Target {
+    file: RawFile,
+    /// Header bytes of the file, the visitor is handed them back with the
+    /// lifetime of the file
+    headers: *const [u8],
+    index: usize,
+    options: Options,
+}
+
+thread_local! {
+    /// Element provided by the Deserializer for the duration of a visit
+    static TARGET: Cell<Option<Target>> = const { Cell::new(None) };
+}
+
+// The headers are plain integers, there is no padding between or within them
+const _: () = assert!(
+    mem::size_of::<Header>()
+        == 2 * mem::size_of::<crate::dmx::StringRef>() + mem::size_of::<[u8; 16]>()
+);
+
+/// Bytes of the element headers of a file
+///
+/// Nothing else exposes them, so a borrow of these bytes can only come from a
+/// borrow of the file they are read from.
+fn header_bytes(headers: &[Header]) -> &[u8] {
+    // SAFETY: the headers are initialized integers without padding, see the
+    // assertion above, and the bytes are borrowed as long as the headers
+    unsafe { slice::from_raw_parts(headers.as_ptr().cast(), mem::size_of_val(headers)) }
+}
+
+/// Makes an element of `file` available to the ElementRef being visited by
+/// `visit`, which receives the header bytes the visitor checks the element
+/// against
+pub(crate) fn provide<'de, R>(
+    file: FileRef<'de>,
+    index: usize,
+    options: Options,
+    visit: impl FnOnce(&'de [u8]) -> R,
+) -> R {
+    let headers = header_bytes(file.headers());
+    let raw = match file {
+        FileRef::Borrowed(file) => RawFile::Borrowed(file as *const _ as *const _),
+        FileRef::Owned(file) => RawFile::Owned(file),
+    };
+    let target = Target {
+        file: raw,
+        headers,
+        index,
+        options,
+    };
+    scoped(&TARGET, Some(target), || visit(headers))
+}
+
+/// Reference to an element of a [File], see the [module
+/// documentation](self)
+pub struct ElementRef<'de, T> {
+    file: FileRef<'de>,
+    index: usize,
+    options: Options,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'de, T> ElementRef<'de, T> {
+    /// Index of the element in the file
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// GUID of the element
+    pub fn id(&self) -> Guid {
+        Guid(self.file.headers()[self.index].guid)
+    }
+
+    /// Deserializes the element from the file the reference was deserialized
+    /// from, with the options of the from_file call that produced it
+    pub fn get(&self) -> Result<T, Error>
+    where
+        T: Deserialize<'de>,
+    {
+        match self.file {
+            FileRef::Borrowed(file) => from_element(file, self.index, self.options),
+            FileRef::Owned(file) => from_element(file, self.index, self.options),
+        }
+    }
+}
+
+impl<'de, T> Clone for ElementRef<'de, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'de, T> Copy for ElementRef<'de, T> {}
+
+impl<'de, T> Debug for ElementRef<'de, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ElementRef")
+            .field("index", &self.index)
+            .field("id", &self.id())
+            .finish()
+    }
+}
+
+impl<'de: 'a, 'a, T> Deserialize<'de> for ElementRef<'a, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(REFERENCE_TOKEN, ElementRefVisitor(PhantomData))
+    }
+}
+
+struct ElementRefVisitor<T>(PhantomData<T>);
+
+impl<'de: 'a, 'a, T> Visitor<'de> for ElementRefVisitor<ElementRef<'a, T>> {
+    type Value = ElementRef<'a, T>;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("an element")
+    }
+
+    /// The Deserializer of this crate provides the element before visiting
+    /// with the header bytes of its file, which are borrowed for as long as
+    /// the file is
+    fn visit_borrowed_bytes<E: serde::de::Error>(
+        self,
+        headers: &'de [u8],
+    ) -> Result<Self::Value, E> {
+        match TARGET.with(|target| target.take()) {
+            Some(target) if ptr::eq(target.headers, headers) => {
+                // SAFETY: the file lends its header bytes for 'de, so it is
+                // borrowed for 'de as well
+                let file = unsafe {
+                    match target.file {
+                        RawFile::Borrowed(file) => FileRef::Borrowed(&*(file as *const _)),
+                        RawFile::Owned(file) => FileRef::Owned(&*file),
+                    }
+                };
+                Ok(ElementRef {
+                    file,
+                    index: target.index,
+                    options: target.options,
+                    marker: PhantomData,
+                })
+            }
+            _ => Err(E::custom(
+                "an ElementRef can only be deserialized by dmxparser::serde::from_file",
+            )),
+        }
+    }
+}
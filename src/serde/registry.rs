@@ -0,0 +1,275 @@
+//! Decoders for the element types that a data structure doesn't model
+//!
+//! An enum deserialized from elements picks its variant from the type of the
+//! element, so an element type it has no variant for fails the whole
+//! deserialization. A [Registry] maps DMX element type names to the Rust
+//! types they are decoded as, and can have a fallback handler for the other
+//! types. While the registry is in [scope](Registry::scope), the enums that
+//! have a [Custom] variant named `$custom` receive these elements in it:
+//!
+//! ```ignore
+//! #[derive(Deserialize, Serialize)]
+//! enum ElementType {
+//!     CMapEntity(CMapEntity),
+//!     #[serde(rename = "$custom")]
+//!     Custom(Custom),
+//! }
+//!
+//! let mut registry = Registry::new();
+//! registry
+//!     .register::<MyProxy>("CMapMyProxy")
+//!     .fallback(|element: RawElement| Ok(element));
+//!
+//! let root: ElementType = registry.scope(|| from_file(&file))?;
+//! ```
+//!
+//! Registered types take precedence over the variants of the enum, while the
+//! fallback handler only receives the elements the enum has no variant for.
+//! Registered types are deserialized from the file as with
+//! [ElementRef::get](super::ElementRef::get), so they are not supported by
+//! [from_reader](super::from_reader).
+use super::{
+    from_element, from_file, reference::FileRef, scoped, to_file, Error, Options, RawElement,
+};
+use serde::{de::Visitor, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    any::Any,
+    cell::Cell,
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    rc::Rc,
+};
+
+/// Name of the enum variant receiving the custom elements
+pub(crate) const CUSTOM_VARIANT: &str = "$custom";
+
+/// Name of the newtype struct requested from the Deserializer, which
+/// provides the decoded element through [provide]
+pub(crate) const CUSTOM_TOKEN: &str = "$dmxparser::Custom";
+
+type Decoder = Rc<dyn Fn(FileRef, usize, Options) -> Result<Box<dyn Value>, Error>>;
+type Fallback = Rc<dyn Fn(RawElement) -> Result<Box<dyn Value>, Error>>;
+
+/// Decoders of element types, see the [module documentation](self)
+#[derive(Clone, Default)]
+pub struct Registry {
+    decoders: HashMap<String, Decoder>,
+    fallback: Option<Fallback>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the elements of type `type_` as `T`
+    pub fn register<T>(&mut self, type_: &str) -> &mut Self
+    where
+        T: for<'de> Deserialize<'de> + Serialize + Debug + 'static,
+    {
+        let decoder = |file: FileRef, index, options| {
+            let value: T = match file {
+                FileRef::Borrowed(file) => from_element(file, index, options)?,
+                FileRef::Owned(file) => from_element(file, index, options)?,
+            };
+            Ok(Box::new(value) as Box<dyn Value>)
+        };
+
+        self.decoders.insert(type_.to_string(), Rc::new(decoder));
+        self
+    }
+
+    /// Decodes the elements of the types that are neither registered nor
+    /// modelled by the enum with `handler`, which receives their attributes
+    pub fn fallback<T, F>(&mut self, handler: F) -> &mut Self
+    where
+        T: Serialize + Debug + 'static,
+        F: Fn(RawElement) -> Result<T, Error> + 'static,
+    {
+        self.fallback = Some(Rc::new(move |element| {
+            handler(element).map(|value| Box::new(value) as Box<dyn Value>)
+        }));
+        self
+    }
+
+    /// Makes the registry available to the from_file calls made by `f`, on
+    /// the current thread
+    ///
+    /// Each call uses the registry in scope when it starts, a nested scope
+    /// replaces it until it ends.
+    pub fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        scoped(&CURRENT, Some(Rc::new(self.clone())), f)
+    }
+
+    /// Whether the elements of type `type_` are decoded by the registry, when
+    /// the enum they are deserialized to has the variants `variants`
+    pub(crate) fn handles(&self, type_: &str, variants: &[&str]) -> bool {
+        self.decoders.contains_key(type_) || (self.fallback.is_some() && !variants.contains(&type_))
+    }
+
+    /// Decodes an element with the decoder registered for its type, or the
+    /// fallback handler, which receives the element from `raw`
+    pub(crate) fn decode(
+        &self,
+        type_: &str,
+        file: Option<FileRef>,
+        index: usize,
+        options: Options,
+        raw: impl FnOnce() -> Result<RawElement, Error>,
+    ) -> Result<Custom, Error> {
+        let value = match (self.decoders.get(type_), &self.fallback) {
+            (Some(decoder), _) => {
+                let file = file.ok_or_else(|| {
+                    Error::custom(format!(
+                        "the registered {} elements cannot be deserialized by from_reader",
+                        type_
+                    ))
+                })?;
+                decoder(file, index, options)?
+            }
+            (None, Some(fallback)) => fallback(raw()?)?,
+            (None, None) => {
+                return Err(Error::custom(format!(
+                    "no decoder is registered for {} elements",
+                    type_
+                )))
+            }
+        };
+
+        Ok(Custom {
+            type_: type_.to_string(),
+            value,
+        })
+    }
+}
+
+impl Debug for Registry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("types", &self.decoders.keys().collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+thread_local! {
+    /// Registry in scope on the current thread
+    static CURRENT: Cell<Option<Rc<Registry>>> = const { Cell::new(None) };
+}
+
+/// Registry in scope, if any
+pub(crate) fn current() -> Option<Rc<Registry>> {
+    CURRENT.with(|current| {
+        let registry = current.take();
+        current.set(registry.clone());
+        registry
+    })
+}
+
+/// Value decoded by a [Registry]
+trait Value: Debug {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+    /// Converts the value to a RawElement, to be written back
+    fn to_raw(&self) -> Result<RawElement, Error>;
+}
+
+impl<T: Serialize + Debug + 'static> Value for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn to_raw(&self) -> Result<RawElement, Error> {
+        from_file(&to_file(self, "", 0)?)
+    }
+}
+
+/// Element decoded by a [Registry], see the [module documentation](self)
+///
+/// Serializing it writes an element of the same type, with the attributes
+/// serialized from the decoded value.
+#[derive(Debug)]
+pub struct Custom {
+    type_: String,
+    value: Box<dyn Value>,
+}
+
+impl Custom {
+    /// Type of the element
+    pub fn type_(&self) -> &str {
+        &self.type_
+    }
+
+    pub fn is<T: Any>(&self) -> bool {
+        self.value.as_any().is::<T>()
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.as_any().downcast_ref()
+    }
+
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.value.as_any_mut().downcast_mut()
+    }
+
+    pub fn downcast<T: Any>(self) -> Result<T, Self> {
+        if self.is::<T>() {
+            match self.value.into_any().downcast() {
+                Ok(value) => Ok(*value),
+                Err(_) => unreachable!(),
+            }
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl Serialize for Custom {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut element = self.value.to_raw().map_err(Z::Error::custom)?;
+        element.type_ = self.type_.clone();
+        element.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Custom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(CUSTOM_TOKEN, CustomVisitor)
+    }
+}
+
+thread_local! {
+    /// Element provided by the Deserializer for the duration of a visit
+    static DECODED: Cell<Option<Custom>> = const { Cell::new(None) };
+}
+
+/// Makes a decoded element available to the Custom being visited
+pub(crate) fn provide<R>(custom: Custom, visit: impl FnOnce() -> R) -> R {
+    scoped(&DECODED, Some(custom), visit)
+}
+
+struct CustomVisitor;
+
+impl<'de> Visitor<'de> for CustomVisitor {
+    type Value = Custom;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("an element")
+    }
+
+    /// The Deserializer of this crate provides the element before visiting
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Custom, E> {
+        DECODED.with(|decoded| decoded.take()).ok_or_else(|| {
+            E::custom("a Custom element can only be deserialized by dmxparser::serde::from_file")
+        })
+    }
+}
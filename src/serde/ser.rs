@@ -0,0 +1,990 @@
+//! Implementation of a serde [Serializer] producing a [File]
+use super::{Error, PathSegment, EXTRA_FIELD, ID_FIELD, NAME_FIELD, TYPE_FIELD};
+use crate::dmx::{
+    parse_guid, random_guid, Attribute, AttributeType, AttributeValue, Body, Color, File,
+    FileHeader, Header, Qangle, Quaternion, StringTable, Time, Vector2, Vector3, Vector4, Vmatrix,
+};
+use serde::ser::{
+    Error as _, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+    SerializeTuple, SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+use std::{
+    collections::{
+        hash_map::{Entry, HashMap, RandomState},
+        HashSet,
+    },
+    convert::{TryFrom, TryInto},
+    mem::take,
+    os::raw::{c_char, c_float, c_int},
+};
+
+/// Attribute value whose String values are not interned yet, so that strings
+/// only used in arrays are kept out of the string table
+type Value = AttributeValue<Vec<u8>, String, String>;
+
+/// Names of the structs serialized as a single attribute value instead of an element
+const VALUE_STRUCTS: &[&str] = &[
+    "Time",
+    "Color",
+    "Vector2",
+    "Vector3",
+    "Vector4",
+    "Qangle",
+    "Quaternion",
+];
+
+/// Serializes a value into a new [File] in the binary encoding, version 9
+///
+/// The value must serialize to an element, which becomes the root of the file:
+///
+/// - Structs and maps become elements, with their fields as attributes. The
+///   type of the element is the name of the struct, or the name of the
+///   variant for newtype variants (like the variants of
+///   [ElementType](crate::formats::vmap::ElementType)), and `DmElement` for
+///   maps.
+/// - Newtype variants named after an [AttributeType] (like the variants of
+///   [PolygonMeshStreamData](crate::formats::vmap::PolygonMeshStreamData))
+///   serialize their content as an attribute value, the name of the variant
+///   is used as the type of empty arrays.
+/// - The math types of the [dmx](crate::dmx) module become the matching
+///   attribute types, `u8` becomes `Uint8`, `u64` becomes `Uint64`, other
+///   integers become `Int` and floating point numbers become `Float`.
+/// - Sequences become arrays of the type of their items, sequences of `u8`
+///   become `Binary` values, empty sequences become empty `ElementArray`s
+///   unless their type is known from the variant name.
+/// - `None` and unit values are omitted from their element.
+/// - The entries of a `$extra` field (see [RawAttributes](super::RawAttributes))
+///   are added to the attributes of the element, and the `$type` and `$name`
+///   fields of a [RawElement](super::RawElement) set the type and name of the
+///   element.
+///
+/// Other elements get an empty name, and the GUID from its `$id` field (see
+/// [Guid](super::Guid)) or a new random GUID. Elements are written in the
+/// order they are serialized in, values with the same `$id` are written
+/// once and referenced from every place they appear in (the first one is
+/// kept), other values are written once for each reference.
+pub fn to_file<T>(
+    value: &T,
+    format_name: &str,
+    format_version: c_int,
+) -> Result<File<Vec<u8>, String>, Error>
+where
+    T: Serialize + ?Sized,
+{
+    to_file_modelled(value, format_name, format_version).map(|(file, _)| file)
+}
+
+/// Serializes a value like [to_file], also returning the attributes that the
+/// serialized values model
+pub(crate) fn to_file_modelled<T>(
+    value: &T,
+    format_name: &str,
+    format_version: c_int,
+) -> Result<(File<Vec<u8>, String>, Modelled), Error>
+where
+    T: Serialize + ?Sized,
+{
+    let mut builder = FileBuilder {
+        strings: StringTable::default(),
+        headers: Vec::new(),
+        bodies: Vec::new(),
+        fields: Vec::new(),
+        random: RandomState::new(),
+        guids: HashMap::new(),
+        orphans: false,
+    };
+
+    match value.serialize(ValueSerializer::new(&mut builder))? {
+        Some(AttributeValue::Element(0)) => {}
+        _ => return Err(Error::custom("the root value must serialize to an element")),
+    }
+
+    let mut modelled = Modelled {
+        elements: HashMap::with_capacity(builder.headers.len()),
+    };
+    for (header, fields) in builder.headers.iter().zip(builder.fields) {
+        modelled.elements.entry(header.guid).or_insert(fields);
+    }
+
+    let mut file = File {
+        header: FileHeader {
+            encoding_name: "binary".to_string(),
+            encoding_version: 9,
+            format_name: format_name.to_string(),
+            format_version,
+        },
+        prefix: Vec::new(),
+        strings: builder.strings.into_strings(),
+        headers: builder.headers,
+        bodies: builder.bodies,
+    };
+
+    // Drops the elements allocated for duplicate values and `$extra` fields
+    if builder.orphans {
+        file.compact().map_err(Error::custom)?;
+    }
+
+    Ok((file, modelled))
+}
+
+/// Attributes modelled by the values serialized by [to_file_modelled], by
+/// GUID of their element
+pub(crate) struct Modelled {
+    elements: HashMap<[u8; 16], Fields>,
+}
+
+impl Modelled {
+    /// Whether the value serialized to the element with this GUID models the
+    /// attribute, even if it omitted it
+    pub(crate) fn contains(&self, guid: &[u8; 16], name: &str) -> bool {
+        match self.elements.get(guid) {
+            Some(Fields::Names(names)) => names.contains(name),
+            Some(Fields::All) => true,
+            None => false,
+        }
+    }
+}
+
+/// Attributes modelled by the value serialized to an element
+enum Fields {
+    /// Names of the fields of a struct, including omitted ones
+    Names(HashSet<String>),
+    /// Every attribute, for maps and structs with an `$extra` field
+    All,
+}
+
+/// Elements and strings of the file being serialized
+struct FileBuilder {
+    strings: StringTable,
+    headers: Vec<Header>,
+    bodies: Vec<Body<Vec<u8>, String>>,
+    /// Attributes modelled by the value of each element
+    fields: Vec<Fields>,
+    random: RandomState,
+    /// Elements with a GUID provided by a `$id` field
+    guids: HashMap<[u8; 16], usize>,
+    /// Whether some elements were allocated for values that are not written
+    /// as elements, which are duplicates and `$extra` fields
+    orphans: bool,
+}
+
+impl FileBuilder {
+    /// Allocates a new element, its attributes are filled once all of them
+    /// have been serialized so that parents come before their children
+    fn element(&mut self, type_: &str) -> usize {
+        let index = self.headers.len();
+        let header = Header {
+            type_: self.strings.intern(type_),
+            name: self.strings.intern(""),
+            guid: self.guid(index),
+        };
+
+        self.headers.push(header);
+        self.bodies.push(Body {
+            attributes: Vec::new(),
+        });
+        self.fields.push(Fields::Names(HashSet::new()));
+
+        index
+    }
+
+    /// Generates a random (version 4) GUID
+    fn guid(&self, index: usize) -> [u8; 16] {
+        random_guid(&self.random, index)
+    }
+}
+
+/// Serialize a value to an attribute value, returns None for omitted values
+struct ValueSerializer<'a> {
+    builder: &'a mut FileBuilder,
+    /// Name of the enum variant wrapping this value
+    variant: Option<&'static str>,
+    /// Name of the newtype struct wrapping this value
+    newtype: Option<&'static str>,
+}
+
+impl<'a> ValueSerializer<'a> {
+    fn new(builder: &'a mut FileBuilder) -> Self {
+        ValueSerializer {
+            builder,
+            variant: None,
+            newtype: None,
+        }
+    }
+
+    /// Type of the element for a struct or map, the variant name takes
+    /// precedence unless it names an attribute type
+    fn element_type(&self, name: &'static str) -> &'static str {
+        match self.variant {
+            Some(variant) if AttributeType::from_name(variant).is_none() => variant,
+            _ => name,
+        }
+    }
+
+    fn element(self, name: &'static str) -> ElementSerializer<'a> {
+        let type_ = self.element_type(name);
+        let index = self.builder.element(type_);
+        ElementSerializer {
+            builder: self.builder,
+            index,
+            attributes: Vec::new(),
+            key: None,
+            duplicate: None,
+        }
+    }
+
+    fn seq(self, len: Option<usize>) -> SeqSerializer<'a> {
+        SeqSerializer {
+            builder: self.builder,
+            hint: self.variant.and_then(AttributeType::from_name),
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        }
+    }
+}
+
+impl<'a> Serializer for ValueSerializer<'a> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = SeqSerializer<'a>;
+    type SerializeMap = ElementSerializer<'a>;
+    type SerializeStruct = StructSerializer<'a>;
+    type SerializeStructVariant = ElementSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Error> {
+        Ok(Some(AttributeValue::Bool(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Error> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Error> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Error> {
+        Ok(Some(AttributeValue::Int(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Error> {
+        let v = c_int::try_from(v)
+            .map_err(|_| Error::custom(format!("{} is out of range for an Int attribute", v)))?;
+        self.serialize_i32(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Error> {
+        Ok(Some(AttributeValue::Uint8(v)))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Error> {
+        self.serialize_i32(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Error> {
+        Ok(Some(AttributeValue::Uint64(v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Error> {
+        Ok(Some(AttributeValue::Float(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Error> {
+        self.serialize_f32(v as c_float)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Error> {
+        Ok(Some(AttributeValue::String(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Error> {
+        Ok(Some(AttributeValue::String(v.to_string())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Error> {
+        Ok(Some(AttributeValue::Binary(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        if name != "Vmatrix" {
+            return value.serialize(ValueSerializer {
+                builder: self.builder,
+                variant: self.variant,
+                newtype: Some(name),
+            });
+        }
+
+        let value = value.serialize(self)?;
+        match value {
+            Some(AttributeValue::FloatArray(values)) if values.len() == 16 => {
+                let mut matrix = [0.0; 16];
+                matrix.copy_from_slice(&values);
+                Ok(Some(AttributeValue::Vmatrix(Vmatrix(matrix))))
+            }
+            _ => Err(Error::custom("expected 16 floats for a Vmatrix")),
+        }
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let value = value.serialize(ValueSerializer {
+            builder: self.builder,
+            variant: Some(variant),
+            newtype: None,
+        })?;
+
+        // Keeps null references of RawValue::Element
+        match value {
+            None if variant == "Element" => Ok(Some(AttributeValue::Element(-1))),
+            value => Ok(value),
+        }
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Ok(self.seq(len))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Error> {
+        Ok(self.seq(Some(len)))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Ok(self.seq(Some(len)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        ValueSerializer {
+            builder: self.builder,
+            variant: Some(variant),
+            newtype: None,
+        }
+        .serialize_tuple(len)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        let name = self.newtype.unwrap_or("DmElement");
+        let element = self.element(name);
+        element.builder.fields[element.index] = Fields::All;
+        Ok(element)
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        if VALUE_STRUCTS.contains(&name) {
+            Ok(StructSerializer::Value(ValueStructSerializer {
+                builder: self.builder,
+                name,
+                fields: Vec::with_capacity(len),
+            }))
+        } else {
+            Ok(StructSerializer::Element(self.element(name)))
+        }
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Ok(ValueSerializer {
+            builder: self.builder,
+            variant: Some(variant),
+            newtype: None,
+        }
+        .element(variant))
+    }
+}
+
+/// Serialize the items of a sequence to an array attribute
+struct SeqSerializer<'a> {
+    builder: &'a mut FileBuilder,
+    /// Type of the array given by the enclosing variant, if any
+    hint: Option<AttributeType>,
+    items: Vec<Option<Value>>,
+}
+
+impl<'a> SeqSerializer<'a> {
+    fn push<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let item = value
+            .serialize(ValueSerializer::new(self.builder))
+            .map_err(|err| err.prepend(PathSegment::Index(self.items.len())))?;
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>, Error> {
+        array(self.items, self.hint).map(Some)
+    }
+}
+
+impl<'a> SerializeSeq for SeqSerializer<'a> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeTuple for SeqSerializer<'a> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeTupleStruct for SeqSerializer<'a> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeTupleVariant for SeqSerializer<'a> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+/// Builds an array attribute from the values of its items, null items are
+/// only allowed in element arrays
+fn array(items: Vec<Option<Value>>, hint: Option<AttributeType>) -> Result<Value, Error> {
+    let kind = match items.iter().flatten().next() {
+        Some(item) => item.kind(),
+        None if items.is_empty() => return Ok(empty_array(hint)),
+        None => AttributeType::Element,
+    };
+
+    Ok(match kind {
+        AttributeType::Element => {
+            AttributeValue::ElementArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::Element(index)) => Some(index),
+                None => Some(-1),
+                _ => None,
+            })?)
+        }
+        AttributeType::Int => AttributeValue::IntArray(collect(items, kind, |item| match item {
+            Some(AttributeValue::Int(value)) => Some(value),
+            _ => None,
+        })?),
+        AttributeType::Float => {
+            AttributeValue::FloatArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::Float(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Bool => {
+            AttributeValue::BoolArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::Bool(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::String => {
+            AttributeValue::StringArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::String(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Binary => {
+            AttributeValue::BinaryArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::Binary(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Time => {
+            AttributeValue::TimeArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::Time(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Color => {
+            AttributeValue::ColorArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::Color(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Vector2 => {
+            AttributeValue::Vector2Array(collect(items, kind, |item| match item {
+                Some(AttributeValue::Vector2(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Vector3 => {
+            AttributeValue::Vector3Array(collect(items, kind, |item| match item {
+                Some(AttributeValue::Vector3(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Vector4 => {
+            AttributeValue::Vector4Array(collect(items, kind, |item| match item {
+                Some(AttributeValue::Vector4(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Qangle => {
+            AttributeValue::QangleArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::Qangle(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Quaternion => {
+            AttributeValue::QuaternionArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::Quaternion(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Vmatrix => {
+            AttributeValue::VmatrixArray(collect(items, kind, |item| match item {
+                Some(AttributeValue::Vmatrix(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Uint64 => {
+            AttributeValue::Uint64Array(collect(items, kind, |item| match item {
+                Some(AttributeValue::Uint64(value)) => Some(value),
+                _ => None,
+            })?)
+        }
+        AttributeType::Uint8 => AttributeValue::Binary(collect(items, kind, |item| match item {
+            Some(AttributeValue::Uint8(value)) => Some(value),
+            _ => None,
+        })?),
+        _ => return Err(Error::custom("arrays of arrays are not supported")),
+    })
+}
+
+/// Extracts the items of an array, fails if one of them is not of type `kind`
+fn collect<T>(
+    items: Vec<Option<Value>>,
+    kind: AttributeType,
+    item: impl Fn(Option<Value>) -> Option<T>,
+) -> Result<Vec<T>, Error> {
+    items
+        .into_iter()
+        .map(|value| {
+            let found = value.as_ref().map(|value| value.kind().name());
+            item(value).ok_or_else(|| {
+                Error::custom(format!(
+                    "expected all the items of the array to be of type {}, found {}",
+                    kind.name(),
+                    found.unwrap_or("null"),
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Empty array of the type named by the enclosing variant, or an empty
+/// ElementArray if it is unknown
+fn empty_array(hint: Option<AttributeType>) -> Value {
+    match hint {
+        Some(AttributeType::Binary) => AttributeValue::Binary(Vec::new()),
+        hint => hint
+            .and_then(AttributeValue::empty_array)
+            .unwrap_or_else(|| AttributeValue::ElementArray(Vec::new())),
+    }
+}
+
+/// Serialize the fields of a struct or the entries of a map to the
+/// attributes of an element
+struct ElementSerializer<'a> {
+    builder: &'a mut FileBuilder,
+    index: usize,
+    attributes: Vec<Attribute<Vec<u8>, String>>,
+    /// Name of the map entry whose value is being serialized
+    key: Option<String>,
+    /// Element already written for the GUID of this value
+    duplicate: Option<usize>,
+}
+
+impl<'a> ElementSerializer<'a> {
+    fn attribute<T>(&mut self, name: &str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        if self.duplicate.is_some() {
+            return Ok(());
+        }
+
+        match name {
+            ID_FIELD => return self.id(value),
+            TYPE_FIELD | NAME_FIELD => return self.header(name, value),
+            EXTRA_FIELD => return self.extra(value),
+            _ => self.field(name),
+        }
+
+        let value = value
+            .serialize(ValueSerializer::new(self.builder))
+            .map_err(|err| err.prepend(PathSegment::Attribute(name.to_string())))?;
+
+        if let Some(value) = value {
+            let strings = &mut self.builder.strings;
+            self.attributes.push(Attribute {
+                name: strings.intern(name),
+                value: value.map_reference(|value| strings.intern(&value)),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Records an attribute modelled by the value of the element
+    fn field(&mut self, name: &str) {
+        if let Fields::Names(names) = &mut self.builder.fields[self.index] {
+            names.insert(name.to_string());
+        }
+    }
+
+    /// Sets the GUID of the element, or marks it as a duplicate of the
+    /// element that already has this GUID
+    fn id<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let guid = match value.serialize(ValueSerializer::new(self.builder))? {
+            None => return Ok(()),
+            Some(AttributeValue::String(value)) => parse_guid(&value),
+            Some(AttributeValue::Binary(value)) => value.as_slice().try_into().ok(),
+            _ => None,
+        };
+
+        let guid = guid.ok_or_else(|| Error::custom("the `$id` field must be a GUID"))?;
+        match self.builder.guids.entry(guid) {
+            Entry::Occupied(entry) => {
+                self.duplicate = Some(*entry.get());
+                self.builder.orphans = true;
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(self.index);
+                self.builder.headers[self.index].guid = guid;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the type or name of the element
+    fn header<T>(&mut self, name: &str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let value = match value.serialize(ValueSerializer::new(self.builder))? {
+            None => return Ok(()),
+            Some(AttributeValue::String(value)) => self.builder.strings.intern(&value),
+            Some(_) => {
+                return Err(Error::custom(format!(
+                    "the `{}` field must be a string",
+                    name
+                )))
+            }
+        };
+
+        let header = &mut self.builder.headers[self.index];
+        if name == TYPE_FIELD {
+            header.type_ = value;
+        } else {
+            header.name = value;
+        }
+
+        Ok(())
+    }
+
+    /// Adds the entries of a map to the attributes of the element
+    fn extra<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match value.serialize(ValueSerializer::new(self.builder))? {
+            None => Ok(()),
+            Some(AttributeValue::Element(index)) => {
+                let attributes = take(&mut self.builder.bodies[index as usize].attributes);
+                self.attributes.extend(attributes);
+                self.builder.fields[self.index] = Fields::All;
+                self.builder.orphans = true;
+                Ok(())
+            }
+            Some(_) => Err(Error::custom(format!(
+                "the `{}` field must be a map",
+                EXTRA_FIELD
+            ))),
+        }
+    }
+
+    fn finish(self) -> Result<Option<Value>, Error> {
+        if let Some(index) = self.duplicate {
+            return Ok(Some(AttributeValue::Element(index as c_int)));
+        }
+
+        self.builder.bodies[self.index].attributes = self.attributes;
+        Ok(Some(AttributeValue::Element(self.index as c_int)))
+    }
+}
+
+impl<'a> SerializeMap for ElementSerializer<'a> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match key.serialize(ValueSerializer::new(self.builder))? {
+            Some(AttributeValue::String(key)) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(Error::custom("attribute names must be strings")),
+        }
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::custom("serialize_value called before serialize_key"))?;
+        self.attribute(&key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeStructVariant for ElementSerializer<'a> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.attribute(key, value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Error> {
+        self.field(key);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        self.finish()
+    }
+}
+
+/// Serialize a struct either to an element or to a single attribute value
+enum StructSerializer<'a> {
+    Element(ElementSerializer<'a>),
+    Value(ValueStructSerializer<'a>),
+}
+
+impl<'a> SerializeStruct for StructSerializer<'a> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            StructSerializer::Element(element) => element.attribute(key, value),
+            StructSerializer::Value(value_struct) => value_struct.field(key, value),
+        }
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Error> {
+        if let StructSerializer::Element(element) = self {
+            element.field(key);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        match self {
+            StructSerializer::Element(element) => element.finish(),
+            StructSerializer::Value(value_struct) => value_struct.finish().map(Some),
+        }
+    }
+}
+
+/// Serialize the numeric fields of one of the math types
+struct ValueStructSerializer<'a> {
+    builder: &'a mut FileBuilder,
+    name: &'static str,
+    fields: Vec<(&'static str, f64)>,
+}
+
+impl<'a> ValueStructSerializer<'a> {
+    fn field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let value = match value.serialize(ValueSerializer::new(self.builder))? {
+            Some(AttributeValue::Int(value)) => value.into(),
+            Some(AttributeValue::Float(value)) => value.into(),
+            Some(AttributeValue::Uint8(value)) => value.into(),
+            _ => {
+                return Err(Error::custom(format!(
+                    "expected a number for field `{}` of {}",
+                    key, self.name
+                )))
+            }
+        };
+
+        self.fields.push((key, value));
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<f64, Error> {
+        self.fields
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| Error::custom(format!("missing field `{}` in {}", key, self.name)))
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        let float = |key| self.get(key).map(|value| value as c_float);
+        let byte = |key| self.get(key).map(|value| value as c_char);
+
+        Ok(match self.name {
+            "Time" => AttributeValue::Time(Time {
+                millis: self.get("millis")? as c_int,
+            }),
+            "Color" => AttributeValue::Color(Color {
+                r: byte("r")?,
+                g: byte("g")?,
+                b: byte("b")?,
+                a: byte("a")?,
+            }),
+            "Vector2" => AttributeValue::Vector2(Vector2 {
+                x: float("x")?,
+                y: float("y")?,
+            }),
+            "Vector3" => AttributeValue::Vector3(Vector3 {
+                x: float("x")?,
+                y: float("y")?,
+                z: float("z")?,
+            }),
+            "Vector4" => AttributeValue::Vector4(Vector4 {
+                x: float("x")?,
+                y: float("y")?,
+                z: float("z")?,
+                w: float("w")?,
+            }),
+            "Qangle" => AttributeValue::Qangle(Qangle {
+                pitch: float("pitch")?,
+                yaw: float("yaw")?,
+                roll: float("roll")?,
+            }),
+            "Quaternion" => AttributeValue::Quaternion(Quaternion {
+                x: float("x")?,
+                y: float("y")?,
+                z: float("z")?,
+                w: float("w")?,
+            }),
+            _ => unreachable!("{} is not in VALUE_STRUCTS", self.name),
+        })
+    }
+}
@@ -0,0 +1,168 @@
+//! Deserialization of the elements referenced multiple times as a single
+//! shared value
+//!
+//! Fields of type `Rc<T>` or `Arc<T>` annotated with
+//! `#[serde(with = "dmxparser::serde::shared")]` (or [Shared] values, for
+//! the items of collections) are deserialized once per element by
+//! [from_file](super::from_file), and every other reference to the element
+//! gets a clone of the same pointer:
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct CMapInstance {
+//!     #[serde(with = "dmxparser::serde::shared")]
+//!     target: Rc<CMapGroup<Vec<u8>, String>>,
+//! }
+//! ```
+//!
+//! The values are cached by element and pointer type, so the target type
+//! must not borrow from the file (`T: 'static`). Other deserializers build a
+//! new value for each pointer.
+use serde::{
+    de::{Error, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Formatter},
+    marker::PhantomData,
+    ops::Deref,
+    rc::Rc,
+    sync::Arc,
+};
+
+/// Name of the newtype struct requested from the Deserializer, which
+/// provides the identity of the element before its content
+pub(crate) const SHARED_TOKEN: &str = "$dmxparser::Shared";
+
+/// Key of a cached value: from_file call, element index and pointer type
+type Key = (u64, u64, TypeId);
+
+thread_local! {
+    /// Values deserialized by the from_file calls in progress
+    static VALUES: RefCell<HashMap<Key, Box<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Drops the values cached by a from_file call
+pub(crate) fn release(call: u64) {
+    VALUES.with(|values| values.borrow_mut().retain(|key, _| key.0 != call));
+}
+
+/// Pointer types that can share a deserialized element
+pub trait SharedPointer: Deref + Clone + 'static {
+    fn new(value: Self::Target) -> Self;
+}
+
+impl<T: 'static> SharedPointer for Rc<T> {
+    fn new(value: T) -> Self {
+        Rc::new(value)
+    }
+}
+
+impl<T: 'static> SharedPointer for Arc<T> {
+    fn new(value: T) -> Self {
+        Arc::new(value)
+    }
+}
+
+pub fn deserialize<'de, D, P>(deserializer: D) -> Result<P, D::Error>
+where
+    D: Deserializer<'de>,
+    P: SharedPointer,
+    P::Target: Deserialize<'de> + Sized,
+{
+    deserializer.deserialize_newtype_struct(SHARED_TOKEN, SharedVisitor(PhantomData))
+}
+
+pub fn serialize<Z, P>(value: &P, serializer: Z) -> Result<Z::Ok, Z::Error>
+where
+    Z: Serializer,
+    P: Deref,
+    P::Target: Serialize,
+{
+    (**value).serialize(serializer)
+}
+
+/// Pointer to an element shared by all its references, see the [module
+/// documentation](self)
+#[derive(Clone, Debug)]
+pub struct Shared<P>(pub P);
+
+impl<P> Deref for Shared<P> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        &self.0
+    }
+}
+
+impl<'de, P> Deserialize<'de> for Shared<P>
+where
+    P: SharedPointer,
+    P::Target: Deserialize<'de> + Sized,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer).map(Shared)
+    }
+}
+
+impl<P> Serialize for Shared<P>
+where
+    P: Deref,
+    P::Target: Serialize,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serialize(&self.0, serializer)
+    }
+}
+
+struct SharedVisitor<P>(PhantomData<P>);
+
+impl<'de, P> Visitor<'de> for SharedVisitor<P>
+where
+    P: SharedPointer,
+    P::Target: Deserialize<'de> + Sized,
+{
+    type Value = P;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("an element")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<P, D::Error> {
+        P::Target::deserialize(deserializer).map(P::new)
+    }
+
+    /// The Deserializer of this crate provides the from_file call and the
+    /// index of the element, followed by the element
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<P, A::Error> {
+        let call: u64 = seq
+            .next_element()?
+            .ok_or_else(|| Error::invalid_length(0, &self))?;
+        let index: u64 = seq
+            .next_element()?
+            .ok_or_else(|| Error::invalid_length(1, &self))?;
+        let key = (call, index, TypeId::of::<P>());
+
+        let cached = VALUES.with(|values| {
+            values
+                .borrow()
+                .get(&key)
+                .and_then(|value| value.downcast_ref::<P>())
+                .cloned()
+        });
+        if let Some(value) = cached {
+            return Ok(value);
+        }
+
+        let value = P::new(
+            seq.next_element()?
+                .ok_or_else(|| Error::invalid_length(2, &self))?,
+        );
+        VALUES.with(|values| values.borrow_mut().insert(key, Box::new(value.clone())));
+
+        Ok(value)
+    }
+}
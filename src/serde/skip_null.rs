@@ -0,0 +1,62 @@
+//! Deserialization of element arrays without their null entries
+//!
+//! The entries of an ElementArray that reference no element (-1, as left by
+//! Hammer after deleting some elements) are deserialized as `None`, which
+//! fails for a `Vec<T>` unless it is a `Vec<Option<T>>`. Fields annotated
+//! with `#[serde(with = "dmxparser::serde::skip_null")]` skip these entries
+//! instead:
+//!
+//! ```ignore
+//! #[derive(Deserialize, Serialize)]
+//! struct CMapWorld {
+//!     #[serde(with = "dmxparser::serde::skip_null")]
+//!     children: Vec<Element<Vec<u8>, String>>,
+//! }
+//! ```
+use serde::{
+    de::{SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    fmt::{self, Formatter},
+    marker::PhantomData,
+};
+
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct SkipNullVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for SkipNullVisitor<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+            f.write_str("a sequence")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Vec<T>, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(item) = seq.next_element::<Option<T>>()? {
+                items.extend(item);
+            }
+
+            Ok(items)
+        }
+    }
+
+    deserializer.deserialize_seq(SkipNullVisitor(PhantomData))
+}
+
+/// Serializes the items that are left, the null entries are not restored
+pub fn serialize<S, T>(value: &[T], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    serializer.collect_seq(value)
+}
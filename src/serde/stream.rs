@@ -0,0 +1,264 @@
+//! Deserialization straight from a reader, without reading the whole [File]
+//! first
+//!
+//! Only the head of the file (the prefix attributes, the string table and the
+//! element headers) stays in memory. The bodies of the elements are read as
+//! the target type requests them, and dropped once their element has been
+//! deserialized, the bodies that are read ahead to reach an element are kept
+//! until they are deserialized in turn. Since the bodies don't live as long
+//! as the deserialization, their values are never borrowed by the target
+//! type (see [Transient]).
+use super::{
+    deserialize_element, Bodies, BodyRef, BufferWrapper, Context, Error, Options, StringWrapper,
+    Transient, TransientValue, Wrap,
+};
+use crate::{
+    dmx::{Body, File},
+    read::{Readable, Reader, ReaderString},
+};
+use serde::{
+    de::{
+        value::{SeqDeserializer, StrDeserializer},
+        DeserializeOwned, DeserializeSeed, Deserializer, Error as _, IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any,
+};
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug, Formatter},
+    io::BufRead,
+    ops::Deref,
+    rc::Rc,
+};
+
+/// Deserializes the root element of a file as it is read, which only keeps
+/// the bodies of the elements that are not deserialized yet in memory,
+/// instead of the whole file for [from_file](super::from_file)
+///
+/// Each element can only be deserialized once, the elements referenced
+/// several times must be deserialized as [Shared](super::Shared) values, and
+/// [ElementRef](super::ElementRef) is not supported.
+///
+/// ```ignore
+/// let reader = BufReader::new(fs::File::open("map.vmap")?);
+/// let root: CMapRootElement<Vec<u8>, String> = from_reader(reader)?;
+/// ```
+pub fn from_reader<T>(reader: impl BufRead) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    from_reader_with(reader, Options::default())
+}
+
+/// Same as [from_reader], with options
+pub fn from_reader_with<T>(reader: impl BufRead, options: Options) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let mut reader = StreamReader(reader);
+    let file = File::read_head(&mut reader).map_err(read_error)?;
+
+    let stream = Stream {
+        file: &file,
+        reader: RefCell::new(reader),
+        bodies: RefCell::new(Vec::new()),
+    };
+
+    let context = Context::new(&file, None, Box::new(&stream), options);
+    deserialize_element(context, 0)
+}
+
+fn read_error(err: anyhow::Error) -> Error {
+    Error::custom(format!("{:#}", err))
+}
+
+/// Buffers and strings of a streamed file, which are never borrowed by the
+/// deserialized values since they are dropped with the bodies
+struct Streamed<T>(T);
+
+impl<T: Deref> Deref for Streamed<T> {
+    type Target = T::Target;
+
+    fn deref(&self) -> &T::Target {
+        &self.0
+    }
+}
+
+impl<T: Debug> Debug for Streamed<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for BufferWrapper<'b, Streamed<Vec<u8>>> {
+    type Deserializer = StreamedBufferDeserializer<'b>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        StreamedBufferDeserializer(&self.0 .0)
+    }
+}
+
+/// Deserializes a streamed buffer like [BufferDeserializer](super::BufferDeserializer),
+/// as bytes that are not borrowed
+struct StreamedBufferDeserializer<'a>(&'a [u8]);
+
+impl<'de, 'a> Deserializer<'de> for StreamedBufferDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_bytes(self.0)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        SeqDeserializer::new(self.0.iter().cloned()).deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+impl<'de, 'b> IntoDeserializer<'de, Error> for StringWrapper<'b, Streamed<String>> {
+    type Deserializer = StrDeserializer<'b, Error>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        StrDeserializer::new(&self.0 .0)
+    }
+}
+
+/// The bodies of a streamed file are always shared with the [Stream]
+impl<'de> Transient<'de, Streamed<String>> for Streamed<Vec<u8>> {
+    fn deserialize_value<T>(
+        value: TransientValue<'de, '_, Self, Streamed<String>>,
+        seed: T,
+    ) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        value.deserialize(StreamValues, seed)
+    }
+}
+
+/// Values of the bodies shared with the [Stream], which are never borrowed
+#[derive(Clone, Copy)]
+struct StreamValues;
+
+impl<'de, 'b> Wrap<'de, 'b, Streamed<Vec<u8>>, Streamed<String>> for StreamValues {
+    type Buffer = BufferWrapper<'b, Streamed<Vec<u8>>>;
+    type String = StringWrapper<'b, Streamed<String>>;
+
+    fn buffer(self, value: &'b Streamed<Vec<u8>>) -> Self::Buffer {
+        BufferWrapper(value)
+    }
+
+    fn string(self, value: &'b Streamed<String>) -> Self::String {
+        StringWrapper(value)
+    }
+}
+
+/// Reads the buffers and strings of a file as [Streamed] values
+struct StreamReader<R>(R);
+
+impl<R: BufRead> Reader for StreamReader<R> {
+    type Buffer = Streamed<Vec<u8>>;
+    type String = Streamed<String>;
+
+    fn read_into(&mut self, buf: &mut [u8]) -> anyhow::Result<()> {
+        Reader::read_into(&mut self.0, buf)
+    }
+
+    fn read_bytes(&mut self, size: usize) -> anyhow::Result<Self::Buffer> {
+        Reader::read_bytes(&mut self.0, size).map(Streamed)
+    }
+
+    fn read_until(&mut self, predicate: u8) -> anyhow::Result<Self::Buffer> {
+        Reader::read_until(&mut self.0, predicate).map(Streamed)
+    }
+}
+
+impl<R: BufRead> Readable<StreamReader<R>> for Streamed<String> {
+    fn read(reader: &mut StreamReader<R>) -> anyhow::Result<Self> {
+        String::read(&mut reader.0).map(Streamed)
+    }
+}
+
+impl ReaderString for Streamed<String> {
+    fn split(&mut self, index: usize) -> Self {
+        Streamed(self.0.split(index))
+    }
+}
+
+type StreamedFile = File<Streamed<Vec<u8>>, Streamed<String>>;
+type StreamedBody = Body<Streamed<Vec<u8>>, Streamed<String>>;
+
+/// Bodies of a file being read
+struct Stream<'f, R> {
+    /// Head of the file
+    file: &'f StreamedFile,
+    reader: RefCell<StreamReader<R>>,
+    /// Bodies read so far, none once their element has been deserialized
+    bodies: RefCell<Vec<Option<Rc<StreamedBody>>>>,
+}
+
+impl<'de, R: BufRead> Bodies<'de, Streamed<Vec<u8>>, Streamed<String>> for &'de Stream<'de, R> {
+    fn len(&self) -> usize {
+        self.file.headers.len()
+    }
+
+    fn body(
+        &self,
+        index: usize,
+    ) -> Result<BodyRef<'de, Streamed<Vec<u8>>, Streamed<String>>, Error> {
+        let mut bodies = self.bodies.borrow_mut();
+
+        while bodies.len() <= index && bodies.len() < self.len() {
+            let body = self
+                .file
+                .read_body(&mut *self.reader.borrow_mut())
+                .map_err(read_error)?;
+            bodies.push(Some(Rc::new(body)));
+        }
+
+        match bodies.get(index) {
+            // The deserializers using the body keep it alive after release
+            Some(Some(body)) => Ok(BodyRef::Shared(body.clone())),
+            Some(None) => Err(Error::custom(
+                "the element was already deserialized from the stream, elements referenced several times must be deserialized as Shared values",
+            )),
+            None => Err(Error::custom(format!("missing body of element {}", index))),
+        }
+    }
+
+    fn release(&self, index: usize) {
+        if let Some(body) = self.bodies.borrow_mut().get_mut(index) {
+            *body = None;
+        }
+    }
+}
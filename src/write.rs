@@ -0,0 +1,53 @@
+use std::{
+    io::Write,
+    os::raw::{c_char, c_float, c_int},
+};
+
+use anyhow::Result;
+
+pub trait Writable {
+    fn write(&self, writer: &mut dyn Write) -> Result<()>;
+}
+
+macro_rules! impl_to_bytes {
+    ( $ty:ty ) => {
+        impl Writable for $ty {
+            fn write(&self, writer: &mut dyn Write) -> anyhow::Result<()> {
+                writer.write_all(&self.to_le_bytes())?;
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_to_bytes!(u8);
+impl_to_bytes!(c_char);
+impl_to_bytes!(c_int);
+impl_to_bytes!(c_float);
+impl_to_bytes!(u64);
+
+impl Writable for str {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        writer.write_all(self.as_bytes())?;
+        writer.write_all(&[0])?;
+        Ok(())
+    }
+}
+
+impl Writable for [u8] {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        (self.len() as c_int).write(writer)?;
+        writer.write_all(self)?;
+        Ok(())
+    }
+}
+
+impl<T: Writable> Writable for Vec<T> {
+    fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        (self.len() as c_int).write(writer)?;
+        for item in self {
+            item.write(writer)?;
+        }
+        Ok(())
+    }
+}
@@ -3,16 +3,37 @@
 extern crate test;
 
 use std::{
+    collections::HashMap,
     env,
-    fs::{read_dir, File},
+    fs::{self, read_dir, File},
     io::{BufReader, Read},
+    ops::Deref,
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
 };
 use test::{test_main, ShouldPanic, TestDesc, TestDescAndFn, TestFn, TestName, TestType};
 
+use ::serde::{de::IgnoredAny, Deserialize, Serialize};
 use serde_json::Value;
 
-use dmxparser::{formats::vmap::read_vmap, from_reader, from_slice, serde::from_file};
+use dmxparser::{
+    dmx::{
+        diff,
+        json::{from_json, to_json},
+        merge, Attribute, AttributeDiff, AttributeType, AttributeValue, Body, Color, ConflictKind,
+        DiagnosticKind, File as DmxFile, HashOptions, Header, Match, Quaternion, Query, Side,
+        StringRef, Vector2, Vector3, Vmatrix,
+    },
+    formats::vmap::{read_vmap, write_vmap, CMapRootElement, ElementType},
+    from_reader, from_slice,
+    serde::{
+        self, from_file, from_file_with, to_file, Custom, Document, ElementRef, Guid, Options,
+        PathSegment, RawAttributes, RawElement, RawValue, Registry, Shared,
+    },
+    to_vec,
+};
 
 fn main() {
     let mut args = Vec::new();
@@ -36,16 +57,22 @@ fn main() {
         let path_4 = path_1.clone();
         let path_5 = path_1.clone();
         let path_6 = path_1.clone();
+        let path_7 = path_1.clone();
+        let path_8 = path_1.clone();
+        let path_9 = path_1.clone();
+        let path_10 = path_1.clone();
+        let path_11 = path_1.clone();
 
-        tests.push(TestDescAndFn {
-            desc: TestDesc {
-                name: TestName::DynTestName(format!("from_slice({})", path_1.display())),
-                ignore: false,
-                should_panic: ShouldPanic::No,
-                allow_fail: false,
-                test_type: TestType::IntegrationTest,
-            },
-            testfn: TestFn::DynTestFn(Box::new(move || {
+        for &(name, run) in FIXTURE_TESTS {
+            let path = path_1.clone();
+            tests.push(test(format!("{}({})", name, path.display()), move || {
+                run(&path)
+            }));
+        }
+
+        tests.push(test(
+            format!("from_slice({})", path_1.display()),
+            move || {
                 let mut data = Vec::new();
                 let mut file = File::open(path_1).unwrap();
                 file.read_to_end(&mut data).unwrap();
@@ -53,36 +80,24 @@ fn main() {
                 if let Err(err) = from_slice(&data) {
                     panic!("{}", err);
                 }
-            })),
-        });
-
-        tests.push(TestDescAndFn {
-            desc: TestDesc {
-                name: TestName::DynTestName(format!("from_reader({})", path_2.display())),
-                ignore: false,
-                should_panic: ShouldPanic::No,
-                allow_fail: false,
-                test_type: TestType::IntegrationTest,
             },
-            testfn: TestFn::DynTestFn(Box::new(move || {
+        ));
+
+        tests.push(test(
+            format!("from_reader({})", path_2.display()),
+            move || {
                 let file = File::open(path_2).unwrap();
                 let reader = BufReader::new(file);
 
                 if let Err(err) = from_reader(reader) {
                     panic!("{}", err);
                 }
-            })),
-        });
-
-        tests.push(TestDescAndFn {
-            desc: TestDesc {
-                name: TestName::DynTestName(format!("from_file(from_slice({}))", path_3.display())),
-                ignore: false,
-                should_panic: ShouldPanic::No,
-                allow_fail: false,
-                test_type: TestType::IntegrationTest,
             },
-            testfn: TestFn::DynTestFn(Box::new(move || {
+        ));
+
+        tests.push(test(
+            format!("from_file(from_slice({}))", path_3.display()),
+            move || {
                 let mut data = Vec::new();
                 let mut file = File::open(path_3).unwrap();
                 file.read_to_end(&mut data).unwrap();
@@ -92,21 +107,12 @@ fn main() {
                 if let Err(err) = from_file::<_, _, Value>(&file) {
                     panic!("{}", err);
                 }
-            })),
-        });
-
-        tests.push(TestDescAndFn {
-            desc: TestDesc {
-                name: TestName::DynTestName(format!(
-                    "from_file(from_reader({}))",
-                    path_4.display()
-                )),
-                ignore: false,
-                should_panic: ShouldPanic::No,
-                allow_fail: false,
-                test_type: TestType::IntegrationTest,
             },
-            testfn: TestFn::DynTestFn(Box::new(move || {
+        ));
+
+        tests.push(test(
+            format!("from_file(from_reader({}))", path_4.display()),
+            move || {
                 let file = File::open(path_4).unwrap();
                 let reader = BufReader::new(file);
                 let file = from_reader(reader).unwrap();
@@ -114,18 +120,12 @@ fn main() {
                 if let Err(err) = from_file::<Vec<u8>, String, Value>(&file) {
                     panic!("{}", err);
                 }
-            })),
-        });
-
-        tests.push(TestDescAndFn {
-            desc: TestDesc {
-                name: TestName::DynTestName(format!("read_vmap(from_slice({}))", path_5.display())),
-                ignore: false,
-                should_panic: ShouldPanic::No,
-                allow_fail: false,
-                test_type: TestType::IntegrationTest,
             },
-            testfn: TestFn::DynTestFn(Box::new(move || {
+        ));
+
+        tests.push(test(
+            format!("read_vmap(from_slice({}))", path_5.display()),
+            move || {
                 let mut data = Vec::new();
                 let mut file = File::open(path_5).unwrap();
                 file.read_to_end(&mut data).unwrap();
@@ -135,21 +135,12 @@ fn main() {
                 if let Err(err) = read_vmap(&file) {
                     panic!("{}", err);
                 }
-            })),
-        });
-
-        tests.push(TestDescAndFn {
-            desc: TestDesc {
-                name: TestName::DynTestName(format!(
-                    "read_vmap(from_reader({}))",
-                    path_6.display()
-                )),
-                ignore: false,
-                should_panic: ShouldPanic::No,
-                allow_fail: false,
-                test_type: TestType::IntegrationTest,
             },
-            testfn: TestFn::DynTestFn(Box::new(move || {
+        ));
+
+        tests.push(test(
+            format!("read_vmap(from_reader({}))", path_6.display()),
+            move || {
                 let file = File::open(path_6).unwrap();
                 let reader = BufReader::new(file);
                 let file = from_reader(reader).unwrap();
@@ -157,13 +148,1716 @@ fn main() {
                 if let Err(err) = read_vmap(&file) {
                     panic!("{}", err);
                 }
-            })),
-        });
+            },
+        ));
+
+        tests.push(test(
+            format!("to_vec(from_slice({}))", path_7.display()),
+            move || {
+                let mut data = Vec::new();
+                let mut file = File::open(path_7).unwrap();
+                file.read_to_end(&mut data).unwrap();
+
+                let file = from_slice(&data).unwrap();
+                let written = to_vec(&file).unwrap();
+
+                assert!(written == data, "written file differs from the original");
+            },
+        ));
+
+        tests.push(test(
+            format!("to_file(read_vmap(from_slice({})))", path_8.display()),
+            move || {
+                let mut data = Vec::new();
+                let mut file = File::open(path_8).unwrap();
+                file.read_to_end(&mut data).unwrap();
+
+                let file = from_slice(&data).unwrap();
+                let root = read_vmap(&file).unwrap();
+
+                let written = to_file(&root, "vmap", file.header.format_version).unwrap();
+                let written = to_vec(&written).unwrap();
+                let written = from_slice(&written).unwrap();
+
+                let expected = serde_json::to_value(&root).unwrap();
+                let actual = serde_json::to_value(&read_vmap(&written).unwrap()).unwrap();
+                assert!(actual == expected, "written map differs from the original");
+            },
+        ));
+
+        tests.push(test(
+            format!("write_vmap(read_vmap(from_slice({})))", path_9.display()),
+            move || {
+                let mut data = Vec::new();
+                let mut file = File::open(path_9).unwrap();
+                file.read_to_end(&mut data).unwrap();
+
+                let file = from_slice(&data).unwrap();
+                let root = read_vmap(&file).unwrap();
+                let written = write_vmap(&root, &file).unwrap();
+
+                // Only the elements unreachable from the root are dropped
+                let diff = diff(&file, &written);
+                assert!(diff.added.is_empty(), "{:?}", diff.added);
+                assert!(diff.changed.is_empty(), "{:?}", diff.changed);
+                assert!(
+                    diff.removed.iter().all(|element| element.path.is_none()),
+                    "{:?}",
+                    diff.removed
+                );
+            },
+        ));
+
+        tests.push(test(
+            format!("from_file_with(from_slice({}), strict)", path_10.display()),
+            move || {
+                let mut data = Vec::new();
+                let mut file = File::open(path_10).unwrap();
+                file.read_to_end(&mut data).unwrap();
+
+                let file = from_slice(&data).unwrap();
+                let options = Options {
+                    strict: true,
+                    ..Options::default()
+                };
+                let root = from_file_with::<_, _, CMapRootElement<&[u8], &str>>(&file, options);
+
+                if let Err(err) = root {
+                    panic!("{}", err);
+                }
+            },
+        ));
+
+        tests.push(test(
+            format!("serde::from_reader({})", path_11.display()),
+            move || {
+                let mut data = Vec::new();
+                let mut file = File::open(&path_11).unwrap();
+                file.read_to_end(&mut data).unwrap();
+
+                let file = from_slice(&data).unwrap();
+                let expected = serde_json::to_value(&read_vmap(&file).unwrap()).unwrap();
+
+                let reader = BufReader::new(File::open(path_11).unwrap());
+                let root: CMapRootElement<Vec<u8>, String> = match serde::from_reader(reader) {
+                    Ok(root) => root,
+                    Err(err) => panic!("{}", err),
+                };
+
+                let actual = serde_json::to_value(&root).unwrap();
+                assert!(actual == expected, "streamed map differs from the original");
+            },
+        ));
     });
 
     test_main(&args, tests, None);
 }
 
+/// Test running on a fixture
+type FixtureTest = fn(&Path);
+
+/// Tests running on each fixture, by name
+const FIXTURE_TESTS: &[(&str, FixtureTest)] = &[
+    ("File::compact", compact_mismatched),
+    ("File::validate", validate),
+    ("File::serialize", serialize_file),
+    ("from_file($extra)", extra_attributes),
+    ("from_file(error path)", error_path),
+    ("from_file::<Rc<_>>", shared_elements),
+    ("from_file($type, $name, $id)", header_fields),
+    ("from_file::<[f32; 3]>", math_sequences),
+    ("from_file::<Document>", document),
+    ("from_file_with(coerce)", coercions),
+    ("Registry::register", registry_decoder),
+    ("Registry::fallback", registry_fallback),
+    ("Registry::scope", registry_nested_scope),
+    ("from_file::<&str>", borrow_from_owned_file),
+    (
+        "serde::from_reader::<RawAttributes>",
+        stream_element_reached_twice,
+    ),
+    ("from_file(string out of range)", string_out_of_range),
+    ("from_file::<enum>", enum_variants),
+    ("File::extract", extract),
+    ("File::content_hash", content_hash),
+    ("from_json(to_json)", json_round_trip),
+    ("Query::parse", query_syntax),
+    ("Query::eval", query_descendants),
+    ("merge", merge_clean),
+    ("merge(conflicts)", merge_conflicts),
+    ("merge(deleted)", merge_deleted),
+    ("diff(NaN)", diff_nan),
+    ("from_file::<ElementRef<_>>", element_ref),
+    ("write_vmap(changes)", write_vmap_changes),
+];
+
+/// Value of the attribute of an element with the given name
+fn attribute_value<'a, B, S: Deref<Target = str>>(
+    file: &'a DmxFile<B, S>,
+    element: usize,
+    name: &str,
+) -> &'a AttributeValue<B, S, StringRef> {
+    let attributes = &file.bodies[element].attributes;
+    let attr = attributes
+        .iter()
+        .find(|attr| file.string(attr.name) == Some(name));
+    &attr.unwrap().value
+}
+
+fn attribute_value_mut<'a, B, S: Deref<Target = str>>(
+    file: &'a mut DmxFile<B, S>,
+    element: usize,
+    name: &str,
+) -> &'a mut AttributeValue<B, S, StringRef> {
+    let name = file.strings.iter().position(|string| &**string == name);
+    let name = StringRef(name.unwrap() as i32);
+    let attributes = &mut file.bodies[element].attributes;
+    let attr = attributes.iter_mut().find(|attr| attr.name == name);
+    &mut attr.unwrap().value
+}
+
+const WORLD: usize = 5;
+const ENTITY: usize = 26;
+
+fn compact_mismatched(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+    let (headers, bodies, strings) = (file.headers.len(), file.bodies.len(), file.strings.len());
+
+    // Nothing is dropped from a file whose headers and bodies do not match
+    let Header { type_, name, guid } = &file.headers[0];
+    let header = Header {
+        type_: *type_,
+        name: *name,
+        guid: *guid,
+    };
+    file.headers.push(header);
+    let err = file.compact().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "File has {} element headers but {} element bodies",
+            headers + 1,
+            bodies
+        )
+    );
+    assert_eq!(file.headers.len(), headers + 1);
+    assert_eq!(file.bodies.len(), bodies);
+    assert_eq!(file.strings.len(), strings);
+
+    file.headers.pop();
+    file.bodies.push(Body {
+        attributes: Vec::new(),
+    });
+    assert!(file.compact().is_err());
+    assert_eq!(file.bodies.len(), bodies + 1);
+
+    file.bodies.pop();
+    let compaction = file.compact().unwrap();
+    assert_eq!(file.headers.len(), headers - compaction.elements.len());
+    assert_eq!(file.bodies.len(), file.headers.len());
+}
+
+fn validate(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+    assert_eq!(file.validate(), []);
+
+    let out_of_range = StringRef(file.strings.len() as i32);
+    file.headers[WORLD].name = out_of_range;
+    match attribute_value_mut(&mut file, WORLD, "children") {
+        AttributeValue::ElementArray(children) => children[1] = 1000,
+        value => panic!("{:?}", value),
+    }
+    *attribute_value_mut(&mut file, WORLD, "mapUsageType") = AttributeValue::String(out_of_range);
+    let origin = file.bodies[WORLD].attributes[0].name;
+    file.bodies[WORLD].attributes.push(Attribute {
+        name: origin,
+        value: AttributeValue::Int(0),
+    });
+    file.headers[8].guid = file.headers[WORLD].guid;
+    file.bodies[ENTITY].attributes[0].name = out_of_range;
+    file.bodies.push(Body {
+        attributes: Vec::new(),
+    });
+
+    let diagnostics = file.validate();
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.element,
+                diagnostic.element_name.as_deref(),
+                diagnostic.attribute.as_deref(),
+                diagnostic.kind.clone(),
+            )
+        })
+        .collect();
+    let headers = file.headers.len();
+    assert_eq!(
+        found,
+        [
+            (
+                None,
+                None,
+                None,
+                DiagnosticKind::BodyCountMismatch {
+                    headers,
+                    bodies: headers + 1,
+                },
+            ),
+            (
+                Some(WORLD),
+                Some("CMapWorld"),
+                None,
+                DiagnosticKind::InvalidName(out_of_range),
+            ),
+            (
+                Some(WORLD),
+                Some("CMapWorld"),
+                Some("\"children\""),
+                DiagnosticKind::InvalidElement(1000),
+            ),
+            (
+                Some(WORLD),
+                Some("CMapWorld"),
+                Some("\"mapUsageType\""),
+                DiagnosticKind::InvalidString(out_of_range),
+            ),
+            (
+                Some(WORLD),
+                Some("CMapWorld"),
+                Some("\"origin\""),
+                DiagnosticKind::DuplicateAttribute,
+            ),
+            (
+                Some(8),
+                Some("CMapMesh \"\""),
+                None,
+                DiagnosticKind::DuplicateGuid { first: WORLD },
+            ),
+            (
+                Some(ENTITY),
+                Some("CMapEntity \"\""),
+                Some("#0"),
+                DiagnosticKind::InvalidAttributeName(out_of_range),
+            ),
+        ]
+    );
+    assert_eq!(
+        diagnostics[2].to_string(),
+        "element #5 (CMapWorld), attribute \"children\": element reference 1000 is out of range"
+    );
+
+    file.headers.clear();
+    file.bodies.clear();
+    let diagnostics = file.validate();
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0].kind, DiagnosticKind::MissingRoot);
+    assert_eq!(diagnostics[0].to_string(), "file has no root element");
+}
+
+/// Attribute of an element serialized with the JSON schema, by name
+fn json_attribute<'a>(element: &'a Value, name: &str) -> &'a Value {
+    let attributes = element["attributes"].as_array().unwrap();
+    let attribute = attributes
+        .iter()
+        .find(|attribute| attribute["name"] == name);
+    attribute.unwrap()
+}
+
+fn serialize_file(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+
+    // The world is shared by two attributes of the root
+    file.strings.push("worldAlias");
+    file.bodies[0].attributes.push(Attribute {
+        name: StringRef(file.strings.len() as i32 - 1),
+        value: AttributeValue::Element(WORLD as i32),
+    });
+    *attribute_value_mut(&mut file, 0, "gridspacing") = AttributeValue::Float(f32::NAN);
+
+    let value = serde_json::to_value(&file).unwrap();
+    assert_eq!(value["encoding"], "binary");
+    assert_eq!(value["format"], "vmap");
+    assert_eq!(value["format_version"], file.header.format_version);
+
+    // Shared elements are serialized once and referenced by index
+    let elements = value["elements"].as_array().unwrap();
+    assert_eq!(elements.len(), file.headers.len());
+    let root = &elements[0];
+    assert_eq!(root["type"], "CMapRootElement");
+    assert_eq!(
+        json_attribute(root, "world"),
+        &serde_json::json!({ "name": "world", "type": "Element", "value": WORLD })
+    );
+    assert_eq!(json_attribute(root, "worldAlias")["value"], WORLD);
+    assert_eq!(elements[WORLD]["type"], "CMapWorld");
+    assert_eq!(
+        json_attribute(&elements[WORLD], "children")["value"],
+        serde_json::json!([8, ENTITY, 29])
+    );
+
+    // Values that JSON cannot represent exactly are strings
+    assert_eq!(json_attribute(root, "gridspacing")["value"], "NaN");
+    let reference = json_attribute(&elements[8], "referenceID");
+    assert_eq!(reference["type"], "Uint64");
+    assert_eq!(reference["value"], "1009445275097407959");
+
+    let guid = root["guid"].as_str().unwrap();
+    assert_eq!(guid.len(), 36, "{}", guid);
+    assert_eq!(guid.matches('-').count(), 4, "{}", guid);
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "CMapRootElement")]
+struct PartialRoot {
+    world: PartialWorld,
+    #[serde(rename = "$extra", default)]
+    extra: RawAttributes,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "CMapWorld")]
+struct PartialWorld {
+    origin: Vector3,
+    #[serde(rename = "$extra", default)]
+    extra: RawAttributes,
+}
+
+fn extra_attributes(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let file = from_slice(&data).unwrap();
+    let root: PartialRoot = from_file(&file).unwrap();
+
+    // Every attribute not consumed by a field is captured with its type
+    let extra = |attributes: &RawAttributes, name: &str| {
+        let value = attributes.0.iter().find(|(attr, _)| attr == name);
+        value.map(|(_, value)| value.clone())
+    };
+    let world = &root.world.extra;
+    assert_eq!(world.0.len(), file.bodies[WORLD].attributes.len() - 1);
+    assert_eq!(extra(world, "origin"), None);
+    assert_eq!(extra(world, "nodeID"), Some(RawValue::Int(1)));
+    assert_eq!(extra(world, "referenceID"), Some(RawValue::Uint64(0)));
+    assert_eq!(
+        extra(world, "mapUsageType"),
+        Some(RawValue::String("standard".to_string()))
+    );
+    match extra(world, "children") {
+        Some(RawValue::ElementArray(children)) => {
+            let types: Vec<_> = children
+                .iter()
+                .map(|child| child.as_ref().unwrap().type_.as_str())
+                .collect();
+            assert_eq!(types, ["CMapMesh", "CMapEntity", "CMapMesh"]);
+        }
+        value => panic!("{:?}", value),
+    }
+    assert_eq!(root.extra.0.len(), file.bodies[0].attributes.len() - 1);
+    assert_eq!(
+        extra(&root.extra, "gridspacing"),
+        Some(RawValue::Float(64.0))
+    );
+
+    // The captured attributes are written back, so that only the
+    // unreachable elements are lost
+    let written = to_file(&root, "vmap", file.header.format_version).unwrap();
+    let diff = diff(&file, &written);
+    assert!(diff.added.is_empty(), "{:?}", diff.added);
+    assert!(diff.changed.is_empty(), "{}", diff);
+    assert!(
+        diff.removed.iter().all(|element| element.path.is_none()),
+        "{:?}",
+        diff.removed
+    );
+}
+
+#[derive(Debug, Deserialize)]
+struct MistypedRoot {
+    #[serde(rename = "world")]
+    _world: MistypedWorld,
+}
+
+#[derive(Debug, Deserialize)]
+struct MistypedWorld {
+    #[serde(rename = "children")]
+    _children: Vec<MistypedNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MistypedNode {
+    #[serde(rename = "nodeID")]
+    _node_id: String,
+}
+
+fn error_path(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let file = from_slice(&data).unwrap();
+
+    let result: Result<MistypedRoot, _> = from_file(&file);
+    let err = result.unwrap_err();
+    assert_eq!(
+        err.path,
+        [
+            PathSegment::Attribute("world".to_string()),
+            PathSegment::Attribute("children".to_string()),
+            PathSegment::Index(0),
+            PathSegment::Element {
+                type_: "CMapMesh".to_string(),
+                name: String::new(),
+                guid: Guid(file.headers[8].guid),
+            },
+            PathSegment::Attribute("nodeID".to_string()),
+        ]
+    );
+    assert_eq!(
+        (err.expected, err.found),
+        (Some(AttributeType::String), Some(AttributeType::Int))
+    );
+    assert_eq!(err.message, "invalid type: integer `2`, expected a string");
+    assert_eq!(
+        err.to_string(),
+        "world.children[0]<CMapMesh \"\">.nodeID: expected String, found Int: \
+         invalid type: integer `2`, expected a string"
+    );
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeOnly {
+    #[serde(rename = "nodeID")]
+    node_id: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct SharedRoot {
+    #[serde(with = "serde::shared")]
+    world: Rc<NodeOnly>,
+    #[serde(rename = "worldAlias", with = "serde::shared")]
+    alias: Rc<NodeOnly>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SharedArcRoot {
+    world: Shared<Arc<NodeOnly>>,
+    #[serde(rename = "worldAlias")]
+    alias: Shared<Arc<NodeOnly>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CyclicRoot {
+    #[serde(rename = "world")]
+    _world: CyclicWorld,
+}
+
+#[derive(Debug, Deserialize)]
+struct CyclicWorld {
+    #[serde(rename = "parent")]
+    _parent: Box<CyclicRoot>,
+}
+
+fn shared_elements(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+
+    // The world is also referenced by the root as worldAlias
+    file.strings.push("worldAlias");
+    file.bodies[0].attributes.push(Attribute {
+        name: StringRef(file.strings.len() as i32 - 1),
+        value: AttributeValue::Element(WORLD as i32),
+    });
+
+    let root: SharedRoot = from_file(&file).unwrap();
+    assert_eq!(root.world.node_id, 1);
+    assert!(Rc::ptr_eq(&root.world, &root.alias));
+
+    let sent: SharedArcRoot = from_file(&file).unwrap();
+    assert_eq!(sent.world.node_id, 1);
+    assert!(Arc::ptr_eq(&sent.world, &sent.alias));
+
+    // Each from_file call deserializes its own values
+    let other: SharedRoot = from_file(&file).unwrap();
+    assert!(!Rc::ptr_eq(&root.world, &other.world));
+
+    // The world references the root back
+    file.strings.push("parent");
+    file.bodies[WORLD].attributes.push(Attribute {
+        name: StringRef(file.strings.len() as i32 - 1),
+        value: AttributeValue::Element(0),
+    });
+
+    let result: Result<CyclicRoot, _> = from_file(&file);
+    let err = result.unwrap_err();
+    assert_eq!(
+        err.path,
+        [
+            PathSegment::Attribute("world".to_string()),
+            PathSegment::Attribute("parent".to_string()),
+        ]
+    );
+    assert_eq!(
+        err.message,
+        format!(
+            "cyclic reference to element CMapRootElement \"\" {}",
+            Guid(file.headers[0].guid)
+        )
+    );
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HeaderRoot {
+    world: HeaderElement,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HeaderElement {
+    #[serde(rename = "$id")]
+    id: Guid,
+    #[serde(rename = "$type")]
+    type_: String,
+    #[serde(rename = "$name")]
+    name: String,
+    #[serde(default)]
+    children: Vec<HeaderElement>,
+}
+
+fn header_fields(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+    file.strings.push("spawn");
+    file.headers[ENTITY].name = StringRef(file.strings.len() as i32 - 1);
+
+    let root: HeaderRoot = from_file(&file).unwrap();
+    let world = &root.world;
+    assert_eq!(world.id, Guid(file.headers[WORLD].guid));
+    assert_eq!(
+        (world.type_.as_str(), world.name.as_str()),
+        ("CMapWorld", "")
+    );
+
+    let children: Vec<_> = world
+        .children
+        .iter()
+        .map(|child| (child.id.0, child.type_.as_str(), child.name.as_str()))
+        .collect();
+    assert_eq!(
+        children,
+        [
+            (file.headers[8].guid, "CMapMesh", ""),
+            (file.headers[ENTITY].guid, "CMapEntity", "spawn"),
+            (file.headers[29].guid, "CMapMesh", ""),
+        ]
+    );
+
+    // The header of the elements is written back from the fields
+    let written = to_file(&root, "vmap", file.header.format_version).unwrap();
+    let headers: Vec<_> = written
+        .headers
+        .iter()
+        .map(|header| {
+            (
+                header.guid,
+                written.string(header.type_).unwrap(),
+                written.string(header.name).unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(headers[1], (world.id.0, "CMapWorld", ""));
+    assert_eq!(headers[2..], children[..]);
+}
+
+#[derive(Debug, Deserialize)]
+struct MathRoot {
+    world: MathWorld,
+}
+
+#[derive(Debug, Deserialize)]
+struct MathWorld {
+    origin: [f32; 3],
+    angles: (f32, f32, f32),
+    scales: Vector3,
+    tint: [u8; 4],
+    rotation: Quat,
+    rotation_map: QuatMap,
+    transform: [f32; 16],
+    points: Vec<(f32, f32)>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Quat(f32, f32, f32, f32);
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct QuatMap {
+    w: f32,
+    x: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShortRoot {
+    #[serde(rename = "world")]
+    _world: ShortOrigin,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShortOrigin {
+    #[serde(rename = "origin")]
+    _origin: [f32; 2],
+}
+
+fn math_sequences(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+
+    let quaternion = AttributeValue::Quaternion(Quaternion {
+        x: 1.0,
+        y: 2.0,
+        z: 3.0,
+        w: 4.0,
+    });
+    let mut transform = [0.0; 16];
+    transform[15] = 1.0;
+    let values = vec![
+        (
+            "tint",
+            AttributeValue::Color(Color {
+                r: 10,
+                g: 20,
+                b: 30,
+                a: 127,
+            }),
+        ),
+        ("rotation", quaternion.clone()),
+        ("rotation_map", quaternion),
+        ("transform", AttributeValue::Vmatrix(Vmatrix(transform))),
+        (
+            "points",
+            AttributeValue::Vector2Array(vec![
+                Vector2 { x: 1.0, y: 2.0 },
+                Vector2 { x: 3.0, y: 4.0 },
+            ]),
+        ),
+    ];
+    for (name, value) in values {
+        file.strings.push(name);
+        file.bodies[WORLD].attributes.push(Attribute {
+            name: StringRef(file.strings.len() as i32 - 1),
+            value,
+        });
+    }
+    match attribute_value_mut(&mut file, WORLD, "origin") {
+        AttributeValue::Vector3(origin) => origin.z = 64.0,
+        value => panic!("{:?}", value),
+    }
+
+    let root: MathRoot = from_file(&file).unwrap();
+    let world = root.world;
+    assert_eq!(world.origin, [0.0, 0.0, 64.0]);
+    assert_eq!(world.angles, (0.0, 0.0, 0.0));
+    assert_eq!(
+        world.scales,
+        Vector3 {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0
+        }
+    );
+    assert_eq!(world.tint, [10, 20, 30, 127]);
+    assert_eq!(world.rotation, Quat(1.0, 2.0, 3.0, 4.0));
+    assert_eq!(world.rotation_map, QuatMap { w: 4.0, x: 1.0 });
+    assert_eq!(world.transform, transform);
+    assert_eq!(world.points, [(1.0, 2.0), (3.0, 4.0)]);
+
+    // The number of components is checked
+    let result: Result<ShortRoot, _> = from_file(&file);
+    let err = result.unwrap_err();
+    assert_eq!(err.found, Some(AttributeType::Vector3));
+    assert_eq!(err.to_string().split(':').next(), Some("world.origin"));
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct TypeOnly {
+    #[serde(rename = "$type")]
+    type_: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MistypedPrefix {
+    #[serde(rename = "asset_preview_thumbnail")]
+    _thumbnail: i32,
+}
+
+fn document(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let file = from_slice(&data).unwrap();
+
+    let document: Document<TypeOnly> = from_file(&file).unwrap();
+    assert_eq!(document.format_name, "vmap");
+    assert_eq!(document.format_version, file.header.format_version);
+    assert_eq!(document.root.type_, "CMapRootElement");
+
+    // The prefix attributes keep their types
+    let document: Document<TypeOnly, RawAttributes> = from_file(&file).unwrap();
+    let names: Vec<_> = document
+        .prefix
+        .0
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let expected: Vec<_> = file.prefix.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, expected);
+    // Both values are debug-formatted as the variant name and the value
+    for ((_, actual), (_, expected)) in document.prefix.0.iter().zip(&file.prefix) {
+        assert_eq!(format!("{:?}", actual), format!("{:?}", expected));
+    }
+
+    let streamed: Document<TypeOnly, RawAttributes> = serde::from_reader(&data[..]).unwrap();
+    assert_eq!(streamed.format_version, document.format_version);
+    assert_eq!(streamed.prefix, document.prefix);
+
+    let result: Result<Document<TypeOnly, MistypedPrefix>, _> = from_file(&file);
+    let err = result.unwrap_err();
+    assert_eq!(
+        err.path,
+        [
+            PathSegment::Attribute("prefix".to_string()),
+            PathSegment::Attribute("asset_preview_thumbnail".to_string()),
+        ]
+    );
+    assert_eq!(err.found, Some(AttributeType::Binary));
+}
+
+#[derive(Debug, Deserialize)]
+struct CoercedRoot<T> {
+    world: T,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Coerced {
+    int_bool: bool,
+    zero_bool: bool,
+    string_bool: bool,
+    string_int_bool: bool,
+    bool_int: i32,
+    string_int: i32,
+    junk_int: i32,
+    int_float: f32,
+    string_float: f64,
+    int_string: String,
+    float_string: String,
+    uint64_string: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CoercedOverflow {
+    #[serde(rename = "overflow_int")]
+    _value: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct CoercedVector {
+    #[serde(rename = "origin")]
+    _origin: bool,
+}
+
+fn coercions(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+
+    let strings = [
+        ("string_bool", " true"),
+        ("string_int_bool", " 0 "),
+        ("string_int", "  -12px"),
+        ("junk_int", "abc"),
+        ("string_float", "1.5e3x"),
+        ("overflow_int", "3000000000"),
+    ];
+    for (name, value) in strings.iter() {
+        file.strings.push(name);
+        file.strings.push(value);
+        let len = file.strings.len() as i32;
+        file.bodies[WORLD].attributes.push(Attribute {
+            name: StringRef(len - 2),
+            value: AttributeValue::String(StringRef(len - 1)),
+        });
+    }
+
+    let values = vec![
+        ("int_bool", AttributeValue::Int(2)),
+        ("zero_bool", AttributeValue::Int(0)),
+        ("bool_int", AttributeValue::Bool(true)),
+        ("int_float", AttributeValue::Int(3)),
+        ("int_string", AttributeValue::Int(42)),
+        ("float_string", AttributeValue::Float(0.5)),
+        ("uint64_string", AttributeValue::Uint64(1 << 60)),
+    ];
+    for (name, value) in values {
+        file.strings.push(name);
+        file.bodies[WORLD].attributes.push(Attribute {
+            name: StringRef(file.strings.len() as i32 - 1),
+            value,
+        });
+    }
+
+    let options = Options {
+        coerce: true,
+        ..Options::default()
+    };
+    let root: CoercedRoot<Coerced> = from_file_with(&file, options).unwrap();
+    assert_eq!(
+        root.world,
+        Coerced {
+            int_bool: true,
+            zero_bool: false,
+            string_bool: true,
+            string_int_bool: false,
+            bool_int: 1,
+            string_int: -12,
+            junk_int: 0,
+            int_float: 3.0,
+            string_float: 1500.0,
+            int_string: "42".to_string(),
+            float_string: "0.5".to_string(),
+            uint64_string: (1u64 << 60).to_string(),
+        }
+    );
+
+    // Values are only converted when asked to
+    let result: Result<CoercedRoot<Coerced>, _> = from_file(&file);
+    let err = result.unwrap_err();
+    assert_eq!(err.to_string().split(':').next(), Some("world.string_bool"));
+    assert_eq!(
+        (err.expected, err.found),
+        (Some(AttributeType::Bool), Some(AttributeType::String))
+    );
+
+    // The converted value must fit the target type
+    let result: Result<CoercedRoot<CoercedOverflow>, _> = from_file_with(&file, options);
+    let err = result.unwrap_err();
+    assert_eq!(
+        err.to_string().split(':').next(),
+        Some("world.overflow_int")
+    );
+    assert_eq!(
+        (err.expected, err.found),
+        (Some(AttributeType::Int), Some(AttributeType::String))
+    );
+
+    // Non-scalar values are not converted
+    let result: Result<CoercedRoot<CoercedVector>, _> = from_file_with(&file, options);
+    let err = result.unwrap_err();
+    assert_eq!(
+        (err.expected, err.found),
+        (Some(AttributeType::Bool), Some(AttributeType::Vector3))
+    );
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedWorld {
+    #[serde(rename = "world")]
+    _world: NameOnly,
+}
+
+#[derive(Debug, Deserialize)]
+struct NameOnly {
+    #[serde(rename = "$name")]
+    _name: String,
+}
+
+fn string_out_of_range(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+    let strings = file.strings.len();
+    let out_of_range = StringRef(strings as i32);
+
+    // Name of an element
+    let world = file
+        .headers
+        .iter()
+        .position(|header| file.string(header.type_) == Some("CMapWorld"))
+        .unwrap();
+    let name = file.headers[world].name;
+    file.headers[world].name = out_of_range;
+    let err = from_file::<_, _, NamedWorld>(&file).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "world.$name: reference to string {} of a file with {} strings",
+            out_of_range.0, strings
+        )
+    );
+    file.headers[world].name = name;
+
+    // Name of an attribute
+    file.bodies[0].attributes[0].name = out_of_range;
+    let err = from_file::<_, _, HashMap<String, IgnoredAny>>(&file).unwrap_err();
+    assert!(err.to_string().contains("reference to string"), "{}", err);
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct VariantRoot<T> {
+    world: T,
+}
+
+/// Variants named after the type of the element
+#[derive(Debug, PartialEq, Deserialize)]
+enum UnitVariant {
+    CMapEntity,
+    CMapWorld,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+enum StructVariant {
+    CMapEntity {},
+    CMapWorld {
+        origin: Vector3,
+        angles: MathVariant,
+    },
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+enum TupleVariant {
+    CMapWorld(Vector3, Vector3),
+}
+
+/// Variants named after the type of the attribute
+#[derive(Debug, PartialEq, Deserialize)]
+enum MathVariant {
+    Vector3(f32, f32, f32),
+    Qangle(f32, f32, f32),
+}
+
+fn enum_variants(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let file = from_slice(&data).unwrap();
+    let world = read_vmap(&file).unwrap().world;
+
+    let root: VariantRoot<UnitVariant> = from_file(&file).unwrap();
+    assert_eq!(root.world, UnitVariant::CMapWorld);
+
+    let root: VariantRoot<StructVariant> = from_file(&file).unwrap();
+    let (x, y, z) = (world.angles.pitch, world.angles.yaw, world.angles.roll);
+    assert_eq!(
+        root.world,
+        StructVariant::CMapWorld {
+            origin: world.origin,
+            angles: MathVariant::Qangle(x, y, z),
+        }
+    );
+
+    // The attributes of an element are not a tuple
+    let err = from_file::<_, _, VariantRoot<TupleVariant>>(&file).unwrap_err();
+    assert!(err.to_string().starts_with("world: "), "{}", err);
+}
+
+fn extract(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+    assert!(file.extract(file.headers.len()).is_none());
+
+    let root = file.extract(0).unwrap();
+    assert_eq!(root.string(root.headers[0].type_), Some("CMapRootElement"));
+    assert_eq!(root.headers[0].guid, file.headers[0].guid);
+
+    // The root already has an attribute named after the synthetic one
+    file.strings.push("element");
+    file.bodies[0].attributes.push(Attribute {
+        name: StringRef(file.strings.len() as i32 - 1),
+        value: AttributeValue::String(StringRef(0)),
+    });
+
+    let world = file
+        .headers
+        .iter()
+        .position(|header| file.string(header.type_) == Some("CMapWorld"))
+        .unwrap();
+    let extracted = file.extract(world).unwrap();
+    assert_eq!(
+        extracted.string(extracted.headers[0].type_),
+        Some("CMapRootElement")
+    );
+    assert_ne!(extracted.headers[0].guid, file.headers[0].guid);
+    assert_eq!(extracted.headers[1].guid, file.headers[world].guid);
+
+    let attribute = |name| {
+        extracted.bodies[0]
+            .attributes
+            .iter()
+            .find(|attr| extracted.string(attr.name) == Some(name))
+            .map(|attr| &attr.value)
+    };
+    assert!(matches!(
+        attribute("element"),
+        Some(AttributeValue::String(_))
+    ));
+    assert!(matches!(
+        attribute("element1"),
+        Some(AttributeValue::Element(1))
+    ));
+}
+
+fn content_hash(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+    let options = HashOptions::default();
+    let ignore_guids = HashOptions { ignore_guids: true };
+    assert!(file.content_hash(file.headers.len(), options).is_none());
+
+    // The hash does not depend on the version, the index of the element or
+    // the string table
+    let root = file.content_hash(0, options).unwrap();
+    assert_eq!(root, 0x690a_124b_684b_6807);
+    assert_eq!(file.content_hashes(options)[0], root);
+
+    let world = file
+        .headers
+        .iter()
+        .position(|header| file.string(header.type_) == Some("CMapWorld"))
+        .unwrap();
+    let extracted = file.extract(world).unwrap();
+    assert_eq!(
+        extracted.content_hash(1, options),
+        file.content_hash(world, options)
+    );
+
+    // GUIDs are only hashed if they are not ignored
+    let without_guids = file.content_hash(0, ignore_guids);
+    for header in &mut file.headers {
+        header.guid = [0xff; 16];
+    }
+    assert_eq!(file.content_hash(0, ignore_guids), without_guids);
+    assert_ne!(file.content_hash(0, options), Some(root));
+
+    // Cycles are hashed consistently
+    let name = file.bodies[0].attributes[0].name;
+    file.bodies[world].attributes.push(Attribute {
+        name,
+        value: AttributeValue::Element(0),
+    });
+    let cyclic = file.content_hash(0, options).unwrap();
+    assert_eq!(file.content_hashes(options)[0], cyclic);
+    assert_ne!(Some(cyclic), file.content_hash(0, ignore_guids));
+
+    // Deep graphs do not overflow the stack
+    let type_ = file.headers[0].type_;
+    let first = file.headers.len();
+    for index in first..first + 100_000 {
+        file.headers.push(Header {
+            type_,
+            name,
+            guid: [0; 16],
+        });
+        file.bodies.push(Body {
+            attributes: vec![Attribute {
+                name,
+                value: AttributeValue::Element(index as i32 + 1),
+            }],
+        });
+    }
+    file.bodies[0].attributes.push(Attribute {
+        name,
+        value: AttributeValue::Element(first as i32),
+    });
+    assert!(file.content_hash(0, options).is_some());
+}
+
+fn json_round_trip(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+
+    // A null String reference is kept as such
+    let (element, attr) = file
+        .bodies
+        .iter()
+        .enumerate()
+        .find_map(|(element, body)| {
+            let attr = body
+                .attributes
+                .iter()
+                .position(|attr| matches!(attr.value, AttributeValue::String(_)))?;
+            Some((element, attr))
+        })
+        .unwrap();
+    file.bodies[element].attributes[attr].value = AttributeValue::String(StringRef(-1));
+
+    let json = to_json(&file);
+    assert_eq!(
+        json["elements"][element]["attributes"][attr]["value"],
+        Value::Null
+    );
+
+    let copy = from_json(&json).unwrap();
+    assert!(matches!(
+        copy.bodies[element].attributes[attr].value,
+        AttributeValue::String(StringRef(-1))
+    ));
+    assert!(to_json(&copy) == json, "the JSON representation is lossy");
+
+    // The string table of the fixture has unused strings, and from_json
+    // interns the strings in the order they are used, so the bytes are only
+    // compared from the first round trip on
+    let again = from_json(&to_json(&copy)).unwrap();
+    assert!(to_vec(&again).unwrap() == to_vec(&copy).unwrap());
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Origin {
+    origin: Vector3,
+}
+
+/// Custom elements among the children of the world
+fn custom_children<B, S>(root: &CMapRootElement<B, S>) -> Vec<&Custom> {
+    root.world
+        .children
+        .iter()
+        .filter_map(|child| match &**child {
+            ElementType::Custom(custom) => Some(custom),
+            _ => None,
+        })
+        .collect()
+}
+
+fn registry_decoder(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let file = from_slice(&data).unwrap();
+
+    let mut registry = Registry::new();
+    registry.register::<Origin>("CMapEntity");
+    let root: CMapRootElement<&[u8], &str> = registry.scope(|| from_file(&file)).unwrap();
+
+    let custom = custom_children(&root);
+    assert_eq!(custom.len(), 1);
+    assert_eq!(custom[0].type_(), "CMapEntity");
+    assert!(custom[0].is::<Origin>(), "{:?}", custom[0]);
+
+    // The registry is only used by the calls it is in scope for
+    let root: CMapRootElement<&[u8], &str> = from_file(&file).unwrap();
+    assert!(custom_children(&root).is_empty());
+}
+
+fn registry_fallback(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+
+    // Renames the type of the entity to one the map format has no variant for
+    let entity = file
+        .headers
+        .iter()
+        .position(|header| file.string(header.type_) == Some("CMapEntity"))
+        .unwrap();
+    file.strings.push("CMapUnknown");
+    file.headers[entity].type_ = StringRef(file.strings.len() as i32 - 1);
+
+    assert!(from_file::<_, _, CMapRootElement<&[u8], &str>>(&file).is_err());
+
+    let mut registry = Registry::new();
+    registry.fallback(|element: RawElement| Ok(element));
+    let root: CMapRootElement<&[u8], &str> = registry.scope(|| from_file(&file)).unwrap();
+
+    let custom = custom_children(&root);
+    assert_eq!(custom.len(), 1);
+    assert_eq!(custom[0].type_(), "CMapUnknown");
+    let element = custom[0].downcast_ref::<RawElement>().unwrap();
+    assert!(
+        element
+            .attributes
+            .0
+            .iter()
+            .any(|(name, _)| name == "origin"),
+        "{:?}",
+        element
+    );
+}
+
+fn registry_nested_scope(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let file = from_slice(&data).unwrap();
+    let read = || from_file::<_, _, CMapRootElement<&[u8], &str>>(&file).unwrap();
+
+    let mut outer = Registry::new();
+    outer.register::<Origin>("CMapEntity");
+    let inner = Registry::new();
+
+    outer.scope(|| {
+        assert_eq!(custom_children(&read()).len(), 1);
+        inner.scope(|| assert!(custom_children(&read()).is_empty()));
+        assert_eq!(custom_children(&read()).len(), 1);
+
+        // The outer registry is restored when the inner scope panics
+        let result = panic::catch_unwind(AssertUnwindSafe(|| inner.scope(|| panic!())));
+        assert!(result.is_err());
+        assert_eq!(custom_children(&read()).len(), 1);
+    });
+
+    assert!(custom_children(&read()).is_empty());
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct BorrowedRoot<'a> {
+    #[serde(rename = "itemFile")]
+    item_file: &'a str,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct BorrowedPrefix<'a> {
+    asset_preview_thumbnail: &'a [u8],
+    asset_preview_thumbnail_format: &'a str,
+}
+
+fn borrow_from_owned_file(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let file = from_slice(&data).unwrap();
+    let expected: Document<BorrowedRoot, BorrowedPrefix> = from_file(&file).unwrap();
+
+    // Strings and buffers are borrowed from an owned file just as well
+    let file = from_reader(&data[..]).unwrap();
+    let actual: Document<BorrowedRoot, BorrowedPrefix> = from_file(&file).unwrap();
+
+    assert_eq!(actual.root, expected.root);
+    assert_eq!(actual.prefix, expected.prefix);
+    assert!(!actual.prefix.asset_preview_thumbnail.is_empty());
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct AliasedRoot {
+    world: TypeOnly,
+    #[serde(rename = "$extra")]
+    extra: RawAttributes,
+}
+
+fn stream_element_reached_twice(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_reader(&data[..]).unwrap();
+
+    // The world is also referenced by an attribute captured by $extra
+    let world = file
+        .headers
+        .iter()
+        .position(|header| file.string(header.type_) == Some("CMapWorld"))
+        .unwrap();
+    file.strings.push("worldAlias".to_string());
+    file.bodies[0].attributes.push(Attribute {
+        name: StringRef(file.strings.len() as i32 - 1),
+        value: AttributeValue::Element(world as i32),
+    });
+    let data = to_vec(&file).unwrap();
+
+    let expected: AliasedRoot = from_file(&from_slice(&data).unwrap()).unwrap();
+    let actual: AliasedRoot = match serde::from_reader(&data[..]) {
+        Ok(root) => root,
+        Err(err) => panic!("{}", err),
+    };
+    assert_eq!(actual, expected);
+
+    assert_eq!(actual.world.type_, "CMapWorld");
+    let alias = actual.extra.0.iter().find(|(name, _)| name == "worldAlias");
+    match alias {
+        Some((_, RawValue::Element(Some(element)))) => assert_eq!(element.type_, "CMapWorld"),
+        alias => panic!("{:?}", alias),
+    }
+}
+
+fn query_syntax(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+    *attribute_value_mut(&mut file, 0, "gridspacing") = AttributeValue::Float(0.1);
+
+    let queries: &[(&str, &[usize])] = &[
+        (".", &[0]),
+        ("world", &[5]),
+        ("/world", &[5]),
+        ("*[@type=CMapWorld]", &[5]),
+        ("world[ @type = \"CMapWorld\" ]", &[5]),
+        ("world[@type!='CMapWorld']", &[]),
+        ("world[@name='']/.", &[5]),
+        (
+            "//CMapEntity[entity_properties/classname='info_player_start']",
+            &[26],
+        ),
+        ("//*[entity_properties][@type!=CMapWorld]", &[26]),
+        (".[gridspacing=0.1]", &[0]),
+        (".[gridspacing=0.1000001]", &[]),
+    ];
+    for (query, expected) in queries {
+        let query: Query = query.parse().unwrap();
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&index| Match::Element(index))
+            .collect();
+        assert_eq!(query.eval(&file), expected, "{:?}", query);
+    }
+
+    let errors = [
+        ("", "Expected a name at the end of query \"\""),
+        ("world/", "Expected a name at the end of query \"world/\""),
+        ("world[", "Expected a name at the end of query \"world[\""),
+        (
+            "world[x=1",
+            "Expected ']' at the end of query \"world[x=1\"",
+        ),
+        ("world]", "Unexpected \"]\" in query \"world]\""),
+        (
+            "world[@kind]",
+            "Unknown header field @kind in query \"world[@kind]\"",
+        ),
+        (
+            "world[@type='CMapWorld]",
+            "Unterminated string in query \"world[@type='CMapWorld]\"",
+        ),
+    ];
+    for (query, error) in errors.iter() {
+        assert_eq!(Query::parse(query).unwrap_err().to_string(), *error);
+    }
+}
+
+fn query_descendants(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+    let world = Match::Element(5);
+
+    // The context node is not its own descendant
+    assert_eq!(file.query("//CMapRootElement").unwrap(), []);
+    assert_eq!(file.query("//CMapWorld").unwrap(), [world]);
+    assert_eq!(file.query("world//CMapWorld").unwrap(), []);
+    assert!(!file.query("world//*").unwrap().contains(&world));
+
+    // The world is reached from the root through a second attribute, and the
+    // root from the world
+    file.strings.push("worldAlias");
+    file.bodies[0].attributes.push(Attribute {
+        name: StringRef(file.strings.len() as i32 - 1),
+        value: AttributeValue::Element(5),
+    });
+    file.strings.push("parent");
+    file.bodies[5].attributes.push(Attribute {
+        name: StringRef(file.strings.len() as i32 - 1),
+        value: AttributeValue::Element(0),
+    });
+
+    assert_eq!(file.query("//world").unwrap(), [world]);
+    assert_eq!(file.query("//worldAlias").unwrap(), [world]);
+    assert_eq!(file.query("worldAlias//world").unwrap(), [world]);
+    assert_eq!(file.query("//parent").unwrap(), [Match::Element(0)]);
+    assert_eq!(file.query("//CMapWorld").unwrap(), [world]);
+    assert_eq!(
+        file.query("world//CMapWorld").unwrap(),
+        file.query("world").unwrap()
+    );
+}
+
+fn merge_clean(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let base = from_slice(&data).unwrap();
+
+    // Changes to different attributes of the same element and to different
+    // elements are all applied
+    let moved = Vector3 {
+        x: 1.0,
+        y: 2.0,
+        z: 3.0,
+    };
+    let mut ours = from_slice(&data).unwrap();
+    *attribute_value_mut(&mut ours, WORLD, "origin") = AttributeValue::Vector3(moved);
+
+    let mut theirs = from_slice(&data).unwrap();
+    *attribute_value_mut(&mut theirs, WORLD, "nodeID") = AttributeValue::Int(7);
+    theirs.strings.push("spawn");
+    theirs.headers[ENTITY].name = StringRef(theirs.strings.len() as i32 - 1);
+
+    let merged = merge(&base, &ours, &theirs);
+    assert!(merged.is_clean(), "{:?}", merged.conflicts);
+
+    let file = &merged.file;
+    assert_eq!(file.headers.len(), base.headers.len());
+    match attribute_value(file, WORLD, "origin") {
+        AttributeValue::Vector3(origin) => assert_eq!(*origin, moved),
+        value => panic!("{:?}", value),
+    }
+    match attribute_value(file, WORLD, "nodeID") {
+        AttributeValue::Int(id) => assert_eq!(*id, 7),
+        value => panic!("{:?}", value),
+    }
+    assert_eq!(file.string(file.headers[ENTITY].name), Some("spawn"));
+}
+
+fn merge_conflicts(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let base = from_slice(&data).unwrap();
+
+    let move_world = |x| {
+        let mut file = from_slice(&data).unwrap();
+        let origin = Vector3 { x, y: 0.0, z: 0.0 };
+        *attribute_value_mut(&mut file, WORLD, "origin") = AttributeValue::Vector3(origin);
+        file
+    };
+
+    let mut ours = move_world(1.0);
+    ours.header.format_version += 1;
+    let mut theirs = move_world(2.0);
+    theirs.header.format_version += 2;
+
+    let merged = merge(&base, &ours, &theirs);
+    assert_eq!(merged.conflicts.len(), 2, "{:?}", merged.conflicts);
+
+    // Conflicts are resolved to ours
+    let file = &merged.file;
+    assert_eq!(file.header.format_version, ours.header.format_version);
+    match attribute_value(file, WORLD, "origin") {
+        AttributeValue::Vector3(origin) => assert_eq!(origin.x, 1.0),
+        value => panic!("{:?}", value),
+    }
+
+    let format = &merged.conflicts[0];
+    assert!(format.element.is_none());
+    match &format.kind {
+        ConflictKind::Format { base, ours, theirs } => {
+            assert_eq!(ours.1, base.1 + 1);
+            assert_eq!(theirs.1, base.1 + 2);
+        }
+        kind => panic!("{:?}", kind),
+    }
+    assert!(format.to_string().starts_with("<header>: format changed"));
+
+    let origin = &merged.conflicts[1];
+    let element = origin.element.as_ref().unwrap();
+    assert_eq!(
+        (element.index, element.type_.as_str()),
+        (WORLD, "CMapWorld")
+    );
+    match &origin.kind {
+        ConflictKind::Attribute {
+            name,
+            base: Some(AttributeValue::Vector3(base)),
+            ours: Some(AttributeValue::Vector3(ours)),
+            theirs: Some(AttributeValue::Vector3(theirs)),
+        } => {
+            assert_eq!(name, "origin");
+            assert_eq!((base.x, ours.x, theirs.x), (0.0, 1.0, 2.0));
+        }
+        kind => panic!("{:?}", kind),
+    }
+    assert!(origin
+        .to_string()
+        .contains(": attribute \"origin\" changed on both sides"));
+}
+
+fn merge_deleted(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let base = from_slice(&data).unwrap();
+    let guid = base.headers[ENTITY].guid;
+
+    // The entity is deleted from theirs, along with the elements it references
+    let mut theirs = from_slice(&data).unwrap();
+    match attribute_value_mut(&mut theirs, WORLD, "children") {
+        AttributeValue::ElementArray(children) => children.retain(|&child| child != ENTITY as i32),
+        value => panic!("{:?}", value),
+    }
+    theirs.compact().unwrap();
+    assert!(theirs.headers.iter().all(|header| header.guid != guid));
+
+    let ours = from_slice(&data).unwrap();
+    let merged = merge(&base, &ours, &theirs);
+    assert!(merged.is_clean(), "{:?}", merged.conflicts);
+    assert_eq!(merged.file.headers.len(), theirs.headers.len());
+    assert!(merged.file.headers.iter().all(|header| header.guid != guid));
+
+    // Deleted in theirs but modified in ours, the element is kept but not
+    // referenced anymore
+    let mut ours = from_slice(&data).unwrap();
+    *attribute_value_mut(&mut ours, ENTITY, "nodeID") = AttributeValue::Int(42);
+    let mut merged = merge(&base, &ours, &theirs);
+    assert_eq!(merged.conflicts.len(), 1, "{:?}", merged.conflicts);
+
+    let conflict = &merged.conflicts[0];
+    assert!(matches!(
+        conflict.kind,
+        ConflictKind::Deleted { side: Side::Theirs }
+    ));
+    let element = conflict.element.as_ref().unwrap();
+    assert_eq!((element.guid, element.path.as_deref()), (guid, None));
+    let index = element.index;
+    assert!(matches!(
+        attribute_value(&merged.file, index, "nodeID"),
+        AttributeValue::Int(42)
+    ));
+
+    let compaction = merged.file.compact().unwrap();
+    assert_eq!(compaction.elements, [index]);
+    assert_eq!(merged.file.headers.len(), theirs.headers.len());
+}
+
+fn diff_nan(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let with_nans = || {
+        let mut file = from_slice(&data).unwrap();
+        *attribute_value_mut(&mut file, 0, "gridspacing") = AttributeValue::Float(f32::NAN);
+        match attribute_value_mut(&mut file, WORLD, "origin") {
+            AttributeValue::Vector3(origin) => origin.x = f32::NAN,
+            value => panic!("{:?}", value),
+        }
+        match attribute_value_mut(&mut file, 25, "data") {
+            AttributeValue::Vector3Array(data) => data[0].y = f32::NAN,
+            value => panic!("{:?}", value),
+        }
+        file
+    };
+
+    let old = with_nans();
+    let mut new = with_nans();
+    let unchanged = diff(&old, &new);
+    assert!(unchanged.is_empty(), "{}", unchanged);
+
+    *attribute_value_mut(&mut new, 0, "gridspacing") = AttributeValue::Float(-f32::NAN);
+    let changed = diff(&old, &new);
+    assert!(changed.added.is_empty() && changed.removed.is_empty());
+    assert_eq!(changed.changed.len(), 1, "{}", changed);
+    assert_eq!(changed.changed[0].old.index, 0);
+}
+
+#[derive(Debug, Deserialize)]
+struct LazyRoot<'a> {
+    #[serde(borrow)]
+    world: ElementRef<'a, TypeOnly>,
+}
+
+fn element_ref(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let file = from_slice(&data).unwrap();
+
+    let root: LazyRoot = from_file(&file).unwrap();
+    assert_eq!(root.world.index(), WORLD);
+    assert_eq!(root.world.id(), Guid(file.headers[WORLD].guid));
+    assert_eq!(root.world.get().unwrap().type_, "CMapWorld");
+
+    // The element is read from the file the reference was deserialized from
+    let owned = from_reader(&data[..]).unwrap();
+    let root: LazyRoot = from_file(&owned).unwrap();
+    assert_eq!(root.world.get().unwrap().type_, "CMapWorld");
+
+    // Other deserializers cannot produce a reference
+    let result: Result<ElementRef<TypeOnly>, _> = serde_json::from_str("null");
+    assert!(result.is_err());
+}
+
+fn write_vmap_changes(path: &Path) {
+    let data = fs::read(path).unwrap();
+    let mut file = from_slice(&data).unwrap();
+    let mesh = match attribute_value(&file, WORLD, "children") {
+        AttributeValue::ElementArray(children) => children[0] as usize,
+        value => panic!("{:?}", value),
+    };
+    let properties = match attribute_value(&file, ENTITY, "entity_properties") {
+        AttributeValue::Element(index) => *index as usize,
+        value => panic!("{:?}", value),
+    };
+
+    // An attribute that no field of CMapMesh models
+    file.strings.extend(&["editorComment", "kept"]);
+    file.bodies[mesh].attributes.push(Attribute {
+        name: StringRef(file.strings.len() as i32 - 2),
+        value: AttributeValue::String(StringRef(file.strings.len() as i32 - 1)),
+    });
+
+    let mut root = read_vmap(&file).unwrap();
+    let tint_color = Color {
+        r: 1,
+        g: 2,
+        b: 3,
+        a: 4,
+    };
+    match &mut *root.world.children[0] {
+        ElementType::CMapMesh(mesh) => {
+            mesh.tint_color = tint_color;
+            mesh.renderwithdynamic = None;
+        }
+        element => panic!("{:?}", element),
+    }
+    match &mut *root.world.children[1] {
+        ElementType::CMapEntity(entity) => {
+            assert!(entity.entity_properties.remove("vscripts").is_some());
+        }
+        element => panic!("{:?}", element),
+    }
+
+    let written = write_vmap(&root, &file).unwrap();
+    let diff = diff(&file, &written);
+    assert!(diff.added.is_empty(), "{}", diff);
+    assert!(
+        diff.removed.iter().all(|element| element.path.is_none()),
+        "{}",
+        diff
+    );
+
+    let changed: Vec<_> = diff
+        .changed
+        .iter()
+        .map(|element| {
+            assert_eq!(element.old.guid, element.new.guid);
+            assert!(!element.type_changed() && !element.name_changed());
+            let attributes: Vec<_> = element
+                .attributes
+                .iter()
+                .map(|attr| match attr {
+                    AttributeDiff::Changed { name, new, .. } => {
+                        assert_eq!(*new, AttributeValue::Color(tint_color));
+                        format!("~{}", name)
+                    }
+                    AttributeDiff::Removed { name, .. } => format!("-{}", name),
+                    attr => panic!("{:?}", attr),
+                })
+                .collect();
+            format!("#{} {}", element.old.index, attributes.join(" "))
+        })
+        .collect();
+    assert_eq!(
+        changed,
+        [
+            format!("#{} -renderwithdynamic ~tintColor", mesh),
+            format!("#{} -vscripts", properties),
+        ],
+        "{}",
+        diff
+    );
+
+    let index = written
+        .headers
+        .iter()
+        .position(|header| header.guid == file.headers[mesh].guid)
+        .unwrap();
+    assert_eq!(
+        written.string(written.headers[index].name),
+        file.string(file.headers[mesh].name)
+    );
+    match attribute_value(&written, index, "editorComment") {
+        AttributeValue::String(value) => assert_eq!(written.string(*value), Some("kept")),
+        value => panic!("{:?}", value),
+    }
+}
+
+/// Integration test running `f`, which fails by panicking
+fn test(name: String, f: impl FnOnce() + Send + 'static) -> TestDescAndFn {
+    TestDescAndFn {
+        desc: TestDesc {
+            name: TestName::DynTestName(name),
+            ignore: false,
+            ignore_message: None,
+            source_file: file!(),
+            start_line: 0,
+            start_col: 0,
+            end_line: 0,
+            end_col: 0,
+            should_panic: ShouldPanic::No,
+            compile_fail: false,
+            no_run: false,
+            test_type: TestType::IntegrationTest,
+        },
+        testfn: TestFn::DynTestFn(Box::new(move || {
+            f();
+            Ok(())
+        })),
+    }
+}
+
 /// Recursively traverses a directory, yielding all *.vmap files to the visitor as they are found
 fn visit_directory(dir: &Path, handle_file: &mut impl FnMut(PathBuf)) {
     let iter = match read_dir(dir) {